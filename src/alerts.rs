@@ -0,0 +1,607 @@
+// Alert rules evaluated on the game state
+
+use crate::{
+    game_state::{GameState, Reading},
+    i18n,
+    resource_float::{FloatConfig, FloatDetector},
+};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::BTreeMap, fmt};
+
+/// A value of the game state usable in conditions
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Population,
+    PopulationCap,
+    Food,
+    Wood,
+    Gold,
+    Stone,
+    IdleVillagers,
+    FoodWorkers,
+    WoodWorkers,
+    GoldWorkers,
+    StoneWorkers,
+    /// Sum of all workers and idle villagers
+    Villagers,
+    /// 1 if a villager is queued, 0 otherwise
+    VillagerInProduction,
+    /// 1 for the Dark Age up to 4 for the Imperial Age
+    Age,
+    /// 1 while advancing to the next age, 0 otherwise
+    AgingUp,
+    /// `icon.<name>`: 1 if the icon of the icon registry is found, 0 otherwise
+    Icon(String),
+}
+
+impl Field {
+    const NAMES: [(&'static str, Field); 15] = [
+        ("population", Field::Population),
+        ("population_cap", Field::PopulationCap),
+        ("food", Field::Food),
+        ("wood", Field::Wood),
+        ("gold", Field::Gold),
+        ("stone", Field::Stone),
+        ("idle_villagers", Field::IdleVillagers),
+        ("food_workers", Field::FoodWorkers),
+        ("wood_workers", Field::WoodWorkers),
+        ("gold_workers", Field::GoldWorkers),
+        ("stone_workers", Field::StoneWorkers),
+        ("villagers", Field::Villagers),
+        ("villager_in_production", Field::VillagerInProduction),
+        ("age", Field::Age),
+        ("aging_up", Field::AgingUp),
+    ];
+
+    fn from_name(name: &str) -> Option<Field> {
+        if let Some(icon) = name.strip_prefix("icon.").filter(|icon| !icon.is_empty()) {
+            return Some(Field::Icon(icon.to_string()));
+        }
+        Self::NAMES.iter().find(|(n, _)| *n == name).map(|(_, field)| field.clone())
+    }
+
+    /// `None` if the value is unknown, like icons that were not searched for
+    fn value(&self, state: &GameState, icons: &BTreeMap<String, bool>) -> Option<f64> {
+        let reading = |reading: Reading<u32>| reading.known().map(f64::from);
+        match self {
+            Field::Population => state.population.known().map(|p| p.current as f64),
+            Field::PopulationCap => state.population.known().map(|p| p.cap as f64),
+            Field::Food => reading(state.resources.food),
+            Field::Wood => reading(state.resources.wood),
+            Field::Gold => reading(state.resources.gold),
+            Field::Stone => reading(state.resources.stone),
+            Field::IdleVillagers => reading(state.idle_villagers),
+            Field::FoodWorkers => reading(state.workers.food),
+            Field::WoodWorkers => reading(state.workers.wood),
+            Field::GoldWorkers => reading(state.workers.gold),
+            Field::StoneWorkers => reading(state.workers.stone),
+            Field::Villagers => reading(state.villagers()),
+            Field::VillagerInProduction => Some(if state.villager_in_production { 1.0 } else { 0.0 }),
+            Field::Age => state.age.known().map(|age| age.number() as f64),
+            Field::AgingUp => Some(if state.aging_up { 1.0 } else { 0.0 }),
+            Field::Icon(name) => icons.get(name).map(|&found| if found { 1.0 } else { 0.0 }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Field(Field),
+    Number(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Vec<(f64, Term)>, CompareOp, Vec<(f64, Term)>),
+    /// A sum that is true if not 0
+    Truthy(Vec<(f64, Term)>),
+}
+
+impl Expr {
+    /// `None` if the result depends on an unknown value
+    fn eval(&self, state: &GameState, icons: &BTreeMap<String, bool>) -> Option<bool> {
+        let sum = |terms: &[(f64, Term)]| -> Option<f64> {
+            terms
+                .iter()
+                .map(|(sign, term)| match term {
+                    Term::Field(field) => field.value(state, icons).map(|value| sign * value),
+                    Term::Number(number) => Some(sign * number),
+                })
+                .sum()
+        };
+        match self {
+            Expr::And(a, b) => match (a.eval(state, icons), b.eval(state, icons)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Expr::Or(a, b) => match (a.eval(state, icons), b.eval(state, icons)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            Expr::Not(a) => a.eval(state, icons).map(|value| !value),
+            Expr::Compare(left, op, right) => {
+                let (left, right) = (sum(left)?, sum(right)?);
+                Some(match op {
+                    CompareOp::Less => left < right,
+                    CompareOp::LessEqual => left <= right,
+                    CompareOp::Greater => left > right,
+                    CompareOp::GreaterEqual => left >= right,
+                    CompareOp::Equal => left == right,
+                    CompareOp::NotEqual => left != right,
+                })
+            }
+            Expr::Truthy(terms) => sum(terms).map(|value| value != 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Op(&'static str),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    const OPERATORS: [&str; 14] =
+        ["<=", ">=", "==", "!=", "&&", "||", "<", ">", "+", "-", "(", ")", "!", "="];
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_alphabetic() || c == '_' {
+            // Dots for `icon.<name>`
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            len
+        } else if c.is_ascii_digit() || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = rest[..len].parse().map_err(|_| format!("Invalid number '{}'", &rest[..len]))?;
+            tokens.push(Token::Number(number));
+            len
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            // A single "=" is accepted as "=="
+            tokens.push(Token::Op(if *op == "=" { "==" } else { op }));
+            op.len()
+        } else {
+            return Err(format!("Unexpected character '{}'", c));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser for
+/// `or := and ("or" and)*`, `and := unary ("and" unary)*`, `unary := "not" unary | primary`,
+/// `primary := "(" or ")" | sum (op sum)?`, `sum := term (("+" | "-") term)*`
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn accept(&mut self, op: &str, keyword: &str) -> bool {
+        let matches = match self.peek() {
+            Some(Token::Op(o)) => *o == op,
+            Some(Token::Ident(ident)) => ident == keyword,
+            _ => false,
+        };
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.accept("||", "or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        while self.accept("&&", "and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.accept("!", "not") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.peek() == Some(&Token::Op("(")) {
+            self.position += 1;
+            let expr = self.parse_or()?;
+            if self.next() != Some(Token::Op(")")) {
+                return Err("Missing ')'".to_string());
+            }
+            return Ok(expr);
+        }
+
+        let left = self.parse_sum()?;
+        let op = match self.peek() {
+            Some(Token::Op("<")) => CompareOp::Less,
+            Some(Token::Op("<=")) => CompareOp::LessEqual,
+            Some(Token::Op(">")) => CompareOp::Greater,
+            Some(Token::Op(">=")) => CompareOp::GreaterEqual,
+            Some(Token::Op("==")) => CompareOp::Equal,
+            Some(Token::Op("!=")) => CompareOp::NotEqual,
+            _ => return Ok(Expr::Truthy(left)),
+        };
+        self.position += 1;
+        Ok(Expr::Compare(left, op, self.parse_sum()?))
+    }
+
+    fn parse_sum(&mut self) -> Result<Vec<(f64, Term)>, String> {
+        let mut terms = vec![(1.0, self.parse_term()?)];
+        loop {
+            let sign = match self.peek() {
+                Some(Token::Op("+")) => 1.0,
+                Some(Token::Op("-")) => -1.0,
+                _ => return Ok(terms),
+            };
+            self.position += 1;
+            terms.push((sign, self.parse_term()?));
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Term, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Term::Number(number)),
+            Some(Token::Ident(name)) => Field::from_name(&name).map(Term::Field).ok_or_else(|| {
+                let mut names: Vec<_> = Field::NAMES.iter().map(|(name, _)| *name).collect();
+                names.push("icon.<name>");
+                format!("Unknown field '{}', expected one of {}", name, names.join(", "))
+            }),
+            Some(token) => Err(format!("Expected a field or number, found {:?}", token)),
+            None => Err("Unexpected end of condition".to_string()),
+        }
+    }
+}
+
+/// A condition on the game state, e.g. `population + 2 >= population_cap and not
+/// villager_in_production`. Comparisons with unknown values are never true. `icon.<name>` is 1
+/// while the icon `<name>` of the icon registry is found, e.g. `icon.idle_scout`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let expr = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {:?} in condition '{}'", token, source));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    pub fn is_met(&self, state: &GameState) -> bool {
+        self.eval(state) == Some(true)
+    }
+
+    /// `None` if the result depends on an unknown value. Icons are unknown.
+    pub fn eval(&self, state: &GameState) -> Option<bool> {
+        self.eval_with_icons(state, &BTreeMap::new())
+    }
+
+    /// Like [`Self::eval`] with the icons of the icon registry, by name whether they are found
+    pub fn eval_with_icons(
+        &self,
+        state: &GameState,
+        icons: &BTreeMap<String, bool>,
+    ) -> Option<bool> {
+        self.expr.eval(state, icons)
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Self::parse(&source)
+    }
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> Self {
+        condition.source
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// When an alert is shown and hidden, all durations in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertTiming {
+    /// How long the condition must hold before the alert is shown
+    pub min_duration_ms: u64,
+    /// How long the condition must be false before the alert is hidden
+    pub deactivation_delay_ms: u64,
+    /// How long the alert is shown at least
+    pub min_display_ms: u64,
+    /// How long the alert stays off after the player reacted to it
+    pub cooldown_ms: u64,
+}
+
+impl Default for AlertTiming {
+    fn default() -> Self {
+        Self {
+            min_duration_ms: 0,
+            deactivation_delay_ms: 1500,
+            min_display_ms: 3000,
+            cooldown_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub condition: Condition,
+    #[serde(flatten)]
+    pub timing: AlertTiming,
+    /// Higher priorities are shown first
+    #[serde(default)]
+    pub priority: i32,
+    /// Text to show, either a key of the translation catalogs (e.g. `alert.house`) or literal text
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Icon name of the overlay icon theme to show
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertConfig {
+    pub rules: Vec<AlertRule>,
+    /// Warning about resources that are not spent
+    pub resource_float: FloatConfig,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        let rule = |name: &str, condition: &str, priority, message: &str| AlertRule {
+            name: name.to_string(),
+            condition: Condition::parse(condition).unwrap(),
+            timing: AlertTiming::default(),
+            priority,
+            message: Some(message.to_string()),
+            icon: None,
+        };
+        // Every rule depends on the population, so no alert fires while it is unreadable, e.g.
+        // outside of a match
+        Self {
+            rules: vec![
+                rule("house", "population + 2 >= population_cap", 30, "alert.house"),
+                rule("idle", "population > 0 and idle_villagers > 0", 20, "alert.idle"),
+                rule(
+                    "villager",
+                    "population > 0 and not villager_in_production",
+                    10,
+                    "alert.villager",
+                ),
+            ],
+            resource_float: FloatConfig::default(),
+        }
+    }
+}
+
+/// An alert whose rule currently fires
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    pub name: String,
+    pub priority: i32,
+    /// Catalog key or literal text, see [`AlertRule::message`]
+    pub message: Option<String>,
+    /// Catalog keys or literal texts listed after the message, e.g. the floating resources
+    #[serde(default)]
+    pub message_args: Vec<String>,
+    pub icon: Option<String>,
+    /// Timestamp at which the alert was raised
+    pub since_ms: u64,
+}
+
+impl Alert {
+    /// The message in the current language, e.g. "Spend your resources! Food, Wood"
+    pub fn text(&self) -> Option<String> {
+        let message = i18n::tr_or_literal(self.message.as_deref()?);
+        if self.message_args.is_empty() {
+            return Some(message);
+        }
+        let args: Vec<String> = self
+            .message_args
+            .iter()
+            .map(|arg| i18n::tr_or_literal(arg))
+            .collect();
+        Some(format!("{} {}", message, args.join(", ")))
+    }
+}
+
+/// Activation state of one alert
+#[derive(Debug, Clone, Default)]
+struct AlertState {
+    /// Since when the condition holds
+    condition_since_ms: Option<u64>,
+    /// Since when the condition is false while the alert is active
+    cleared_since_ms: Option<u64>,
+    /// Timestamp at which the alert was raised, if it is active
+    active_since_ms: Option<u64>,
+    cooldown_until_ms: u64,
+}
+
+impl AlertState {
+    /// Add the result of the condition at `timestamp_ms`, `None` if it is unknown. Unknown
+    /// results, e.g. from a failed OCR read, leave the alert as it is. Returns since when the alert
+    /// is active.
+    fn update(
+        &mut self,
+        condition: Option<bool>,
+        timing: &AlertTiming,
+        timestamp_ms: u64,
+    ) -> Option<u64> {
+        match condition {
+            Some(true) => {
+                self.cleared_since_ms = None;
+                let since = *self.condition_since_ms.get_or_insert(timestamp_ms);
+                if self.active_since_ms.is_none()
+                    && timestamp_ms.saturating_sub(since) >= timing.min_duration_ms
+                    && timestamp_ms >= self.cooldown_until_ms
+                {
+                    self.active_since_ms = Some(timestamp_ms);
+                }
+            }
+            Some(false) => {
+                self.condition_since_ms = None;
+                if let Some(active_since) = self.active_since_ms {
+                    let cleared_since = *self.cleared_since_ms.get_or_insert(timestamp_ms);
+                    if timestamp_ms.saturating_sub(cleared_since) >= timing.deactivation_delay_ms
+                        && timestamp_ms.saturating_sub(active_since) >= timing.min_display_ms
+                    {
+                        self.active_since_ms = None;
+                        self.cleared_since_ms = None;
+                        self.cooldown_until_ms = cleared_since + timing.cooldown_ms;
+                    }
+                }
+            }
+            None => {}
+        }
+        self.active_since_ms
+    }
+}
+
+/// Evaluates the alert rules on every game state update and decides which alerts are shown
+#[derive(Debug, Clone, Default)]
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    states: Vec<AlertState>,
+    float_detector: FloatDetector,
+    float_timing: AlertTiming,
+    float_state: AlertState,
+    /// Last resource float warning, shown until the warning is hidden
+    float_alert: Option<Alert>,
+}
+
+impl AlertEngine {
+    pub fn new(config: &AlertConfig) -> Self {
+        Self {
+            rules: config.rules.clone(),
+            states: vec![AlertState::default(); config.rules.len()],
+            float_detector: FloatDetector::new(&config.resource_float),
+            float_timing: Self::float_timing(config),
+            ..Default::default()
+        }
+    }
+
+    /// The float detector waits `min_duration_ms` itself
+    fn float_timing(config: &AlertConfig) -> AlertTiming {
+        AlertTiming {
+            min_duration_ms: 0,
+            ..config.resource_float.timing
+        }
+    }
+
+    /// Replace the rules. The state of all rules is reset if they changed.
+    pub fn set_config(&mut self, config: &AlertConfig) {
+        if self.rules != config.rules {
+            self.rules = config.rules.clone();
+            self.states = vec![AlertState::default(); config.rules.len()];
+        }
+        self.float_detector.set_config(&config.resource_float);
+        self.float_timing = Self::float_timing(config);
+    }
+
+    /// Evaluate all rules and return the shown alerts, highest priority first and older alerts
+    /// first among equal priorities. `icons` tells by name which icons of the icon registry are
+    /// found.
+    pub fn update(
+        &mut self,
+        state: &GameState,
+        icons: &BTreeMap<String, bool>,
+        timestamp_ms: u64,
+    ) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for (rule, rule_state) in self.rules.iter().zip(self.states.iter_mut()) {
+            let condition = rule.condition.eval_with_icons(state, icons);
+            if let Some(since_ms) = rule_state.update(condition, &rule.timing, timestamp_ms) {
+                alerts.push(Alert {
+                    name: rule.name.clone(),
+                    priority: rule.priority,
+                    message: rule.message.clone(),
+                    message_args: Vec::new(),
+                    icon: rule.icon.clone(),
+                    since_ms,
+                });
+            }
+        }
+
+        let float_alert = self.float_detector.alert(state, timestamp_ms);
+        let floating = Some(float_alert.is_some());
+        if float_alert.is_some() {
+            self.float_alert = float_alert;
+        }
+        if self.float_state.update(floating, &self.float_timing, timestamp_ms).is_some() {
+            alerts.extend(self.float_alert.clone());
+        } else {
+            self.float_alert = None;
+        }
+
+        alerts.sort_by_key(|alert| (Reverse(alert.priority), alert.since_ms));
+        alerts
+    }
+
+    /// Forget how long conditions held and all cooldowns
+    pub fn reset(&mut self) {
+        self.states = vec![AlertState::default(); self.rules.len()];
+        self.float_detector.reset();
+        self.float_state = AlertState::default();
+        self.float_alert = None;
+    }
+}
@@ -0,0 +1,285 @@
+// Build order guide that advances with the population and worker readings
+//
+// The file format follows the build orders of the community RTS overlays (e.g. from
+// buildorderguide.com or age4builder.com), which are JSON files like:
+//
+//   {
+//     "name": "English Longbow Rush",
+//     "civilization": "English",
+//     "build_order": [
+//       { "population_count": 6, "villager_count": 6, "age": 1,
+//         "resources": { "food": 6, "wood": 0, "gold": 0, "stone": 0 },
+//         "notes": ["6 on sheep"] },
+//       ...
+//     ]
+//   }
+//
+// Counts of -1 mean "not specified". The same structure can be written as `.toml` file.
+
+use crate::game_state::{GameState, Resource};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+fn unspecified() -> i32 {
+    -1
+}
+
+/// Worker assignment of a step
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StepResources {
+    #[serde(default = "unspecified")]
+    pub food: i32,
+    #[serde(default = "unspecified")]
+    pub wood: i32,
+    #[serde(default = "unspecified")]
+    pub gold: i32,
+    #[serde(default = "unspecified")]
+    pub stone: i32,
+    /// Builders are not shown in the HUD. They count towards the villager count of the step, up
+    /// to the population that is not gathering or idle.
+    #[serde(default = "unspecified")]
+    pub builder: i32,
+}
+
+impl Default for StepResources {
+    fn default() -> Self {
+        Self {
+            food: -1,
+            wood: -1,
+            gold: -1,
+            stone: -1,
+            builder: -1,
+        }
+    }
+}
+
+impl StepResources {
+    fn get(&self, resource: Resource) -> i32 {
+        match resource {
+            Resource::Food => self.food,
+            Resource::Wood => self.wood,
+            Resource::Gold => self.gold,
+            Resource::Stone => self.stone,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildOrderStep {
+    #[serde(default = "unspecified")]
+    pub population_count: i32,
+    #[serde(default = "unspecified")]
+    pub villager_count: i32,
+    /// Age of the step, 1 for the Dark Age up to 4 for the Imperial Age
+    #[serde(default)]
+    pub age: i32,
+    #[serde(default)]
+    pub resources: StepResources,
+    /// Target game time, e.g. "2:30"
+    #[serde(default)]
+    pub time: Option<String>,
+    #[serde(default)]
+    pub notes: Vec<String>,
+}
+
+impl Default for BuildOrderStep {
+    fn default() -> Self {
+        Self {
+            population_count: -1,
+            villager_count: -1,
+            age: 0,
+            resources: StepResources::default(),
+            time: None,
+            notes: Vec::new(),
+        }
+    }
+}
+
+impl BuildOrderStep {
+    /// Notes without the image references of the community format (`@path/to/icon.png@`)
+    pub fn text(&self) -> String {
+        self.notes
+            .iter()
+            .map(|note| {
+                note.split('@')
+                    .enumerate()
+                    .filter(|(index, part)| index % 2 == 0 || !part.contains('.'))
+                    .map(|(_, part)| part)
+                    .collect::<String>()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .filter(|note| !note.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Villagers of the step: the workers and idle villagers of the HUD plus the builders of the
+    /// step, which the HUD doesn't show. The population not counted in the HUD caps the builders.
+    fn villagers(&self, state: &GameState) -> Option<u32> {
+        let counted = state.villagers().known()?;
+        let mut builders = self.resources.builder.max(0) as u32;
+        if let Some(population) = state.population.known() {
+            builders = builders.min(population.current.saturating_sub(counted));
+        }
+        Some(counted + builders)
+    }
+
+    /// Whether the readings reach all targets of the step. Unknown readings never do, except for
+    /// the age, which is only checked once it was detected. Advancing counts as the next age.
+    pub fn is_reached(&self, state: &GameState) -> bool {
+        let reached = |target: i32, value: Option<u32>| {
+            target < 0 || value.is_some_and(|value| value as i64 >= target as i64)
+        };
+        let workers = Resource::ALL.map(|resource| state.workers.get(resource).known());
+        let age_reached = state.age.known().is_none_or(|age| {
+            (age.number() + state.aging_up as u32) as i64 >= self.age as i64
+        });
+
+        reached(
+            self.population_count,
+            state.population.known().map(|p| p.current),
+        ) && age_reached
+            && reached(self.villager_count, self.villagers(state))
+            && Resource::ALL
+                .into_iter()
+                .zip(workers)
+                .all(|(resource, count)| reached(self.resources.get(resource), count))
+    }
+}
+
+/// One civilization or a list of them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Civilization {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildOrder {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub civilization: Option<Civilization>,
+    #[serde(default)]
+    pub author: Option<String>,
+    pub build_order: Vec<BuildOrderStep>,
+}
+
+impl BuildOrder {
+    /// Load a build order from a `.json` or `.toml` file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read build order {}", path.display()))?;
+        let build_order: BuildOrder = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&content)?,
+            _ => serde_json::from_str(&content)?,
+        };
+        if build_order.build_order.is_empty() {
+            bail!("Build order {} has no steps", path.display());
+        }
+        Ok(build_order)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildOrderConfig {
+    /// Build order file (.json or .toml). No guide is shown if not set.
+    pub file: Option<PathBuf>,
+    /// Consecutive frames the targets of a step must be reached in before the guide advances,
+    /// so that a single misread doesn't skip a step
+    pub confirm_frames: u32,
+}
+
+impl Default for BuildOrderConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            confirm_frames: 3,
+        }
+    }
+}
+
+impl BuildOrderConfig {
+    pub fn load_build_order(&self) -> Result<Option<BuildOrder>> {
+        self.file.as_deref().map(BuildOrder::load).transpose()
+    }
+}
+
+/// Position in the build order, for display
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildOrderProgress {
+    pub name: String,
+    /// Index of the current step, equal to `steps` when all steps are done
+    pub step: usize,
+    pub steps: usize,
+    /// Notes of the current step
+    pub current: Option<String>,
+    /// Notes of the next step
+    pub next: Option<String>,
+}
+
+/// Follows a build order: the current step is the first one whose targets were not reached yet
+#[derive(Debug, Clone, Default)]
+pub struct BuildOrderGuide {
+    build_order: Option<BuildOrder>,
+    step: usize,
+    /// Consecutive frames the targets of the current step were reached in
+    reached_frames: u32,
+    confirm_frames: u32,
+}
+
+impl BuildOrderGuide {
+    pub fn new(build_order: Option<BuildOrder>, confirm_frames: u32) -> Self {
+        Self {
+            build_order,
+            step: 0,
+            reached_frames: 0,
+            confirm_frames,
+        }
+    }
+
+    /// Replace the build order and start from the first step if it changed
+    pub fn set_build_order(&mut self, build_order: Option<BuildOrder>) {
+        if self.build_order != build_order {
+            *self = Self::new(build_order, self.confirm_frames);
+        }
+    }
+
+    pub fn set_confirm_frames(&mut self, confirm_frames: u32) {
+        self.confirm_frames = confirm_frames;
+    }
+
+    /// Start from the first step, e.g. for a new match
+    pub fn reset(&mut self) {
+        self.step = 0;
+        self.reached_frames = 0;
+    }
+
+    /// Advance to the next step once the readings reach the targets of the current step in
+    /// `confirm_frames` consecutive frames. Steps never go back.
+    pub fn update(&mut self, state: &GameState) -> Option<BuildOrderProgress> {
+        let build_order = self.build_order.as_ref()?;
+        let steps = &build_order.build_order;
+        if steps.get(self.step).is_some_and(|step| step.is_reached(state)) {
+            self.reached_frames += 1;
+            if self.reached_frames >= self.confirm_frames {
+                self.step += 1;
+                self.reached_frames = 0;
+            }
+        } else {
+            self.reached_frames = 0;
+        }
+        Some(BuildOrderProgress {
+            name: build_order.name.clone(),
+            step: self.step,
+            steps: steps.len(),
+            current: steps.get(self.step).map(BuildOrderStep::text),
+            next: steps.get(self.step + 1).map(BuildOrderStep::text),
+        })
+    }
+}
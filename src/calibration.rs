@@ -0,0 +1,338 @@
+// HUD calibration from anchor icons found in a full frame
+
+use crate::{
+    consts::TEMPLATE_REFERENCE_HEIGHT,
+    hud_layout::{HudLayout, LayoutRect, StatRegion},
+    image_analyzer::ImageAnalyzerInner,
+};
+use anyhow::Result;
+use opencv::{
+    core::{AlgorithmHint, Mat, Rect, Size},
+    imgcodecs::{self, IMREAD_COLOR},
+    imgproc,
+    prelude::*,
+};
+
+/// An icon with a known position in the HUD panel
+#[derive(Debug, Clone, Copy)]
+pub struct Anchor {
+    pub name: &'static str,
+    pub template_path: &'static str,
+    /// Top-left corner of the template in the HUD panel, in template pixels (see
+    /// [`TEMPLATE_REFERENCE_HEIGHT`])
+    pub x: f32,
+    pub y: f32,
+}
+
+/// The villager icon is the most distinctive anchor and is searched for first
+pub const ANCHORS: [Anchor; 6] = [
+    Anchor {
+        name: "villager",
+        template_path: "src_images/detect/villager.png",
+        x: 16.0,
+        y: 23.0,
+    },
+    Anchor {
+        name: "population",
+        template_path: "src_images/anchors/population.png",
+        x: 12.0,
+        y: 176.0,
+    },
+    Anchor {
+        name: "food",
+        template_path: "src_images/anchors/food.png",
+        x: 18.0,
+        y: 268.0,
+    },
+    Anchor {
+        name: "wood",
+        template_path: "src_images/anchors/wood.png",
+        x: 18.0,
+        y: 320.0,
+    },
+    Anchor {
+        name: "gold",
+        template_path: "src_images/anchors/gold.png",
+        x: 18.0,
+        y: 372.0,
+    },
+    Anchor {
+        name: "stone",
+        template_path: "src_images/anchors/stone.png",
+        x: 16.0,
+        y: 425.0,
+    },
+];
+
+/// An anchor found in the frame
+#[derive(Debug, Clone)]
+pub struct AnchorMatch {
+    pub anchor: Anchor,
+    pub score: f64,
+    /// Bounding box in frame pixels
+    pub bbox: Rect,
+    /// Frame pixels per template pixel
+    pub scale: f32,
+}
+
+/// Result of a calibration run
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    pub matches: Vec<AnchorMatch>,
+    /// Frame pixels per template pixel
+    pub scale: f32,
+    /// Top-left corner of the HUD panel in frame pixels
+    pub origin: (f32, f32),
+    /// Layout measured at the size of the calibrated frame
+    pub layout: HudLayout,
+}
+
+/// Configuration for [`HudCalibrator`]
+#[derive(Debug, Clone)]
+pub struct CalibrationConfig {
+    /// Minimum template matching score for an anchor to count
+    pub min_score: f64,
+    /// Range of UI scales to search the villager icon in (1.0 = 100%)
+    pub min_ui_scale: f32,
+    pub max_ui_scale: f32,
+    pub ui_scale_step: f32,
+    /// Search margin around the predicted position of the remaining anchors, in template pixels
+    pub search_margin: f32,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            min_score: 0.6,
+            min_ui_scale: 0.5,
+            max_ui_scale: 2.0,
+            ui_scale_step: 0.025,
+            search_margin: 40.0,
+        }
+    }
+}
+
+/// Derives a [`HudLayout`] by searching a full frame for the anchor icons
+pub struct HudCalibrator {
+    anchors: Vec<(Anchor, Mat)>,
+    config: CalibrationConfig,
+}
+
+impl HudCalibrator {
+    pub fn new(config: CalibrationConfig) -> Result<Self> {
+        let mut anchors = Vec::new();
+        for anchor in ANCHORS {
+            let template = imgcodecs::imread(anchor.template_path, IMREAD_COLOR)?;
+            if template.empty() {
+                anyhow::bail!("Failed to load anchor template from {}", anchor.template_path);
+            }
+            anchors.push((anchor, template));
+        }
+        Ok(Self { anchors, config })
+    }
+
+    /// Search the frame for the anchors and derive the HUD layout from their positions
+    ///
+    /// # Arguments
+    ///
+    /// * `frame`: &Mat - Full frame in BGR or BGRA format
+    pub fn calibrate(&self, frame: &Mat) -> Result<Calibration> {
+        let frame = if frame.channels() == 4 {
+            let mut bgr = Mat::default();
+            imgproc::cvt_color(
+                frame,
+                &mut bgr,
+                imgproc::COLOR_BGRA2BGR,
+                0,
+                AlgorithmHint::ALGO_HINT_DEFAULT,
+            )?;
+            bgr
+        } else {
+            frame.try_clone()?
+        };
+        let frame_width = frame.cols() as f32;
+        let frame_height = frame.rows() as f32;
+        let full_frame = Rect::new(0, 0, frame.cols(), frame.rows());
+
+        // Find the villager icon over all UI scales in the full frame
+        let (villager, villager_template) = &self.anchors[0];
+        let resolution_scale = frame_height / TEMPLATE_REFERENCE_HEIGHT;
+        let mut best: Option<AnchorMatch> = None;
+        let mut ui_scale = self.config.min_ui_scale;
+        while ui_scale <= self.config.max_ui_scale {
+            let scale = ui_scale * resolution_scale;
+            let template = Self::scale_template(villager_template, scale)?;
+            if let Some((score, bbox)) =
+                ImageAnalyzerInner::find_template(&frame, &template, full_frame)?
+            {
+                if best.as_ref().is_none_or(|b| score > b.score) {
+                    best = Some(AnchorMatch {
+                        anchor: *villager,
+                        score,
+                        bbox,
+                        scale,
+                    });
+                }
+            }
+            ui_scale += self.config.ui_scale_step;
+        }
+        let villager_match = match best {
+            Some(m) if m.score >= self.config.min_score => m,
+            Some(m) => anyhow::bail!(
+                "Villager icon not found (best score {:.2} < {:.2})",
+                m.score,
+                self.config.min_score
+            ),
+            None => anyhow::bail!("Frame is too small to search for the villager icon"),
+        };
+        log::info!(
+            "Found villager icon at {:?} with score {:.2} and scale {:.3}",
+            villager_match.bbox,
+            villager_match.score,
+            villager_match.scale
+        );
+
+        // Search the remaining anchors around their predicted position
+        let scale = villager_match.scale;
+        let origin = (
+            villager_match.bbox.x as f32 - villager.x * scale,
+            villager_match.bbox.y as f32 - villager.y * scale,
+        );
+        let mut matches = vec![villager_match];
+        for (anchor, template) in &self.anchors[1..] {
+            let template = Self::scale_template(template, scale)?;
+            let margin = self.config.search_margin * scale;
+            let search_area = Rect::new(
+                (origin.0 + anchor.x * scale - margin) as i32,
+                (origin.1 + anchor.y * scale - margin) as i32,
+                (template.cols() as f32 + 2.0 * margin) as i32,
+                (template.rows() as f32 + 2.0 * margin) as i32,
+            );
+            match ImageAnalyzerInner::find_template(&frame, &template, search_area)? {
+                Some((score, bbox)) if score >= self.config.min_score => {
+                    log::info!(
+                        "Found {} anchor at {:?} with score {:.2}",
+                        anchor.name,
+                        bbox,
+                        score
+                    );
+                    matches.push(AnchorMatch {
+                        anchor: *anchor,
+                        score,
+                        bbox,
+                        scale,
+                    });
+                }
+                Some((score, _)) => {
+                    log::warn!("Anchor {} not found (best score {:.2})", anchor.name, score)
+                }
+                None => log::warn!("Anchor {} is outside of the frame", anchor.name),
+            }
+        }
+
+        let (scale, origin) = Self::fit(&matches).unwrap_or((scale, origin));
+        let layout = Self::derive_layout(frame_width as u32, frame_height as u32, scale, origin);
+
+        Ok(Calibration {
+            matches,
+            scale,
+            origin,
+            layout,
+        })
+    }
+
+    fn scale_template(template: &Mat, scale: f32) -> Result<Mat> {
+        let mut scaled = Mat::default();
+        let interpolation = if scale < 1.0 {
+            imgproc::INTER_AREA
+        } else {
+            imgproc::INTER_LINEAR
+        };
+        imgproc::resize(
+            template,
+            &mut scaled,
+            Size::new(
+                ((template.cols() as f32 * scale).round() as i32).max(1),
+                ((template.rows() as f32 * scale).round() as i32).max(1),
+            ),
+            0.0,
+            0.0,
+            interpolation,
+        )?;
+        Ok(scaled)
+    }
+
+    /// Least squares fit of `frame = origin + scale * anchor` over all matched anchors.
+    /// Returns `None` if the anchors do not span enough distance for a stable fit.
+    pub fn fit(matches: &[AnchorMatch]) -> Option<(f32, (f32, f32))> {
+        if matches.len() < 2 {
+            return None;
+        }
+        let n = matches.len() as f32;
+        let mean = |f: &dyn Fn(&AnchorMatch) -> f32| matches.iter().map(f).sum::<f32>() / n;
+        let (mean_ax, mean_ay) = (mean(&|m| m.anchor.x), mean(&|m| m.anchor.y));
+        let (mean_fx, mean_fy) = (mean(&|m| m.bbox.x as f32), mean(&|m| m.bbox.y as f32));
+
+        let mut covariance = 0.0;
+        let mut variance = 0.0;
+        for m in matches {
+            let (ax, ay) = (m.anchor.x - mean_ax, m.anchor.y - mean_ay);
+            covariance += ax * (m.bbox.x as f32 - mean_fx) + ay * (m.bbox.y as f32 - mean_fy);
+            variance += ax * ax + ay * ay;
+        }
+        if variance < 100.0 {
+            return None;
+        }
+        let scale = covariance / variance;
+        Some((scale, (mean_fx - scale * mean_ax, mean_fy - scale * mean_ay)))
+    }
+
+    /// Layout measured at the calibrated frame size. The UI scale is expressed relative to the
+    /// template scale, so the normalized HUD panel matches the templates again.
+    pub fn derive_layout(
+        frame_width: u32,
+        frame_height: u32,
+        scale: f32,
+        origin: (f32, f32),
+    ) -> HudLayout {
+        let reference = HudLayout::default();
+        let ui_scale = scale * TEMPLATE_REFERENCE_HEIGHT / frame_height as f32;
+        let (origin_x, origin_y) = (origin.0.max(0.0), origin.1.max(0.0));
+        let icon_area = reference.villager_icon_area;
+        let scaled = |area: LayoutRect| LayoutRect {
+            x: area.x * scale,
+            y: area.y * scale,
+            width: area.width * scale,
+            height: area.height * scale,
+        };
+
+        HudLayout {
+            name: format!("calibrated {}x{}", frame_width, frame_height),
+            reference_width: frame_width,
+            reference_height: frame_height,
+            reference_ui_scale: ui_scale,
+            ui_scale,
+            area_width: origin_x + reference.area_width * scale,
+            area_height: frame_height as f32 - origin_y,
+            stat_width: reference.stat_width * scale,
+            stat_height: reference.stat_height * scale,
+            villager_icon_area: LayoutRect {
+                x: origin_x + icon_area.x * scale,
+                y: icon_area.y * scale,
+                width: icon_area.width * scale,
+                height: icon_area.height * scale,
+            },
+            clock_area: scaled(reference.clock_area),
+            age_area: scaled(reference.age_area),
+            stats: reference
+                .stats
+                .iter()
+                .map(|stat| StatRegion {
+                    name: stat.name.clone(),
+                    x: origin_x + stat.x * scale,
+                    y: stat.y * scale,
+                })
+                .collect(),
+        }
+    }
+}
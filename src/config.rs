@@ -0,0 +1,235 @@
+// Persistent configuration file with live reload
+
+use crate::{
+    alerts::AlertConfig,
+    build_order::BuildOrderConfig,
+    game_state_tracker::TrackerConfig,
+    hud_layout::HudLayout,
+    image_analyzer::{AnalyzerConfig, OCRModel},
+    match_report::ReportConfig,
+    match_stats::StatsConfig,
+    session::SessionConfig,
+    worker_advisor::WorkerConfig,
+    overlay_window_gtk::OverlayConfig,
+};
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::watch;
+
+/// How often the configuration file is checked for changes
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureMode {
+    /// Full screen
+    Monitor,
+    /// Application window
+    Window,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Changes require a restart
+    pub mode: CaptureMode,
+    /// Capture only starts when this process is running. Empty to start right away. Changes
+    /// require a restart.
+    pub process_name: String,
+    /// Process check interval in milliseconds. Changes require a restart.
+    pub check_interval_ms: u64,
+    /// Minimum time between two analyzed frames in milliseconds
+    pub frame_interval_ms: u64,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            mode: CaptureMode::Window,
+            process_name: "RelicCardinal.".to_string(),
+            check_interval_ms: 3000,
+            frame_interval_ms: 250,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// HUD layout file (.toml or .json). If not set, a builtin profile is picked by frame size.
+    pub file: Option<PathBuf>,
+    /// In-game UI scale (1.0 = 100%), overrides the value of the layout
+    pub ui_scale: Option<f32>,
+}
+
+impl LayoutConfig {
+    pub fn load_layout(&self) -> Result<Option<HudLayout>> {
+        self.file.as_deref().map(HudLayout::load).transpose()
+    }
+}
+
+/// Content of `config.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// Language of the overlay texts, e.g. "de". If not set, the system locale is used. Changes
+    /// require a restart.
+    pub language: Option<String>,
+    pub capture: CaptureConfig,
+    pub analysis: AnalyzerConfig,
+    pub tracking: TrackerConfig,
+    pub alerts: AlertConfig,
+    pub session: SessionConfig,
+    pub stats: StatsConfig,
+    pub workers: WorkerConfig,
+    pub build_order: BuildOrderConfig,
+    pub report: ReportConfig,
+    pub layout: LayoutConfig,
+    pub overlay: OverlayConfig,
+}
+
+/// Values given on the command line. They take precedence over the configuration file, also
+/// after a reload.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub capture_mode: Option<CaptureMode>,
+    pub process_name: Option<String>,
+    pub check_interval_ms: Option<u64>,
+    pub frame_interval_ms: Option<u64>,
+    pub ocr_engine: Option<OCRModel>,
+    pub layout: Option<PathBuf>,
+    pub ui_scale: Option<f32>,
+    pub build_order: Option<PathBuf>,
+    pub show_debug_window: Option<bool>,
+}
+
+impl ConfigOverrides {
+    pub fn apply(&self, config: &mut AppConfig) {
+        if let Some(mode) = self.capture_mode {
+            config.capture.mode = mode;
+        }
+        if let Some(process_name) = &self.process_name {
+            config.capture.process_name = process_name.clone();
+        }
+        if let Some(check_interval_ms) = self.check_interval_ms {
+            config.capture.check_interval_ms = check_interval_ms;
+        }
+        if let Some(frame_interval_ms) = self.frame_interval_ms {
+            config.capture.frame_interval_ms = frame_interval_ms;
+        }
+        if let Some(ocr_engine) = self.ocr_engine {
+            config.analysis.ocr_engine = ocr_engine;
+        }
+        if let Some(layout) = &self.layout {
+            config.layout.file = Some(layout.clone());
+        }
+        if let Some(ui_scale) = self.ui_scale {
+            config.layout.ui_scale = Some(ui_scale);
+        }
+        if let Some(build_order) = &self.build_order {
+            config.build_order.file = Some(build_order.clone());
+        }
+        if let Some(show_debug_window) = self.show_debug_window {
+            config.overlay.show_debug_window = show_debug_window;
+        }
+    }
+}
+
+impl AppConfig {
+    /// Default location of the configuration file
+    pub fn default_path() -> PathBuf {
+        crate::utils::config_dir().join("config.toml")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    /// Load the configuration file, or write one with the default values if it does not exist
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        if path.exists() {
+            return Self::load(path);
+        }
+
+        let config = Self::default();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(&config)?)
+            .with_context(|| format!("Failed to write config file {}", path.display()))?;
+        info!("Wrote default configuration to {}", path.display());
+        Ok(config)
+    }
+
+    /// Log changes that only apply after a restart
+    fn warn_about_restart(&self, previous: &AppConfig) {
+        if self.capture.mode != previous.capture.mode
+            || self.capture.process_name != previous.capture.process_name
+            || self.capture.check_interval_ms != previous.capture.check_interval_ms
+        {
+            warn!("Changes to capture mode, process name and check interval require a restart");
+        }
+        if self.language != previous.language {
+            warn!("Changes to the language require a restart");
+        }
+    }
+}
+
+/// Reloads the configuration file whenever it changes and publishes it on a watch channel
+pub struct ConfigWatcher;
+
+impl ConfigWatcher {
+    /// Start watching `path`. The thread ends when all receivers are dropped.
+    pub fn spawn(
+        path: PathBuf,
+        overrides: ConfigOverrides,
+        config: AppConfig,
+    ) -> watch::Receiver<AppConfig> {
+        let (sender, receiver) = watch::channel(config);
+
+        std::thread::spawn(move || {
+            let modified = |path: &Path| -> Option<SystemTime> {
+                std::fs::metadata(path).and_then(|m| m.modified()).ok()
+            };
+            let mut last_modified = modified(&path);
+
+            while !sender.is_closed() {
+                std::thread::sleep(RELOAD_CHECK_INTERVAL);
+                let current_modified = modified(&path);
+                if current_modified == last_modified {
+                    continue;
+                }
+                last_modified = current_modified;
+
+                let mut config = match AppConfig::load(&path) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        error!("Keeping previous configuration: {:#}", e);
+                        continue;
+                    }
+                };
+                overrides.apply(&mut config);
+
+                sender.send_if_modified(|current| {
+                    if *current == config {
+                        return false;
+                    }
+                    info!("Configuration file {} changed, applying", path.display());
+                    config.warn_about_restart(current);
+                    *current = config;
+                    true
+                });
+            }
+        });
+
+        receiver
+    }
+}
@@ -0,0 +1,124 @@
+// Annotated screenshots with the expected HUD values, for accuracy tests and template extraction
+
+use crate::{
+    consts::AOE4_STATS_POS,
+    game_state::Age,
+    hud_layout::{HudLayout, ResolvedHudLayout},
+    image_analyzer::ImageAnalyzerInner,
+};
+use anyhow::{Context, Result};
+use opencv::{
+    core::Mat,
+    imgcodecs::{self, IMREAD_COLOR},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// One annotated screenshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    /// Image file, relative to the annotation file
+    pub file: PathBuf,
+    /// The image is the HUD panel cut out at 1440p and 100% UI scale instead of a full frame
+    #[serde(default)]
+    pub hud_crop: bool,
+    /// HUD layout file, relative to the annotation file. Defaults to a builtin profile.
+    #[serde(default)]
+    pub layout: Option<PathBuf>,
+    /// Whether the villager icon is visible, not evaluated if missing
+    #[serde(default)]
+    pub villager_icon: Option<bool>,
+    /// Age shown by the age emblem, only for full frames. Not evaluated if missing.
+    #[serde(default)]
+    pub age: Option<Age>,
+    /// Expected text per stat name (see `AOE4_STATS_POS`). Stats that are hidden or unreadable
+    /// in the screenshot are left out.
+    #[serde(default)]
+    pub values: BTreeMap<String, String>,
+}
+
+/// Content of an annotation file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Corpus {
+    #[serde(default, rename = "sample")]
+    pub samples: Vec<Sample>,
+    /// Directory of the annotation file, the base for relative paths
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+impl Corpus {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read annotations {}", path.display()))?;
+        let mut corpus: Corpus = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse annotations {}", path.display()))?;
+        corpus.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        for sample in &corpus.samples {
+            if sample.hud_crop && sample.age.is_some() {
+                anyhow::bail!(
+                    "{}: the age emblem is not part of a HUD crop",
+                    sample.file.display()
+                );
+            }
+            for name in sample.values.keys() {
+                if Self::stat_index(name).is_none() {
+                    anyhow::bail!("{}: unknown stat '{}'", sample.file.display(), name);
+                }
+            }
+        }
+        Ok(corpus)
+    }
+
+    /// Index of a stat name in `AOE4_STATS_POS`
+    pub fn stat_index(name: &str) -> Option<usize> {
+        AOE4_STATS_POS.iter().position(|stat| stat.name == name)
+    }
+
+    fn load_image(&self, sample: &Sample) -> Result<Mat> {
+        let path = self.base_dir.join(&sample.file);
+        let image = imgcodecs::imread(&path.to_string_lossy(), IMREAD_COLOR)?;
+        if image.empty() {
+            anyhow::bail!("Failed to load image from {}", path.display());
+        }
+        Ok(image)
+    }
+
+    /// Load the full frame of a sample and the layout to analyze it with, e.g. for the age emblem
+    /// and the match timer. Fails for HUD crops.
+    pub fn load_frame(&self, sample: &Sample) -> Result<(Mat, ResolvedHudLayout)> {
+        if sample.hud_crop {
+            anyhow::bail!("{} is a HUD crop, not a full frame", sample.file.display());
+        }
+        let image = self.load_image(sample)?;
+        let layout = match &sample.layout {
+            Some(layout) => HudLayout::load(&self.base_dir.join(layout))?,
+            None => HudLayout::for_frame_size(image.cols() as u32, image.rows() as u32),
+        };
+        let resolved = layout.resolve(image.cols() as u32, image.rows() as u32);
+        Ok((image, resolved))
+    }
+
+    /// Load the normalized HUD panel of a sample and the layout to analyze it with
+    pub fn load_hud(&self, sample: &Sample) -> Result<(Mat, ResolvedHudLayout)> {
+        if !sample.hud_crop {
+            let (frame, resolved) = self.load_frame(sample)?;
+            let hud = ImageAnalyzerInner::extract_hud_area(&frame, &resolved)?;
+            return Ok((hud, resolved));
+        }
+
+        // The crop already has the reference scale, only the regions inside it are needed
+        let image = self.load_image(sample)?;
+        let layout = match &sample.layout {
+            Some(layout) => HudLayout::load(&self.base_dir.join(layout))?,
+            None => HudLayout::default(),
+        };
+        let resolved = layout.resolve(layout.reference_width, layout.reference_height);
+        Ok((image, resolved))
+    }
+}
@@ -0,0 +1,335 @@
+// Digit templates for the template matching OCR, cut from annotated screenshots
+//
+// The stat regions of every sample of a corpus (see `corpus::Sample`) are preprocessed like in
+// the analyzer and split into glyphs at the columns without text. Regions with as many glyphs as
+// characters in their annotated value give one template candidate per glyph, which is kept as new
+// variant unless it matches a template of the same character already.
+
+use crate::{
+    corpus::Corpus,
+    ocr::template_matching_ocr::{TemplateMatchingOcrEngine, template_char},
+    preprocessing::{self, PreprocessConfig},
+};
+use anyhow::{Context, Result};
+use opencv::{
+    core::{Mat, Rect, Size, Vector},
+    imgcodecs::{self, IMREAD_GRAYSCALE},
+    imgproc,
+    prelude::*,
+};
+use std::path::Path;
+
+/// Thresholds of the template extraction
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractionConfig {
+    /// Glyphs correlating at least this much with a template of the same character are duplicates
+    pub duplicate_threshold: f64,
+    /// Templates of different characters correlating at least this much are reported as ambiguous
+    pub ambiguity_threshold: f64,
+    /// Glyphs with fewer text pixels are noise
+    pub min_glyph_pixels: usize,
+}
+
+impl Default for ExtractionConfig {
+    fn default() -> Self {
+        Self {
+            duplicate_threshold: 0.95,
+            ambiguity_threshold: 0.85,
+            min_glyph_pixels: 8,
+        }
+    }
+}
+
+/// Two templates of different characters that the OCR may confuse
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ambiguity {
+    pub first: String,
+    pub second: String,
+    pub score: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct ExtractionReport {
+    /// File stems of the written templates
+    pub written: Vec<String>,
+    /// Glyphs dropped because a template of the same character matches them
+    pub duplicates: usize,
+    /// Annotated regions whose glyphs could not be assigned to the expected text
+    pub skipped_regions: usize,
+    pub ambiguities: Vec<Ambiguity>,
+}
+
+struct GlyphTemplate {
+    character: char,
+    name: String,
+    image: Mat,
+    /// Extracted in this run, not loaded from the template directory
+    new: bool,
+}
+
+/// File stem of a template variant, the inverse of [`template_char`]. The first variant of a
+/// separator has no number, like the hand cut `slash.png`.
+pub fn template_file_stem(character: char, variant: usize) -> Option<String> {
+    let name = match character {
+        '/' => "slash",
+        ':' => "colon",
+        _ if character.is_ascii_digit() => return Some(format!("{}-{}", character, variant)),
+        _ => return None,
+    };
+    Some(match variant {
+        0 => name.to_string(),
+        _ => format!("{}-{}", name, variant),
+    })
+}
+
+/// Bounding boxes of the glyphs of a grayscale text region, left to right. Text pixels are the
+/// ones brighter than the Otsu threshold, glyphs are separated by columns without text pixels.
+pub fn segment_glyphs(gray: &Mat, min_pixels: usize) -> Result<Vec<Rect>> {
+    let mut binary = Mat::default();
+    imgproc::threshold(
+        gray,
+        &mut binary,
+        0.0,
+        255.0,
+        imgproc::THRESH_BINARY | imgproc::THRESH_OTSU,
+    )?;
+
+    let mut glyphs = Vec::new();
+    let mut start = None;
+    for x in 0..=binary.cols() {
+        let mut has_text = false;
+        if x < binary.cols() {
+            for y in 0..binary.rows() {
+                has_text |= *binary.at_2d::<u8>(y, x)? > 0;
+            }
+        }
+        match (start, has_text) {
+            (None, true) => start = Some(x),
+            (Some(first), false) => {
+                start = None;
+                glyphs.extend(glyph_bounds(&binary, first, x, min_pixels)?);
+            }
+            _ => {}
+        }
+    }
+    Ok(glyphs)
+}
+
+/// Bounding box of the text pixels in the columns `first..end` with one pixel of background
+/// around them, like the hand cut templates. `None` if there are too few text pixels.
+fn glyph_bounds(binary: &Mat, first: i32, end: i32, min_pixels: usize) -> Result<Option<Rect>> {
+    let mut pixels = 0;
+    let (mut top, mut bottom) = (i32::MAX, 0);
+    for y in 0..binary.rows() {
+        for x in first..end {
+            if *binary.at_2d::<u8>(y, x)? > 0 {
+                pixels += 1;
+                top = top.min(y);
+                bottom = bottom.max(y);
+            }
+        }
+    }
+    if pixels < min_pixels {
+        return Ok(None);
+    }
+    let x = (first - 1).max(0);
+    let y = (top - 1).max(0);
+    let right = (end + 1).min(binary.cols());
+    let bottom = (bottom + 2).min(binary.rows());
+    Ok(Some(Rect::new(x, y, right - x, bottom - y)))
+}
+
+/// Normalized correlation of two grayscale templates, the second resized to the size of the first
+pub fn similarity(first: &Mat, second: &Mat) -> Result<f64> {
+    let mut resized = Mat::default();
+    imgproc::resize(
+        second,
+        &mut resized,
+        Size::new(first.cols(), first.rows()),
+        0.0,
+        0.0,
+        imgproc::INTER_LINEAR,
+    )?;
+    let mut result = Mat::default();
+    imgproc::match_template(
+        &resized,
+        first,
+        &mut result,
+        imgproc::TM_CCOEFF_NORMED,
+        &Mat::default(),
+    )?;
+    // Uniform images have no defined correlation
+    let score = *result.at_2d::<f32>(0, 0)? as f64;
+    Ok(if score.is_nan() { 0.0 } else { score })
+}
+
+/// Templates already in the template directory
+fn load_templates(dir: &Path) -> Result<Vec<GlyphTemplate>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut templates = Vec::new();
+    for entry in std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read template directory {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if !path.extension().is_some_and(|ext| ext == "png") {
+            continue;
+        }
+        let name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let Some(character) = template_char(&name) else {
+            log::warn!("Ignoring file: '{}'", path.display());
+            continue;
+        };
+        let image = imgcodecs::imread(&path.to_string_lossy(), IMREAD_GRAYSCALE)?;
+        if image.empty() {
+            anyhow::bail!("Failed to load template image from {}", path.display());
+        }
+        templates.push(GlyphTemplate {
+            character,
+            name,
+            image,
+            new: false,
+        });
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+/// Add a glyph as new variant of its character. Returns false for a duplicate.
+fn add_template(
+    templates: &mut Vec<GlyphTemplate>,
+    character: char,
+    image: Mat,
+    duplicate_threshold: f64,
+) -> Result<bool> {
+    for template in templates.iter().filter(|t| t.character == character) {
+        if similarity(&template.image, &image)? >= duplicate_threshold {
+            return Ok(false);
+        }
+    }
+    let name = (0..)
+        .filter_map(|variant| template_file_stem(character, variant))
+        .find(|name| templates.iter().all(|t| &t.name != name))
+        .context("Unsupported template character")?;
+    templates.push(GlyphTemplate {
+        character,
+        name,
+        image,
+        new: true,
+    });
+    Ok(true)
+}
+
+/// Extract the glyphs of all annotated stat regions of `corpus` as templates into `output`. Every
+/// region is preprocessed with its chain of `preprocessing`, like the analyzer does before the
+/// OCR. The templates in `output` are kept and count for the duplicate check. Ambiguous templates
+/// are logged as warnings and part of the report.
+pub fn extract_digit_templates(
+    corpus: &Corpus,
+    output: &Path,
+    config: &ExtractionConfig,
+    preprocessing: &PreprocessConfig,
+) -> Result<ExtractionReport> {
+    preprocessing.validate()?;
+    let mut templates = load_templates(output)?;
+    let mut report = ExtractionReport::default();
+
+    for sample in &corpus.samples {
+        let (hud, layout) = corpus
+            .load_hud(sample)
+            .with_context(|| format!("Failed to load sample {}", sample.file.display()))?;
+        let (width, height) = (hud.cols() as u32, hud.rows() as u32);
+
+        for (name, expected) in &sample.values {
+            let Some(index) = Corpus::stat_index(name) else {
+                continue;
+            };
+            let expected: Vec<char> = expected.chars().filter(|c| !c.is_whitespace()).collect();
+            if expected.iter().any(|&c| template_file_stem(c, 0).is_none()) {
+                log::warn!(
+                    "{} {}: no templates for the characters of '{}', skipped",
+                    sample.file.display(),
+                    name,
+                    String::from_iter(&expected)
+                );
+                report.skipped_regions += 1;
+                continue;
+            }
+
+            // Clipped to the image like the regions of the analyzer
+            let rect = layout.stat_regions[index];
+            let x = rect.x.min(width.saturating_sub(1));
+            let y = rect.y.min(height.saturating_sub(1));
+            let region = Rect::new(
+                x as i32,
+                y as i32,
+                rect.width.min(width - x) as i32,
+                rect.height.min(height - y) as i32,
+            );
+            let img = preprocessing::run(
+                preprocessing.chain(index),
+                &Mat::roi(&hud, region)?.try_clone()?,
+                None,
+            )?;
+            let gray =
+                TemplateMatchingOcrEngine::rgb_to_gray_mat(&img, 0, 0, img.width(), img.height())?;
+            let glyphs = segment_glyphs(&gray, config.min_glyph_pixels)?;
+            if glyphs.len() != expected.len() {
+                log::warn!(
+                    "{} {}: {} glyphs for '{}', skipped",
+                    sample.file.display(),
+                    name,
+                    glyphs.len(),
+                    String::from_iter(&expected)
+                );
+                report.skipped_regions += 1;
+                continue;
+            }
+
+            for (glyph, character) in glyphs.into_iter().zip(expected) {
+                let image = Mat::roi(&gray, glyph)?.try_clone()?;
+                if !add_template(&mut templates, character, image, config.duplicate_threshold)? {
+                    report.duplicates += 1;
+                }
+            }
+        }
+    }
+
+    std::fs::create_dir_all(output)
+        .with_context(|| format!("Failed to create template directory {}", output.display()))?;
+    for template in templates.iter().filter(|t| t.new) {
+        let path = output.join(format!("{}.png", template.name));
+        if !imgcodecs::imwrite(&path.to_string_lossy(), &template.image, &Vector::new())? {
+            anyhow::bail!("Failed to write template {}", path.display());
+        }
+        report.written.push(template.name.clone());
+    }
+
+    for (index, first) in templates.iter().enumerate() {
+        for second in &templates[index + 1..] {
+            if first.character == second.character {
+                continue;
+            }
+            let score = similarity(&first.image, &second.image)?;
+            if score >= config.ambiguity_threshold {
+                log::warn!(
+                    "Templates {} and {} are ambiguous, correlation {:.2}",
+                    first.name,
+                    second.name,
+                    score
+                );
+                report.ambiguities.push(Ambiguity {
+                    first: first.name.clone(),
+                    second: second.name.clone(),
+                    score,
+                });
+            }
+        }
+    }
+    Ok(report)
+}
@@ -1,11 +1,22 @@
 use crate::{
-    image_analyzer::{AnalysisResult, ImageAnalyzer, OCRModel},
+    alerts::{Alert, AlertEngine},
+    build_order::{BuildOrderGuide, BuildOrderProgress},
+    config::AppConfig,
+    frame_recorder::FrameRecorder,
+    game_clock::GameClock,
+    game_state_tracker::{GameStateTracker, TrackedGameState},
+    hud_layout::{HudLayout, ResolvedHudLayout},
+    image_analyzer::{AnalysisResult, ImageAnalyzer, ImageAnalyzerInner},
+    match_report::{MatchReportBuilder, ReportConfig},
+    match_stats::{MatchStats, MatchStatsTracker},
     pixelbuf_wrapper::{PixbufWrapper, PixelBufWrapperWithDroppedFramesTS},
+    session::{Screen, SessionConfig, SessionDetector, SessionEvent},
+    worker_advisor::{WorkerAdvice, WorkerAdvisor},
 };
 use anyhow::{Result, anyhow};
-use aoe4_overlay::consts::{AREA_HEIGHT, AREA_WIDTH};
 use log::{debug, error, info};
-use opencv::core::{Mat, MatTraitConst, Rect};
+use opencv::core::Mat;
+use tokio::sync::watch;
 use crate::overlay_window_gtk::GuiCommand;
 
 /// Frame data with original image and analysis results
@@ -13,19 +24,142 @@ use crate::overlay_window_gtk::GuiCommand;
 pub struct ProcessedFrame {
     pub original: PixbufWrapper,
     pub analysis: AnalysisResult,
+    /// Game state combined with the previous frames
+    pub tracked: TrackedGameState,
+    /// Alerts of the rules that currently fire, highest priority first
+    pub alerts: Vec<Alert>,
+    /// Game time of the running match, from the match timer
+    pub game_time_ms: Option<u64>,
+    /// Statistics of the running match
+    pub match_stats: Option<MatchStats>,
+    /// Biggest deviation of the workers from the target distribution
+    pub worker_advice: Option<WorkerAdvice>,
+    /// Current and next step of the loaded build order
+    pub build_order: Option<BuildOrderProgress>,
+    /// HUD panel in frame pixels
+    pub hud_area: image::math::Rect,
 }
 
 /// Frame processor that runs in a separate task
 pub struct FrameProcessor {
     analyzer: ImageAnalyzer,
+    tracker: GameStateTracker,
+    alert_engine: AlertEngine,
+    session: SessionDetector,
+    session_config: SessionConfig,
+    match_stats: MatchStatsTracker,
+    worker_advisor: WorkerAdvisor,
+    build_order: BuildOrderGuide,
+    report_builder: MatchReportBuilder,
+    report_config: ReportConfig,
+    config: watch::Receiver<AppConfig>,
+    /// Configured layout. If not set, a builtin profile is picked by frame size.
+    layout: Option<HudLayout>,
+    /// Overrides the UI scale of the layout
+    ui_scale: Option<f32>,
+    /// Writes the analyzed frames to disk
+    recorder: Option<FrameRecorder>,
 }
 
 unsafe impl Send for FrameProcessor {}
 
 impl FrameProcessor {
-    pub fn new() -> Result<Self> {
-        let analyzer = ImageAnalyzer::new(OCRModel::TemplateMatching)?;
-        Ok(Self { analyzer })
+    pub fn new(
+        mut config: watch::Receiver<AppConfig>,
+        recorder: Option<FrameRecorder>,
+    ) -> Result<Self> {
+        let current = config.borrow_and_update().clone();
+        let analyzer = ImageAnalyzer::new(&current.analysis)?;
+        Ok(Self {
+            analyzer,
+            tracker: GameStateTracker::new(current.tracking.clone()),
+            alert_engine: AlertEngine::new(&current.alerts),
+            session: SessionDetector::new(&current.session),
+            session_config: current.session.clone(),
+            match_stats: MatchStatsTracker::new(current.stats.clone()),
+            worker_advisor: WorkerAdvisor::new(&current.workers),
+            build_order: BuildOrderGuide::new(
+                current.build_order.load_build_order()?,
+                current.build_order.confirm_frames,
+            ),
+            report_builder: MatchReportBuilder::new(&current.report),
+            report_config: current.report.clone(),
+            config,
+            layout: current.layout.load_layout()?,
+            ui_scale: current.layout.ui_scale,
+            recorder,
+        })
+    }
+
+    /// Apply a changed configuration. Errors are logged and the previous settings are kept.
+    fn apply_config(
+        config: &AppConfig,
+        analyzer: &mut ImageAnalyzerInner,
+        layout: &mut Option<HudLayout>,
+        ui_scale: &mut Option<f32>,
+    ) {
+        if analyzer.config().ocr_engine != config.analysis.ocr_engine {
+            info!("Switching OCR engine to {:?}", config.analysis.ocr_engine);
+            match ImageAnalyzerInner::with_config(&config.analysis) {
+                Ok(new_analyzer) => *analyzer = new_analyzer,
+                Err(e) => error!("Failed to switch OCR engine: {}", e),
+            }
+        } else {
+            analyzer.set_config(&config.analysis);
+        }
+        analyzer.set_keep_preprocess_stages(config.overlay.show_debug_window);
+
+        match config.layout.load_layout() {
+            Ok(new_layout) => *layout = new_layout,
+            Err(e) => error!("Keeping previous HUD layout: {:#}", e),
+        }
+        *ui_scale = config.layout.ui_scale;
+    }
+
+    /// Resolve the HUD layout for the given frame size
+    fn resolve_layout(
+        layout: Option<&HudLayout>,
+        ui_scale: Option<f32>,
+        width: u32,
+        height: u32,
+    ) -> ResolvedHudLayout {
+        let mut layout = layout
+            .cloned()
+            .unwrap_or_else(|| HudLayout::for_frame_size(width, height));
+        if let Some(ui_scale) = ui_scale {
+            layout.ui_scale = ui_scale;
+        }
+        info!(
+            "Using HUD layout '{}' (UI scale {}) for {}x{} frames",
+            layout.name, layout.ui_scale, width, height
+        );
+        layout.resolve(width, height)
+    }
+
+    /// Write the report of a finished match. Errors are logged.
+    fn write_report(
+        config: &ReportConfig,
+        builder: &mut MatchReportBuilder,
+        started_ms: Option<u64>,
+        stats: MatchStats,
+    ) {
+        let started = started_ms
+            .and_then(|ms| glib::DateTime::from_unix_local((ms / 1000) as i64).ok())
+            .and_then(|time| time.format("%Y-%m-%d %H:%M:%S").ok())
+            .map(|time| time.to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let report = builder.finish(started, stats);
+        if !config.enabled {
+            return;
+        }
+        let dir = config
+            .dir
+            .clone()
+            .unwrap_or_else(|| crate::utils::data_dir().join("reports"));
+        match report.save(&dir, config.format) {
+            Ok(path) => info!("Match report written to {}", path.display()),
+            Err(e) => error!("Failed to write match report: {:#}", e),
+        }
     }
 
     /// Start processing frames from input channel and send results to output channel
@@ -36,12 +170,34 @@ impl FrameProcessor {
         processed_tx: tokio::sync::mpsc::Sender<GuiCommand>,
     ) -> Result<()> {
         info!("Frame processor started");
-        let mut analyzer = self.analyzer.into_inner().ok_or_else(|| anyhow!(""))?;
+        let Self {
+            analyzer,
+            mut tracker,
+            mut alert_engine,
+            mut session,
+            mut session_config,
+            mut match_stats,
+            mut worker_advisor,
+            mut build_order,
+            mut report_builder,
+            mut report_config,
+            mut config,
+            layout: mut layout_override,
+            mut ui_scale,
+            mut recorder,
+        } = self;
+        let mut analyzer = analyzer.into_inner().ok_or_else(|| anyhow!(""))?;
+        analyzer.set_keep_preprocess_stages(config.borrow().overlay.show_debug_window);
 
         let mut frame_count = 0u64;
         let mut processed_count = 0u64;
         let mut dropped_count = 0u32;
         let mut frame = PixbufWrapper::default();
+        let mut resolved_layout: Option<ResolvedHudLayout> = None;
+        let mut layout_frame_size = (0, 0);
+        // Capture time of the first frame of the running match
+        let mut match_started_ms: Option<u64> = None;
+        let mut game_clock = GameClock::default();
 
         while let Ok(has_data) = frame_rx.recv() {
             if !has_data {
@@ -61,6 +217,25 @@ impl FrameProcessor {
 
             dropped_count += dropped_frames;
 
+            if config.has_changed().unwrap_or(false) {
+                let current = config.borrow_and_update().clone();
+                Self::apply_config(&current, &mut analyzer, &mut layout_override, &mut ui_scale);
+                tracker.set_config(&current.tracking);
+                alert_engine.set_config(&current.alerts);
+                session.set_config(&current.session);
+                session_config = current.session.clone();
+                match_stats.set_config(&current.stats);
+                worker_advisor.set_config(&current.workers);
+                build_order.set_confirm_frames(current.build_order.confirm_frames);
+                match current.build_order.load_build_order() {
+                    Ok(new_build_order) => build_order.set_build_order(new_build_order),
+                    Err(e) => error!("Keeping previous build order: {:#}", e),
+                }
+                report_builder.set_config(&current.report);
+                report_config = current.report.clone();
+                resolved_layout = None;
+            }
+
             let cv_type = opencv::core::CV_MAKETYPE(8, 4);
             let r = unsafe {
                 Mat::new_nd_with_data_unsafe(
@@ -78,28 +253,127 @@ impl FrameProcessor {
                 }
             };
 
-            // Crop to the area of interest if needed
-            let roi = Rect::new(0, frame.height - AREA_HEIGHT, AREA_WIDTH, AREA_HEIGHT);
-            let cv_mat = Mat::roi(&cv_mat, roi).unwrap().try_clone()?;
+            // Resolve the layout again whenever the frame size changes
+            let frame_size = (frame.width as u32, frame.height as u32);
+            if resolved_layout.is_none() || layout_frame_size != frame_size {
+                resolved_layout = Some(Self::resolve_layout(
+                    layout_override.as_ref(),
+                    ui_scale,
+                    frame_size.0,
+                    frame_size.1,
+                ));
+                layout_frame_size = frame_size;
+            }
+            let layout = resolved_layout.as_ref().unwrap();
+
+            // Crop to the HUD panel and normalize its scale
+            let hud_mat = ImageAnalyzerInner::extract_hud_area(&cv_mat, layout)?;
 
-            match analyzer.analyze(cv_mat) {
-                Ok(analysis) => {
+            match analyzer.analyze(hud_mat, layout) {
+                Ok(mut analysis) => {
                     processed_count += 1;
 
+                    // Icons, match timer and age outside of the HUD panel
+                    if let Err(e) = analyzer.analyze_frame(&cv_mat, layout, &mut analysis) {
+                        error!("Failed to analyze the frame outside of the HUD: {}", e);
+                    }
+
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.record(&frame, layout.area, Some(&analysis));
+                    }
+
+                    let screen = Screen::classify(
+                        analysis.hud_anchors,
+                        || ImageAnalyzerInner::frame_brightness(&cv_mat).unwrap_or(255.0),
+                        &session_config,
+                    );
+                    match session.update(screen, frame.timestamp_ms) {
+                        Some(SessionEvent::MatchStarted) => {
+                            info!("Match started");
+                            tracker.reset();
+                            alert_engine.reset();
+                            build_order.reset();
+                            match_stats.start(frame.timestamp_ms);
+                            game_clock.reset();
+                            match_started_ms = Some(frame.timestamp_ms);
+                        }
+                        Some(SessionEvent::MatchEnded) => {
+                            if let Some(finished) = match_stats.finish() {
+                                info!("Match ended: {}", finished);
+                                Self::write_report(
+                                    &report_config,
+                                    &mut report_builder,
+                                    match_started_ms.take(),
+                                    finished.clone(),
+                                );
+                                // Unlike the frames the summary must not be dropped, wait for
+                                // room in the channel
+                                if processed_tx
+                                    .blocking_send(GuiCommand::MatchFinished(finished))
+                                    .is_err()
+                                {
+                                    debug!("GUI closed, match summary not shown");
+                                }
+                            }
+                        }
+                        None => {}
+                    }
+
+                    // Alerts, statistics and the build order only run during a match
+                    let tracked = tracker.update(&analysis.game_state, frame.timestamp_ms);
+                    let alerts = if session.in_match() {
+                        let icons = analysis
+                            .icons
+                            .iter()
+                            .map(|(name, icon)| (name.clone(), icon.found))
+                            .collect();
+                        alert_engine.update(&tracked.state, &icons, frame.timestamp_ms)
+                    } else {
+                        Vec::new()
+                    };
+                    let game_time_ms = session
+                        .in_match()
+                        .then(|| game_clock.update(analysis.game_time_ms, frame.timestamp_ms))
+                        .flatten();
+                    match_stats.update(&tracked.state, game_time_ms, frame.timestamp_ms);
+                    if let Some(stats) = match_stats.current() {
+                        // The timer is not affected by pauses, the HUD time is the fallback
+                        let time_ms = game_time_ms.unwrap_or(stats.duration_ms);
+                        report_builder.update(time_ms, &tracked.state, &alerts);
+                    }
+                    let worker_advice = match_stats
+                        .current_phase()
+                        .and_then(|phase| worker_advisor.advise(&tracked.state, phase));
                     let processed_frame = ProcessedFrame {
                         original: frame.clone(),
                         analysis,
+                        tracked,
+                        alerts,
+                        game_time_ms,
+                        match_stats: match_stats.current().cloned(),
+                        worker_advice,
+                        build_order: session
+                            .in_match()
+                            .then(|| build_order.update(&tracked.state))
+                            .flatten(),
+                        hud_area: layout.area,
                     };
 
                     if processed_count % 100 == 0 {
                         info!(
-                            "Processed {} frames (received: {}, dropped: {}). Villager/Convert/OCR time: {}/{}/{}",
+                            "Processed {} frames (received: {}, dropped: {}). Villager/Convert/OCR time: {}/{}/{}, OCR cache hits: {}",
                             processed_count,
                             frame_count,
                             dropped_count,
                             processed_frame.analysis.detect_villager_time.as_millis(),
                             processed_frame.analysis.convert_color_time.as_millis(),
-                            processed_frame.analysis.ocr_time.as_millis()
+                            processed_frame.analysis.ocr_time.as_millis(),
+                            analyzer.take_ocr_cache_stats()
+                        );
+                        info!(
+                            "Screen: {:?}, game state: {}",
+                            session.screen(),
+                            processed_frame.tracked.state
                         );
                     }
                     // Try to send, drop if channel is full
@@ -118,6 +392,11 @@ impl FrameProcessor {
             }
         }
 
+        session.finish();
+        if let Some(finished) = match_stats.finish() {
+            info!("Match ended: {}", finished);
+            Self::write_report(&report_config, &mut report_builder, match_started_ms, finished);
+        }
         info!(
             "Frame processor stopped. Processed {} frames (received: {}, dropped: {})",
             processed_count, frame_count, dropped_count
@@ -0,0 +1,236 @@
+// Recording of captured frames and their analysis results to disk
+
+use crate::{
+    image_analyzer::AnalysisResult,
+    pixelbuf_wrapper::{PixbufWrapper, PixelFormat},
+};
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use opencv::{
+    core::{AlgorithmHint, Mat, Rect, Vector},
+    imgcodecs,
+    imgproc,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{SyncSender, TrySendError, sync_channel},
+    thread,
+};
+
+/// Name of the sidecar file in the recording directory, one [`RecordedFrame`] per line
+pub const SIDECAR_FILE: &str = "frames.jsonl";
+
+/// Frames waiting to be written before new ones are dropped
+const QUEUE_SIZE: usize = 8;
+
+/// Area of a frame in frame pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FrameArea {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// One line of the sidecar file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// Image file, relative to the recording directory
+    pub file: String,
+    /// Capture time in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// Size, stride and pixel format of the frame as received from PipeWire
+    pub width: i32,
+    pub height: i32,
+    pub stride: i32,
+    pub pixel_format: PixelFormat,
+    /// Part of the frame stored in the image file, `None` for the full frame
+    pub crop: Option<FrameArea>,
+    pub analysis: Option<AnalysisResult>,
+}
+
+impl RecordedFrame {
+    /// Read all entries of the sidecar file of a recording directory
+    pub fn load_sidecar(dir: &Path) -> Result<Vec<RecordedFrame>> {
+        let path = dir.join(SIDECAR_FILE);
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Invalid entry in {}:{}", path.display(), index + 1))
+            })
+            .collect()
+    }
+}
+
+struct RecordJob {
+    frame: PixbufWrapper,
+    entry: RecordedFrame,
+}
+
+/// Writes frames as PNG files plus a sidecar JSONL into a directory. Encoding happens in a
+/// background thread, frames are dropped if it falls behind.
+pub struct FrameRecorder {
+    sender: Option<SyncSender<RecordJob>>,
+    join_handle: Option<thread::JoinHandle<()>>,
+    /// Only store the HUD panel instead of the full frame
+    hud_only: bool,
+    frame_index: u64,
+    dropped: u64,
+}
+
+impl FrameRecorder {
+    pub fn new(dir: PathBuf, hud_only: bool) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create recording directory {}", dir.display()))?;
+        let sidecar_path = dir.join(SIDECAR_FILE);
+        if sidecar_path.exists() {
+            anyhow::bail!("{} already contains a recording", dir.display());
+        }
+        let mut sidecar = BufWriter::new(
+            File::create(&sidecar_path)
+                .with_context(|| format!("Failed to create {}", sidecar_path.display()))?,
+        );
+        info!("Recording frames to {}", dir.display());
+
+        let (sender, receiver) = sync_channel::<RecordJob>(QUEUE_SIZE);
+        let join_handle = thread::spawn(move || {
+            while let Ok(job) = receiver.recv() {
+                if let Err(e) = Self::write_image(&dir.join(&job.entry.file), &job) {
+                    error!("Failed to record frame {}: {:#}", job.entry.file, e);
+                    continue;
+                }
+                let line = match serde_json::to_string(&job.entry) {
+                    Ok(line) => line,
+                    Err(e) => {
+                        error!("Failed to serialize frame metadata: {}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = writeln!(sidecar, "{}", line).and_then(|_| sidecar.flush()) {
+                    error!("Failed to write {}: {}", SIDECAR_FILE, e);
+                }
+            }
+        });
+
+        Ok(Self {
+            sender: Some(sender),
+            join_handle: Some(join_handle),
+            hud_only,
+            frame_index: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Queue a frame for writing
+    ///
+    /// # Arguments
+    ///
+    /// * `frame`: Frame as received from PipeWire
+    /// * `hud_area`: HUD panel in frame pixels, stored instead of the full frame if `hud_only` is set
+    /// * `analysis`: Analysis result of the frame
+    pub fn record(
+        &mut self,
+        frame: &PixbufWrapper,
+        hud_area: image::math::Rect,
+        analysis: Option<&AnalysisResult>,
+    ) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        self.frame_index += 1;
+        let crop = self.hud_only.then_some(FrameArea {
+            x: hud_area.x,
+            y: hud_area.y,
+            width: hud_area.width,
+            height: hud_area.height,
+        });
+        let job = RecordJob {
+            frame: frame.clone(),
+            entry: RecordedFrame {
+                file: format!("frame_{:06}.png", self.frame_index),
+                timestamp_ms: frame.timestamp_ms,
+                width: frame.width,
+                height: frame.height,
+                stride: frame.stride,
+                pixel_format: frame.format,
+                crop,
+                analysis: analysis.cloned(),
+            },
+        };
+
+        match sender.try_send(job) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                if self.dropped % 10 == 1 {
+                    warn!("Recording falls behind, dropped {} frames", self.dropped);
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                error!("Recording thread stopped, no further frames are recorded");
+                self.sender = None;
+            }
+        }
+    }
+
+    fn write_image(path: &Path, job: &RecordJob) -> Result<()> {
+        let frame = &job.frame;
+        let cv_type = opencv::core::CV_MAKETYPE(8, 4);
+        // Safety: the Mat only borrows the buffer of `frame`, which outlives it
+        let mat = unsafe {
+            Mat::new_rows_cols_with_data_unsafe(
+                frame.height,
+                frame.width,
+                cv_type,
+                frame.bgr_buffer.as_ptr() as *mut _,
+                frame.stride as usize,
+            )?
+        };
+        let mat = match job.entry.crop {
+            Some(area) => Mat::roi(
+                &mat,
+                Rect::new(area.x as i32, area.y as i32, area.width as i32, area.height as i32),
+            )?
+            .try_clone()?,
+            None => mat.try_clone()?,
+        };
+
+        // The padding byte of BGRx is not an alpha channel, store without it
+        let mut bgr = Mat::default();
+        imgproc::cvt_color(
+            &mat,
+            &mut bgr,
+            imgproc::COLOR_BGRA2BGR,
+            0,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        if !imgcodecs::imwrite(&path.to_string_lossy(), &bgr, &Vector::new())? {
+            anyhow::bail!("Failed to write {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FrameRecorder {
+    fn drop(&mut self) {
+        // Closing the channel ends the thread after the queued frames are written
+        self.sender = None;
+        if let Some(join_handle) = self.join_handle.take() {
+            let _ = join_handle.join();
+        }
+        info!(
+            "Recorded {} frames ({} dropped)",
+            self.frame_index - self.dropped,
+            self.dropped
+        );
+    }
+}
@@ -0,0 +1,96 @@
+// Game time read from the match timer at the top of the screen
+
+/// Readings may run ahead of the wall time by this much, for the one second resolution of the
+/// timer and the capture jitter
+const TOLERANCE_MS: u64 = 2000;
+
+/// Unreadable timers are extrapolated with the wall time for at most this long, as a menu that
+/// hides the timer may also pause the game
+const MAX_EXTRAPOLATION_MS: u64 = 10_000;
+
+/// Parse the match timer, e.g. "12:34" or "1:02:03". Digits without separator are accepted as
+/// well, the last two being seconds. This is the intended path for the template matching OCR:
+/// `src_images/digits` has no colon template, so the colon is not part of the text. A `colon.png`
+/// cut with the digit extraction is used once it exists.
+pub fn parse_clock(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let parts: Vec<&str> = if text.contains(':') {
+        text.split(':').collect()
+    } else if (3..=6).contains(&text.len()) && text.is_char_boundary(text.len() - 2) {
+        let (rest, seconds) = text.split_at(text.len() - 2);
+        if rest.len() > 2 {
+            let (hours, minutes) = rest.split_at(rest.len() - 2);
+            vec![hours, minutes, seconds]
+        } else {
+            vec![rest, seconds]
+        }
+    } else {
+        return None;
+    };
+    if parts.len() > 3 || parts.iter().any(|part| part.is_empty()) {
+        return None;
+    }
+
+    let mut seconds = 0u64;
+    for (index, part) in parts.iter().enumerate() {
+        let value: u64 = part.parse().ok()?;
+        // Minutes and seconds after the first part have two digits and are below 60
+        if index > 0 && (part.len() != 2 || value >= 60) {
+            return None;
+        }
+        seconds = seconds * 60 + value;
+    }
+    Some(seconds * 1000)
+}
+
+/// Filters the timer readings of consecutive frames. A reading is accepted if it is not before
+/// the last accepted one and not further ahead than the wall time that passed. Other readings
+/// are misreads, unless the next reading confirms them, e.g. after a replay seek. The first
+/// reading of a match needs the same confirmation, so a misread can not become the start time.
+#[derive(Debug, Clone, Default)]
+pub struct GameClock {
+    /// Last accepted game time and the wall time of its frame
+    last: Option<(u64, u64)>,
+    /// A rejected reading, accepted if the next one agrees with it
+    candidate: Option<(u64, u64)>,
+}
+
+impl GameClock {
+    /// Forget the game time, e.g. for a new match
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Last accepted game time
+    pub fn game_time_ms(&self) -> Option<u64> {
+        self.last.map(|(game_ms, _)| game_ms)
+    }
+
+    fn follows(previous: Option<(u64, u64)>, game_ms: u64, timestamp_ms: u64) -> bool {
+        previous.is_some_and(|(previous_game_ms, previous_timestamp_ms)| {
+            let elapsed_ms = timestamp_ms.saturating_sub(previous_timestamp_ms);
+            game_ms >= previous_game_ms && game_ms <= previous_game_ms + elapsed_ms + TOLERANCE_MS
+        })
+    }
+
+    /// Add the reading of a frame captured at `timestamp_ms`, `None` if the timer was not
+    /// readable. Returns the game time, `None` until a reading was confirmed. While the timer is
+    /// unreadable, the last accepted reading is advanced by the wall time, up to
+    /// [`MAX_EXTRAPOLATION_MS`].
+    pub fn update(&mut self, reading: Option<u64>, timestamp_ms: u64) -> Option<u64> {
+        let Some(game_ms) = reading else {
+            return self.last.map(|(game_ms, last_timestamp_ms)| {
+                game_ms + timestamp_ms.saturating_sub(last_timestamp_ms).min(MAX_EXTRAPOLATION_MS)
+            });
+        };
+        if Self::follows(self.last, game_ms, timestamp_ms)
+            || Self::follows(self.candidate, game_ms, timestamp_ms)
+        {
+            self.last = Some((game_ms, timestamp_ms));
+            self.candidate = None;
+        } else {
+            self.candidate = Some((game_ms, timestamp_ms));
+        }
+        self.game_time_ms()
+    }
+}
@@ -0,0 +1,225 @@
+// Typed game state parsed from the recognized HUD texts
+
+use crate::consts::{AOE4_STATS_POS, TextType};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A value read from the HUD. `Unknown` if the region was empty or could not be parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reading<T> {
+    #[default]
+    Unknown,
+    Known(T),
+}
+
+impl<T> Reading<T> {
+    pub fn known(self) -> Option<T> {
+        match self {
+            Reading::Known(value) => Some(value),
+            Reading::Unknown => None,
+        }
+    }
+
+    pub fn is_known(&self) -> bool {
+        matches!(self, Reading::Known(_))
+    }
+}
+
+impl<T> From<Option<T>> for Reading<T> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Reading::Unknown, Reading::Known)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Reading<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reading::Known(value) => value.fmt(f),
+            Reading::Unknown => f.write_str("?"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Resource {
+    Food,
+    Wood,
+    Gold,
+    Stone,
+}
+
+impl Resource {
+    pub const ALL: [Resource; 4] = [Resource::Food, Resource::Wood, Resource::Gold, Resource::Stone];
+}
+
+/// One value per resource
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PerResource<T> {
+    pub food: T,
+    pub wood: T,
+    pub gold: T,
+    pub stone: T,
+}
+
+impl<T> PerResource<T> {
+    pub fn get(&self, resource: Resource) -> &T {
+        match resource {
+            Resource::Food => &self.food,
+            Resource::Wood => &self.wood,
+            Resource::Gold => &self.gold,
+            Resource::Stone => &self.stone,
+        }
+    }
+
+    pub fn get_mut(&mut self, resource: Resource) -> &mut T {
+        match resource {
+            Resource::Food => &mut self.food,
+            Resource::Wood => &mut self.wood,
+            Resource::Gold => &mut self.gold,
+            Resource::Stone => &mut self.stone,
+        }
+    }
+}
+
+/// Age of the player, detected from the age emblem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Age {
+    Dark,
+    Feudal,
+    Castle,
+    Imperial,
+}
+
+impl Age {
+    pub const ALL: [Age; 4] = [Age::Dark, Age::Feudal, Age::Castle, Age::Imperial];
+
+    /// 1 for the Dark Age up to 4 for the Imperial Age, as in build orders
+    pub fn number(self) -> u32 {
+        self as u32 + 1
+    }
+
+    /// Lowercase name, e.g. "feudal"
+    pub fn name(self) -> &'static str {
+        match self {
+            Age::Dark => "dark",
+            Age::Feudal => "feudal",
+            Age::Castle => "castle",
+            Age::Imperial => "imperial",
+        }
+    }
+
+    pub fn next(self) -> Option<Age> {
+        Self::ALL.get(self.number() as usize).copied()
+    }
+}
+
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Population {
+    pub current: u32,
+    pub cap: u32,
+}
+
+impl fmt::Display for Population {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.current, self.cap)
+    }
+}
+
+/// Everything read from the HUD in one frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameState {
+    pub population: Reading<Population>,
+    pub resources: PerResource<Reading<u32>>,
+    pub idle_villagers: Reading<u32>,
+    pub workers: PerResource<Reading<u32>>,
+    /// The villager icon is shown, i.e. a villager is queued in the town center
+    pub villager_in_production: bool,
+    #[serde(default)]
+    pub age: Reading<Age>,
+    /// The age emblem shows the advancement to the next age
+    #[serde(default)]
+    pub aging_up: bool,
+}
+
+impl GameState {
+    /// Parse the texts recognized in the regions of `AOE4_STATS_POS`
+    pub fn from_texts(
+        texts: &[fixedstr::str8; AOE4_STATS_POS.len()],
+        villager_in_production: bool,
+    ) -> Self {
+        let mut state = GameState {
+            villager_in_production,
+            ..Default::default()
+        };
+        for (stat, text) in AOE4_STATS_POS.iter().zip(texts) {
+            let text = text.as_str().trim();
+            match stat.text_type {
+                TextType::Population => state.population = Self::parse_population(text).into(),
+                TextType::Idle => state.idle_villagers = Self::parse_count(text).into(),
+                TextType::Resource(resource) => {
+                    *state.resources.get_mut(resource) = Self::parse_count(text).into()
+                }
+                TextType::Workers(resource) => {
+                    *state.workers.get_mut(resource) = Self::parse_count(text).into()
+                }
+                TextType::Unassigned => {}
+            }
+        }
+        state
+    }
+
+    /// Sum of all workers and idle villagers
+    pub fn villagers(&self) -> Reading<u32> {
+        Resource::ALL
+            .iter()
+            .map(|resource| self.workers.get(*resource).known())
+            .chain([self.idle_villagers.known()])
+            .sum::<Option<u32>>()
+            .into()
+    }
+
+    fn parse_count(text: &str) -> Option<u32> {
+        text.parse().ok()
+    }
+
+    /// Parse "current/cap", the cap is never 0
+    fn parse_population(text: &str) -> Option<Population> {
+        let (current, cap) = text.split_once('/')?;
+        let population = Population {
+            current: Self::parse_count(current)?,
+            cap: Self::parse_count(cap)?,
+        };
+        (population.cap > 0).then_some(population)
+    }
+}
+
+impl fmt::Display for GameState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pop {}, food {} ({}), wood {} ({}), gold {} ({}), stone {} ({}), idle {}, villager {}, \
+             age {}{}",
+            self.population,
+            self.resources.food,
+            self.workers.food,
+            self.resources.wood,
+            self.workers.wood,
+            self.resources.gold,
+            self.workers.gold,
+            self.resources.stone,
+            self.workers.stone,
+            self.idle_villagers,
+            if self.villager_in_production { "queued" } else { "none" },
+            self.age,
+            if self.aging_up { " (aging up)" } else { "" }
+        )
+    }
+}
@@ -0,0 +1,307 @@
+// Temporal fusion and plausibility filtering of the per-frame game state
+
+use crate::{
+    consts::TextType,
+    game_state::{Age, GameState, PerResource, Population, Reading, Resource},
+};
+use serde::{Deserialize, Serialize};
+
+/// Limits for accepting a changed reading
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackerConfig {
+    /// How long the last good value is kept while a field is unreadable, in milliseconds
+    pub hold_ms: u64,
+    /// Consecutive frames an implausible value must be read before it is accepted anyway, e.g.
+    /// after a new match started. Also applies to the villager icon and the age.
+    pub reacquire_frames: u32,
+    /// Highest possible population and population cap
+    pub max_population: u32,
+    /// Maximum population change per second
+    pub max_population_rate: f32,
+    /// Maximum population cap change at once, e.g. houses finished in the same frame
+    pub max_population_cap_change: u32,
+    /// Maximum change of a resource stock per second
+    pub max_resource_rate: f32,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            hold_ms: 2000,
+            reacquire_frames: 3,
+            max_population: 300,
+            max_population_rate: 4.0,
+            max_population_cap_change: 50,
+            max_resource_rate: 2000.0,
+        }
+    }
+}
+
+/// Confidence per field from 0 (unknown) to 1 (read consistently)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Confidence {
+    pub population: f32,
+    pub resources: PerResource<f32>,
+    pub idle_villagers: f32,
+    pub workers: PerResource<f32>,
+    pub villager_in_production: f32,
+    #[serde(default)]
+    pub age: f32,
+}
+
+impl Confidence {
+    /// Confidence of the field a HUD region is parsed into
+    pub fn for_text_type(&self, text_type: TextType) -> f32 {
+        match text_type {
+            TextType::Population => self.population,
+            TextType::Idle => self.idle_villagers,
+            TextType::Resource(resource) => *self.resources.get(resource),
+            TextType::Workers(resource) => *self.workers.get(resource),
+            TextType::Unassigned => 0.0,
+        }
+    }
+}
+
+/// Game state combined over several frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackedGameState {
+    pub state: GameState,
+    pub confidence: Confidence,
+}
+
+/// Confidence of a newly accepted value, rises towards 1 with every confirming frame
+const INITIAL_CONFIDENCE: f32 = 0.5;
+/// Confidence factor for every frame in which the value is held without confirmation
+const HOLD_DECAY: f32 = 0.7;
+
+#[derive(Debug, Clone)]
+struct FieldTracker<T> {
+    value: Option<T>,
+    confidence: f32,
+    /// Timestamp of the last reading that confirmed `value`
+    last_confirmed_ms: u64,
+    /// A rejected value and in how many consecutive frames it was read
+    candidate: Option<(T, u32)>,
+}
+
+impl<T> Default for FieldTracker<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            confidence: 0.0,
+            last_confirmed_ms: 0,
+            candidate: None,
+        }
+    }
+}
+
+impl<T: Copy + PartialEq> FieldTracker<T> {
+    /// Combine a new reading with the tracked value
+    ///
+    /// # Arguments
+    ///
+    /// * `plausible`: Whether a change from the first to the second value within the given
+    ///   seconds is possible
+    fn update(
+        &mut self,
+        reading: Reading<T>,
+        timestamp_ms: u64,
+        config: &TrackerConfig,
+        plausible: impl Fn(T, T, f32) -> bool,
+    ) {
+        let Reading::Known(new) = reading else {
+            self.candidate = None;
+            self.hold(timestamp_ms, config);
+            return;
+        };
+        let Some(current) = self.value else {
+            self.accept(new, timestamp_ms);
+            return;
+        };
+        if new == current {
+            self.candidate = None;
+            self.last_confirmed_ms = timestamp_ms;
+            self.confidence += (1.0 - self.confidence) * 0.5;
+            return;
+        }
+
+        let elapsed_s = timestamp_ms.saturating_sub(self.last_confirmed_ms) as f32 / 1000.0;
+        if plausible(current, new, elapsed_s) {
+            self.accept(new, timestamp_ms);
+            return;
+        }
+
+        let count = match self.candidate {
+            Some((candidate, count)) if candidate == new => count + 1,
+            _ => 1,
+        };
+        if count >= config.reacquire_frames {
+            self.accept(new, timestamp_ms);
+        } else {
+            self.candidate = Some((new, count));
+            self.hold(timestamp_ms, config);
+        }
+    }
+
+    fn accept(&mut self, value: T, timestamp_ms: u64) {
+        self.value = Some(value);
+        self.confidence = INITIAL_CONFIDENCE;
+        self.last_confirmed_ms = timestamp_ms;
+        self.candidate = None;
+    }
+
+    /// Keep the last good value until it is too old
+    fn hold(&mut self, timestamp_ms: u64, config: &TrackerConfig) {
+        if timestamp_ms.saturating_sub(self.last_confirmed_ms) > config.hold_ms {
+            self.value = None;
+            self.confidence = 0.0;
+        } else {
+            self.confidence *= HOLD_DECAY;
+        }
+    }
+
+    fn reading(&self) -> Reading<T> {
+        self.value.into()
+    }
+}
+
+/// Combines the game state of consecutive frames: implausible jumps are rejected, the last good
+/// value is kept through short dropouts and every field gets a confidence.
+#[derive(Debug, Clone, Default)]
+pub struct GameStateTracker {
+    config: TrackerConfig,
+    population: FieldTracker<Population>,
+    resources: PerResource<FieldTracker<u32>>,
+    idle_villagers: FieldTracker<u32>,
+    workers: PerResource<FieldTracker<u32>>,
+    villager_in_production: FieldTracker<bool>,
+    age: FieldTracker<Age>,
+    aging_up: FieldTracker<bool>,
+    last_timestamp_ms: Option<u64>,
+}
+
+impl GameStateTracker {
+    pub fn new(config: TrackerConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_config(&mut self, config: &TrackerConfig) {
+        self.config = config.clone();
+    }
+
+    /// Forget all tracked values
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config.clone());
+    }
+
+    /// Add the readings of a frame captured at `timestamp_ms` and return the combined state
+    pub fn update(&mut self, state: &GameState, timestamp_ms: u64) -> TrackedGameState {
+        if self.last_timestamp_ms.is_some_and(|last| timestamp_ms < last) {
+            // Time went backwards, e.g. a replay restarted
+            self.reset();
+        }
+        self.last_timestamp_ms = Some(timestamp_ms);
+        let config = &self.config;
+
+        let max_population = config.max_population;
+        let population = match state.population {
+            Reading::Known(p) if p.current > max_population || p.cap > max_population => {
+                Reading::Unknown
+            }
+            reading => reading,
+        };
+        self.population.update(population, timestamp_ms, config, |old, new, elapsed_s| {
+            old.current.abs_diff(new.current) as f32 <= config.max_population_rate * elapsed_s + 2.0
+                && old.cap.abs_diff(new.cap) <= config.max_population_cap_change
+        });
+
+        // Villager counts can never exceed the population
+        let current_population = self.population.value.map(|p| p.current);
+        let within_population = |reading: Reading<u32>| match (reading, current_population) {
+            (Reading::Known(count), Some(population)) if count > population => Reading::Unknown,
+            _ => reading,
+        };
+
+        self.idle_villagers.update(
+            within_population(state.idle_villagers),
+            timestamp_ms,
+            config,
+            |_, _, _| true,
+        );
+        for resource in Resource::ALL {
+            self.resources.get_mut(resource).update(
+                *state.resources.get(resource),
+                timestamp_ms,
+                config,
+                |old, new, elapsed_s| {
+                    old.abs_diff(new) as f32 <= config.max_resource_rate * elapsed_s + 100.0
+                },
+            );
+            self.workers.get_mut(resource).update(
+                within_population(*state.workers.get(resource)),
+                timestamp_ms,
+                config,
+                |_, _, _| true,
+            );
+        }
+        // A toggling icon must be seen in several frames
+        self.villager_in_production.update(
+            Reading::Known(state.villager_in_production),
+            timestamp_ms,
+            config,
+            |_, _, _| false,
+        );
+        // Ages only advance one at a time, the age-up emblem must be seen in several frames
+        self.age.update(state.age, timestamp_ms, config, |old, new, _| {
+            old.next() == Some(new)
+        });
+        self.aging_up.update(
+            Reading::Known(state.aging_up),
+            timestamp_ms,
+            config,
+            |_, _, _| false,
+        );
+
+        self.current()
+    }
+
+    /// The combined state without adding new readings
+    pub fn current(&self) -> TrackedGameState {
+        let per_resource = |trackers: &PerResource<FieldTracker<u32>>| PerResource {
+            food: trackers.food.reading(),
+            wood: trackers.wood.reading(),
+            gold: trackers.gold.reading(),
+            stone: trackers.stone.reading(),
+        };
+        let confidence = |trackers: &PerResource<FieldTracker<u32>>| PerResource {
+            food: trackers.food.confidence,
+            wood: trackers.wood.confidence,
+            gold: trackers.gold.confidence,
+            stone: trackers.stone.confidence,
+        };
+
+        TrackedGameState {
+            state: GameState {
+                population: self.population.reading(),
+                resources: per_resource(&self.resources),
+                idle_villagers: self.idle_villagers.reading(),
+                workers: per_resource(&self.workers),
+                villager_in_production: self.villager_in_production.value.unwrap_or(false),
+                age: self.age.reading(),
+                aging_up: self.aging_up.value.unwrap_or(false),
+            },
+            confidence: Confidence {
+                population: self.population.confidence,
+                resources: confidence(&self.resources),
+                idle_villagers: self.idle_villagers.confidence,
+                workers: confidence(&self.workers),
+                villager_in_production: self.villager_in_production.confidence,
+                age: self.age.confidence,
+            },
+        }
+    }
+}
@@ -0,0 +1,305 @@
+// HUD layout profiles for different resolutions and in-game UI scales
+
+use crate::consts::{
+    AOE4_STATS_POS, AREA_HEIGHT, AREA_WIDTH, AREA_Y_OFFSET, STAT_RECT, TEMPLATE_REFERENCE_HEIGHT,
+    VILLAGER_ICON_AREA,
+};
+use anyhow::{Context, Result};
+use image::math::Rect;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Layout profiles shipped with the application, see `layouts/`. Only measured profiles belong
+/// here, the layouts scale with the frame height and other resolutions resolve the closest one.
+const BUILTIN_PROFILES: [&str; 1] = [include_str!("../layouts/2560x1440.toml")];
+
+/// A rectangle in reference pixels of a [`HudLayout`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LayoutRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl LayoutRect {
+    fn scaled(&self, scale: f32) -> Rect {
+        Rect {
+            x: (self.x * scale).round().max(0.0) as u32,
+            y: (self.y * scale).round().max(0.0) as u32,
+            width: (self.width * scale).round().max(1.0) as u32,
+            height: (self.height * scale).round().max(1.0) as u32,
+        }
+    }
+
+    /// Frame pixels of a rectangle relative to the top center of the frame, clipped to the frame
+    pub fn top_centered(&self, scale: f32, frame_width: u32, frame_height: u32) -> Rect {
+        let (x, width) = clip_span(
+            frame_width as f32 / 2.0 + self.x * scale,
+            self.width * scale,
+            frame_width,
+        );
+        let (y, height) = clip_span(self.y * scale, self.height * scale, frame_height);
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// Start and length of the pixels `start..start + length` within `0..limit`, at least one pixel
+fn clip_span(start: f32, length: f32, limit: u32) -> (u32, u32) {
+    let start = start.round();
+    let end = start + length.round();
+    let start = (start.max(0.0) as u32).min(limit.saturating_sub(1));
+    let end = (end.max(0.0) as u32).min(limit);
+    (start, end.saturating_sub(start).max(1))
+}
+
+/// Top-left corner of a stat text region, relative to the top-left corner of the HUD panel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatRegion {
+    /// Must match one of the names in [`AOE4_STATS_POS`]
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Position of the bottom-left HUD panel and everything read from it.
+///
+/// All pixel values are measured at `reference_height` and `reference_ui_scale` and get scaled
+/// to the actual frame height and `ui_scale` when resolved. The HUD panel is anchored to the
+/// bottom-left corner of the frame, so ultrawide resolutions only need a different width.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HudLayout {
+    pub name: String,
+    /// Frame size the pixel values were measured at
+    pub reference_width: u32,
+    pub reference_height: u32,
+    /// In-game UI scale the pixel values were measured at (1.0 = 100%)
+    #[serde(default = "default_ui_scale")]
+    pub reference_ui_scale: f32,
+    /// In-game UI scale of the player (1.0 = 100%)
+    #[serde(default = "default_ui_scale")]
+    pub ui_scale: f32,
+    /// Size of the bottom-left HUD panel
+    pub area_width: f32,
+    pub area_height: f32,
+    /// Size of a single stat text region
+    pub stat_width: f32,
+    pub stat_height: f32,
+    /// Search area for the villager icon, relative to the HUD panel
+    pub villager_icon_area: LayoutRect,
+    /// Area of the match timer, relative to the top center of the frame
+    #[serde(default = "default_clock_area")]
+    pub clock_area: LayoutRect,
+    /// Search area for the age emblem, relative to the top center of the frame
+    #[serde(default = "default_age_area")]
+    pub age_area: LayoutRect,
+    pub stats: Vec<StatRegion>,
+}
+
+fn default_ui_scale() -> f32 {
+    1.0
+}
+
+fn default_clock_area() -> LayoutRect {
+    LayoutRect {
+        x: -60.0,
+        y: 4.0,
+        width: 120.0,
+        height: 34.0,
+    }
+}
+
+fn default_age_area() -> LayoutRect {
+    LayoutRect {
+        x: -100.0,
+        y: 0.0,
+        width: 200.0,
+        height: 120.0,
+    }
+}
+
+impl Default for HudLayout {
+    /// The original hard-coded values, measured at 2560x1440 with 100% UI scale
+    fn default() -> Self {
+        Self {
+            name: "2560x1440".to_string(),
+            reference_width: 2560,
+            reference_height: 1440,
+            reference_ui_scale: 1.0,
+            ui_scale: 1.0,
+            area_width: AREA_WIDTH as f32,
+            area_height: AREA_HEIGHT as f32,
+            stat_width: STAT_RECT.width as f32,
+            stat_height: STAT_RECT.height as f32,
+            villager_icon_area: LayoutRect {
+                x: VILLAGER_ICON_AREA.x as f32,
+                y: VILLAGER_ICON_AREA.y as f32,
+                width: VILLAGER_ICON_AREA.width as f32,
+                height: VILLAGER_ICON_AREA.height as f32,
+            },
+            clock_area: default_clock_area(),
+            age_area: default_age_area(),
+            stats: AOE4_STATS_POS
+                .iter()
+                .map(|stat| StatRegion {
+                    name: stat.name.to_string(),
+                    x: stat.x,
+                    y: stat.y - AREA_Y_OFFSET,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A [`HudLayout`] resolved for a specific frame size.
+///
+/// The HUD panel is cropped from the frame at `area` and then resized to `normalized_size`, so
+/// that the OCR templates and the villager icon always see the scale they were cut at (see
+/// [`TEMPLATE_REFERENCE_HEIGHT`]). All other regions are relative to that normalized HUD panel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedHudLayout {
+    /// HUD panel in frame pixels
+    pub area: Rect,
+    /// Frame pixels per normalized pixel
+    pub scale: f32,
+    /// Size of the HUD panel after normalization
+    pub normalized_size: (u32, u32),
+    /// OCR regions, in the same order as [`AOE4_STATS_POS`]
+    pub stat_regions: [Rect; AOE4_STATS_POS.len()],
+    pub villager_icon_area: Rect,
+    /// Match timer in frame pixels
+    pub clock_area: Rect,
+    /// Age emblem search area in frame pixels
+    pub age_area: Rect,
+}
+
+impl ResolvedHudLayout {
+    /// Whether the cropped HUD panel has to be resized to `normalized_size`
+    pub fn needs_resize(&self) -> bool {
+        (self.area.width, self.area.height) != self.normalized_size
+    }
+}
+
+impl HudLayout {
+    /// Load a layout from a `.toml` or `.json` file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read layout file {}", path.display()))?;
+        let layout: HudLayout = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            _ => toml::from_str(&content)?,
+        };
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    /// Write the layout as `.toml` or `.json` file, depending on the file extension
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::to_string_pretty(self)?,
+            _ => toml::to_string_pretty(self)?,
+        };
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write layout file {}", path.display()))?;
+        Ok(())
+    }
+
+    /// All layout profiles shipped with the application
+    pub fn builtin_profiles() -> Vec<HudLayout> {
+        BUILTIN_PROFILES
+            .iter()
+            .map(|content| toml::from_str(content).expect("Invalid builtin layout profile"))
+            .collect()
+    }
+
+    /// Pick the builtin profile that fits the frame size best.
+    ///
+    /// An exact match wins, then a profile with the same aspect ratio, then the profile with
+    /// the closest height.
+    pub fn for_frame_size(width: u32, height: u32) -> HudLayout {
+        let aspect = width as f32 / height.max(1) as f32;
+        Self::builtin_profiles()
+            .into_iter()
+            .min_by_key(|profile| {
+                let exact = profile.reference_width == width && profile.reference_height == height;
+                let profile_aspect =
+                    profile.reference_width as f32 / profile.reference_height as f32;
+                let same_aspect = (profile_aspect - aspect).abs() < 0.05;
+                (!exact, !same_aspect, profile.reference_height.abs_diff(height))
+            })
+            .unwrap_or_default()
+    }
+
+    /// Check that every stat of [`AOE4_STATS_POS`] has a region
+    pub fn validate(&self) -> Result<()> {
+        for stat in AOE4_STATS_POS.iter() {
+            if self.stat(stat.name).is_none() {
+                anyhow::bail!("Layout '{}' has no region for '{}'", self.name, stat.name);
+            }
+        }
+        if self.reference_height == 0 || self.ui_scale <= 0.0 || self.reference_ui_scale <= 0.0 {
+            anyhow::bail!("Layout '{}' has an invalid reference size or UI scale", self.name);
+        }
+        Ok(())
+    }
+
+    fn stat(&self, name: &str) -> Option<&StatRegion> {
+        self.stats.iter().find(|s| s.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Frame pixels per reference pixel for the given frame height
+    pub fn scale_for(&self, frame_height: u32) -> f32 {
+        frame_height as f32 / self.reference_height as f32 * self.ui_scale
+            / self.reference_ui_scale
+    }
+
+    /// Normalized pixels per reference pixel
+    fn normalization(&self) -> f32 {
+        TEMPLATE_REFERENCE_HEIGHT / (self.reference_height as f32 * self.reference_ui_scale)
+    }
+
+    /// Compute pixel regions for a frame of the given size
+    pub fn resolve(&self, frame_width: u32, frame_height: u32) -> ResolvedHudLayout {
+        let scale = self.scale_for(frame_height);
+        let normalization = self.normalization();
+        let area_width = ((self.area_width * scale).round() as u32).clamp(1, frame_width.max(1));
+        let area_height =
+            ((self.area_height * scale).round() as u32).clamp(1, frame_height.max(1));
+        let area = Rect {
+            x: 0,
+            y: frame_height.saturating_sub(area_height),
+            width: area_width,
+            height: area_height,
+        };
+
+        let stat_regions = std::array::from_fn(|index| {
+            let name = AOE4_STATS_POS[index].name;
+            let (x, y) = self.stat(name).map(|s| (s.x, s.y)).unwrap_or_default();
+            LayoutRect {
+                x,
+                y,
+                width: self.stat_width,
+                height: self.stat_height,
+            }
+            .scaled(normalization)
+        });
+
+        ResolvedHudLayout {
+            area,
+            scale: scale / normalization,
+            normalized_size: (
+                (self.area_width * normalization).round() as u32,
+                (self.area_height * normalization).round() as u32,
+            ),
+            stat_regions,
+            villager_icon_area: self.villager_icon_area.scaled(normalization),
+            clock_area: self.clock_area.top_centered(scale, frame_width, frame_height),
+            age_area: self.age_area.top_centered(scale, frame_width, frame_height),
+        }
+    }
+}
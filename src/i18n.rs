@@ -0,0 +1,159 @@
+// Translation catalogs for user-facing strings
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// Language code and content of the catalogs shipped with the application
+const CATALOGS: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.toml")),
+    ("de", include_str!("../locales/de.toml")),
+];
+
+/// Used if neither the configuration nor the system locale select a shipped catalog, and for keys
+/// missing in a catalog
+const FALLBACK_LANGUAGE: &str = "en";
+
+/// Strings of one language by dotted key, e.g. `alert.house`
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    pub language: String,
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// Load a shipped catalog, `None` if there is none for the language
+    pub fn load(language: &str) -> Option<Self> {
+        let strings = Self::parse(language)?;
+        Some(Self {
+            language: language.to_string(),
+            strings,
+            fallback: Self::parse(FALLBACK_LANGUAGE).unwrap_or_default(),
+        })
+    }
+
+    fn parse(language: &str) -> Option<HashMap<String, String>> {
+        let (_, content) = CATALOGS.iter().find(|(l, _)| *l == language)?;
+        let table: toml::Table = toml::from_str(content).expect("Invalid builtin catalog");
+        let mut strings = HashMap::new();
+        Self::flatten("", &table, &mut strings);
+        Some(strings)
+    }
+
+    fn flatten(prefix: &str, table: &toml::Table, strings: &mut HashMap<String, String>) {
+        for (key, value) in table {
+            let key = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            match value {
+                toml::Value::Table(table) => Self::flatten(&key, table, strings),
+                toml::Value::String(text) => {
+                    strings.insert(key, text.clone());
+                }
+                _ => log::warn!("Ignoring non-string catalog entry {}", key),
+            }
+        }
+    }
+
+    /// The translation of `key`, the English string if the catalog lacks it, or `key` itself
+    pub fn get(&self, key: &str) -> String {
+        self.lookup(key).unwrap_or(key).to_string()
+    }
+
+    fn lookup(&self, key: &str) -> Option<&str> {
+        self.strings
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map(String::as_str)
+    }
+}
+
+/// Languages of the system locale in order of preference, from the usual environment variables,
+/// e.g. `["de"]` for `LANG=de_DE.UTF-8`
+pub fn system_languages() -> Vec<String> {
+    locale_languages(|variable| std::env::var(variable).ok())
+}
+
+/// Languages of the locale variables returned by `var`, like gettext: the list in `LANGUAGE`
+/// comes first, unless the locale of `LC_ALL`, `LC_MESSAGES` or `LANG` is unset or "C"
+pub fn locale_languages(var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let language = |value: &str| {
+        value
+            .split(['_', '.', '@', '-'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+    };
+    let Some(locale) = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|variable| var(variable))
+        .find(|value| !value.is_empty())
+    else {
+        return Vec::new();
+    };
+    if locale == "C" || locale == "POSIX" || locale.starts_with("C.") {
+        return Vec::new();
+    }
+
+    let mut languages: Vec<String> = var("LANGUAGE")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|value| !value.is_empty())
+        .map(language)
+        .collect();
+    languages.push(language(&locale));
+    languages.dedup();
+    languages
+}
+
+static CURRENT: RwLock<Option<Arc<Catalog>>> = RwLock::new(None);
+
+/// Select the catalog: the configured language if set, otherwise the first system language with
+/// a catalog, falling back to English
+pub fn init(language: Option<&str>) {
+    let candidates = language
+        .map(str::to_string)
+        .into_iter()
+        .chain(system_languages());
+    let catalog = candidates
+        .filter_map(|language| {
+            let catalog = Catalog::load(&language);
+            if catalog.is_none() {
+                log::warn!("No translations for language '{}'", language);
+            }
+            catalog
+        })
+        .next()
+        .or_else(|| Catalog::load(FALLBACK_LANGUAGE))
+        .unwrap_or_default();
+    log::info!("Using language '{}'", catalog.language);
+    *CURRENT.write().unwrap() = Some(Arc::new(catalog));
+}
+
+fn current() -> Arc<Catalog> {
+    if let Some(catalog) = CURRENT.read().unwrap().as_ref() {
+        return catalog.clone();
+    }
+    init(None);
+    CURRENT.read().unwrap().clone().unwrap_or_default()
+}
+
+/// Translate a catalog key, e.g. `tr("alert.house")`
+pub fn tr(key: &str) -> String {
+    current().get(key)
+}
+
+/// Translate `text` if it is a catalog key, otherwise return it unchanged. Used for texts from the
+/// configuration file that may be keys or literal text.
+pub fn tr_or_literal(text: &str) -> String {
+    current().lookup(text).unwrap_or(text).to_string()
+}
+
+/// Catalog key of a stat name of `AOE4_STATS_POS`, e.g. `stat.food_worker` for "Food Worker"
+pub fn stat_key(name: &str) -> String {
+    format!("stat.{}", name.to_lowercase().replace(' ', "_"))
+}
@@ -0,0 +1,256 @@
+// Named icon templates loaded from a directory
+//
+// Every icon is a `<name>.toml` file next to its template image, e.g. `idle_scout.toml`:
+//
+//   template = "idle_scout.png"   # optional, defaults to <name>.png
+//   region = "hud"                # "hud" or "frame"
+//   area = { x = 180.0, y = 10.0, width = 80.0, height = 70.0 }
+//   threshold = 0.7               # optional, defaults to `icon_threshold` of the analyzer
+//
+// Areas in the "hud" region are in pixels of the normalized HUD panel, like the villager icon
+// area of the layout profiles. Areas in the "frame" region are fractions (0 - 1) of the frame
+// size, for icons outside the HUD panel like the age-up progress or attack notifications.
+//
+// The villager icon `villager.toml` is required. It is searched in the villager icon area of the
+// layout profile.
+
+use crate::{hud_layout::LayoutRect, image_analyzer::ImageAnalyzerInner};
+use anyhow::{Context, Result};
+use opencv::{
+    core::{Mat, Rect, Size},
+    imgcodecs::{self, IMREAD_COLOR},
+    imgproc,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Best match of an icon template
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IconMatch {
+    /// Normalized correlation coefficient, 1.0 is a perfect match
+    pub score: f64,
+    /// Bounding box in the searched image, e.g. the normalized HUD panel
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Scale of the template that matched
+    pub scale: f64,
+}
+
+/// Result of an icon search, detailed enough to tell why an icon was not found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct IconDetection {
+    pub found: bool,
+    pub threshold: f64,
+    /// `None` if the template is larger than the search area at all scales
+    pub best: Option<IconMatch>,
+}
+
+impl fmt::Display for IconDetection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(best) = self.best else {
+            return f.write_str("not found, search area smaller than the icon");
+        };
+        let (result, comparison) = if self.found {
+            ("found", ">=")
+        } else {
+            ("not found", "<")
+        };
+        write!(
+            f,
+            "{}, score {:.2} {} {:.2} at {:.2}x ({}, {})",
+            result, best.score, comparison, self.threshold, best.scale, best.x, best.y
+        )
+    }
+}
+
+/// An icon template resized to all configured scales
+pub struct IconTemplate {
+    template: Mat,
+    scaled: Vec<(f64, Mat)>,
+}
+
+impl IconTemplate {
+    pub fn new(template: Mat, scales: &[f64]) -> Result<Self> {
+        let mut icon = Self {
+            template,
+            scaled: Vec::new(),
+        };
+        icon.set_scales(scales)?;
+        Ok(icon)
+    }
+
+    /// Load a template image (BGR)
+    pub fn load(path: &str, scales: &[f64]) -> Result<Self> {
+        let template = imgcodecs::imread(path, IMREAD_COLOR)?;
+        if template.empty() {
+            anyhow::bail!("Failed to load template image from {}", path);
+        }
+        Self::new(template, scales)
+    }
+
+    /// Resize the template to `scales`. Scales that make it vanish are skipped.
+    pub fn set_scales(&mut self, scales: &[f64]) -> Result<()> {
+        self.scaled.clear();
+        for &scale in scales {
+            let size = Size::new(
+                (self.template.cols() as f64 * scale).round() as i32,
+                (self.template.rows() as f64 * scale).round() as i32,
+            );
+            if size.width < 1 || size.height < 1 {
+                continue;
+            }
+            if size == self.template.size()? {
+                self.scaled.push((scale, self.template.try_clone()?));
+                continue;
+            }
+            let mut resized = Mat::default();
+            let interpolation = if scale < 1.0 {
+                imgproc::INTER_AREA
+            } else {
+                imgproc::INTER_LINEAR
+            };
+            imgproc::resize(&self.template, &mut resized, size, 0.0, 0.0, interpolation)?;
+            self.scaled.push((scale, resized));
+        }
+        Ok(())
+    }
+
+    /// Size of the template at scale 1
+    pub fn size(&self) -> Size {
+        Size::new(self.template.cols(), self.template.rows())
+    }
+
+    /// Best match over all scales in an area of the image
+    pub fn find(&self, img: &Mat, search_area: Rect) -> Result<Option<IconMatch>> {
+        let mut best: Option<IconMatch> = None;
+        for (scale, template) in &self.scaled {
+            let Some((score, bbox)) =
+                ImageAnalyzerInner::find_template(img, template, search_area)?
+            else {
+                continue;
+            };
+            if best.is_none_or(|best| score > best.score) {
+                best = Some(IconMatch {
+                    score,
+                    x: bbox.x,
+                    y: bbox.y,
+                    width: bbox.width,
+                    height: bbox.height,
+                    scale: *scale,
+                });
+            }
+        }
+        Ok(best)
+    }
+}
+
+/// What the search area of an icon is relative to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IconRegion {
+    /// The normalized HUD panel, in its pixels
+    #[default]
+    Hud,
+    /// The whole frame, in fractions of its size
+    Frame,
+}
+
+/// Contents of an icon definition file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IconDefinition {
+    /// Template image relative to the definition file, `<name>.png` if not set
+    #[serde(default)]
+    pub template: Option<PathBuf>,
+    #[serde(default)]
+    pub region: IconRegion,
+    pub area: LayoutRect,
+    /// Minimum template matching score
+    #[serde(default)]
+    pub threshold: Option<f64>,
+}
+
+pub struct RegisteredIcon {
+    pub name: String,
+    pub definition: IconDefinition,
+    pub template: IconTemplate,
+}
+
+/// All icons of a template directory, sorted by name
+#[derive(Default)]
+pub struct IconRegistry {
+    icons: Vec<RegisteredIcon>,
+}
+
+impl IconRegistry {
+    /// Load all icon definitions of `dir`. A missing directory gives an empty registry.
+    pub fn load(dir: &Path, scales: &[f64]) -> Result<Self> {
+        let mut registry = Self::default();
+        if !dir.is_dir() {
+            log::info!(
+                "No icon directory at {}, no extra icons detected",
+                dir.display()
+            );
+            return Ok(registry);
+        }
+
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read icon directory {}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        files.sort();
+
+        for file in files {
+            let name = file
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let content = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read icon definition {}", file.display()))?;
+            let definition: IconDefinition = toml::from_str(&content)
+                .with_context(|| format!("Invalid icon definition {}", file.display()))?;
+            let template_path = dir.join(
+                definition
+                    .template
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from(format!("{}.png", name))),
+            );
+            let template = IconTemplate::load(&template_path.to_string_lossy(), scales)
+                .with_context(|| format!("Failed to load the template of icon '{}'", name))?;
+            registry.icons.push(RegisteredIcon {
+                name,
+                definition,
+                template,
+            });
+        }
+        log::info!(
+            "Loaded {} icons from {}",
+            registry.icons.len(),
+            dir.display()
+        );
+        Ok(registry)
+    }
+
+    pub fn icons(&self) -> &[RegisteredIcon] {
+        &self.icons
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegisteredIcon> {
+        self.icons.iter().find(|icon| icon.name == name)
+    }
+
+    /// Resize all templates to new search scales
+    pub fn set_scales(&mut self, scales: &[f64]) -> Result<()> {
+        for icon in &mut self.icons {
+            icon.template.set_scales(scales)?;
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,13 @@
-use crate::consts::{AOE4_STATS_POS, AREA_Y_OFFSET, STAT_RECT, VILLAGER_ICON_AREA};
+use crate::{
+    calibration::{ANCHORS, Anchor},
+    consts::AOE4_STATS_POS,
+    game_clock::parse_clock,
+    game_state::{Age, GameState, Reading},
+    hud_layout::{LayoutRect, ResolvedHudLayout},
+    icon_registry::{IconDetection, IconRegion, IconRegistry, IconTemplate},
+    ocr_cache::{OcrCache, OcrCacheConfig, OcrCacheStats},
+    preprocessing::{self, PreprocessConfig, PreprocessStages},
+};
 use crate::ocr::{
     OcrEngine,
     OcrEngineWrapper,
@@ -10,34 +19,100 @@ use crate::ocr::{
 };
 use anyhow::Result;
 use image::RgbImage;
+use serde::{Deserialize, Serialize};
 use opencv::{
-    core::{self, AlgorithmHint, Mat, Point, Rect},
-    imgcodecs::{self, IMREAD_COLOR},
+    core::{self, AlgorithmHint, Mat, Point, Rect, Size},
     imgproc::{self},
     prelude::*,
 };
 use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     time::Duration,
 };
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalysisResult {
     pub detected_texts: [fixedstr::str8; AOE4_STATS_POS.len()],
     pub has_villager_icon: bool,
+    /// Score and location of the villager icon search, also if the icon was not found
+    #[serde(default)]
+    pub villager_icon: IconDetection,
+    /// Icons of the icon registry by name
+    #[serde(default)]
+    pub icons: BTreeMap<String, IconDetection>,
+    /// Number of HUD icons (population and resources) found at their place in the HUD panel
+    #[serde(default)]
+    pub hud_anchors: u32,
+    /// Game time of the match timer at the top of the screen, `None` if it was not readable
+    #[serde(default)]
+    pub game_time_ms: Option<u64>,
+    /// `detected_texts` and `has_villager_icon` parsed into typed values
+    pub game_state: GameState,
+    /// Intermediate images of the preprocessing, only kept for the debug window
+    #[serde(skip)]
+    pub preprocess_stages: Option<PreprocessStages>,
     pub detect_villager_time: Duration,
     pub convert_color_time: Duration,
     pub ocr_time: Duration,
 }
 
+/// Configuration of the OCR engine and detection thresholds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyzerConfig {
+    pub ocr_engine: OCRModel,
+    /// Minimum template matching score for the villager icon, unless its `villager.toml` sets one
+    pub villager_icon_threshold: f64,
+    /// Minimum template matching score for the population and resource icons of the HUD
+    pub hud_anchor_threshold: f64,
+    /// Scales the icon templates are searched at, for in-game UI scales that differ from the
+    /// layout profile. The best match of all scales counts.
+    pub icon_scales: Vec<f64>,
+    /// Directory of the icon registry, see [`crate::icon_registry`]
+    pub icon_dir: PathBuf,
+    /// Minimum template matching score for registry icons without their own threshold
+    pub icon_threshold: f64,
+    /// Directory of the age emblem templates: `dark.png`, `feudal.png`, `castle.png`,
+    /// `imperial.png` and `advancing.png` for an age-up in progress. The age is not detected
+    /// unless all four ages have a template.
+    pub age_dir: PathBuf,
+    /// Minimum template matching score for the age emblems
+    pub age_threshold: f64,
+    pub template_matching: TemplateMatchingConfig,
+    /// Preprocessing of the stat regions before OCR
+    pub preprocessing: PreprocessConfig,
+    /// Reuse of the OCR results of unchanged stat regions
+    pub ocr_cache: OcrCacheConfig,
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> Self {
+        Self {
+            ocr_engine: OCRModel::TemplateMatching,
+            villager_icon_threshold: 0.6,
+            hud_anchor_threshold: 0.6,
+            icon_scales: vec![1.0, 0.9, 1.1, 0.8, 1.2],
+            icon_dir: PathBuf::from("src_images/detect"),
+            icon_threshold: 0.7,
+            age_dir: PathBuf::from("src_images/ages"),
+            age_threshold: 0.7,
+            template_matching: TemplateMatchingConfig::default(),
+            preprocessing: PreprocessConfig::default(),
+            ocr_cache: OcrCacheConfig::default(),
+        }
+    }
+}
+
 pub struct ImageAnalyzer {
     inner: Arc<Mutex<Option<ImageAnalyzerInner>>>,
 }
 
 impl ImageAnalyzer {
-    pub fn new(ocrmodel: OCRModel) -> Result<Self> {
-        let inner = ImageAnalyzerInner::new(ocrmodel)?;
+    pub fn new(config: &AnalyzerConfig) -> Result<Self> {
+        let inner = ImageAnalyzerInner::with_config(config)?;
         Ok(Self {
             inner: Arc::new(Mutex::new(Some(inner))),
         })
@@ -52,10 +127,74 @@ impl ImageAnalyzer {
 
 pub struct ImageAnalyzerInner {
     ocr_engine: OcrEngineWrapper,
-    villager_icon_template: Mat,
+    /// Texts of the stat regions that did not change
+    ocr_cache: OcrCache,
+    /// Icons that are always shown in the HUD panel, to tell the game from menus
+    hud_anchor_templates: Vec<(Anchor, IconTemplate)>,
+    icons: IconRegistry,
+    ages: AgeTemplates,
+    /// Keep the intermediate preprocessing images in the analysis results
+    keep_preprocess_stages: bool,
+    config: AnalyzerConfig,
+}
+
+/// Templates of the age emblems. The age is only detected if all ages have a template, a partial
+/// set would read every later age as one of the earlier ones.
+struct AgeTemplates {
+    ages: Vec<(Age, IconTemplate)>,
+    advancing: Option<IconTemplate>,
+}
+
+impl AgeTemplates {
+    fn load(dir: &Path, scales: &[f64]) -> Result<Self> {
+        let load = |name: &str| -> Result<Option<IconTemplate>> {
+            let path = dir.join(format!("{}.png", name));
+            if !path.is_file() {
+                return Ok(None);
+            }
+            IconTemplate::load(&path.to_string_lossy(), scales).map(Some)
+        };
+        let mut ages = Vec::new();
+        let mut missing = Vec::new();
+        for age in Age::ALL {
+            match load(age.name())? {
+                Some(template) => ages.push((age, template)),
+                None => missing.push(age.name()),
+            }
+        }
+        if !missing.is_empty() {
+            log::info!(
+                "No age emblems for {} in {}, the age is not detected",
+                missing.join(", "),
+                dir.display()
+            );
+            ages.clear();
+        }
+        Ok(Self {
+            ages,
+            advancing: load("advancing")?,
+        })
+    }
+
+    fn set_scales(&mut self, scales: &[f64]) -> Result<()> {
+        for (_, template) in &mut self.ages {
+            template.set_scales(scales)?;
+        }
+        if let Some(template) = &mut self.advancing {
+            template.set_scales(scales)?;
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug)]
+/// Name of the villager icon in the icon registry
+const VILLAGER_ICON: &str = "villager";
+
+/// Search margin around the position of a HUD icon, in normalized pixels
+const HUD_ANCHOR_MARGIN: i32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OCRModel {
     #[allow(dead_code)]
     PP,
@@ -68,17 +207,43 @@ pub enum OCRModel {
     // TemplateMatchingWithFallback,
 }
 
+impl std::str::FromStr for OCRModel {
+    type Err = String;
+
+    /// Parse the names used in the configuration file, e.g. "template_matching"
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pp" => Ok(OCRModel::PP),
+            "onnx" => Ok(OCRModel::ONNX),
+            "onnx_par" => Ok(OCRModel::OnnxPar),
+            "template_matching" => Ok(OCRModel::TemplateMatching),
+            _ => Err(format!(
+                "Unknown OCR engine '{}', expected pp, onnx, onnx_par or template_matching",
+                s
+            )),
+        }
+    }
+}
+
 impl ImageAnalyzerInner {
     pub fn new(ocrmodel: OCRModel) -> Result<Self> {
+        Self::with_config(&AnalyzerConfig {
+            ocr_engine: ocrmodel,
+            ..Default::default()
+        })
+    }
+
+    pub fn with_config(config: &AnalyzerConfig) -> Result<Self> {
+        config.preprocessing.validate()?;
+
         // Create OCR engine based on selected model
-        let ocr_engine = match ocrmodel {
+        let ocr_engine = match config.ocr_engine {
             OCRModel::PP => OcrEngineWrapper::Paddle(PaddleOcrEngine::new()?),
             OCRModel::ONNX => OcrEngineWrapper::Onnx(OnnxOcrEngine::new()?),
             OCRModel::OnnxPar => OcrEngineWrapper::OnnxParallel(OnnxParallelOcrEngine::new()?),
-            OCRModel::TemplateMatching => {
-                let config = TemplateMatchingConfig::default();
-                OcrEngineWrapper::TemplateMatching(TemplateMatchingOcrEngine::new(config)?)
-            }
+            OCRModel::TemplateMatching => OcrEngineWrapper::TemplateMatching(
+                TemplateMatchingOcrEngine::new(config.template_matching.clone())?,
+            ),
             // OCRModel::TemplateMatchingWithFallback => {
             //     let config = TemplateMatchingConfig::default();
             //     let primary = OcrEngineWrapper::TemplateMatching(TemplateMatchingOcrEngine::new(config)?);
@@ -87,21 +252,122 @@ impl ImageAnalyzerInner {
             // }
         };
 
-        // Load villager icon template
-        let template_path = "src_images/villager_icon.png";
-        let villager_icon_template = imgcodecs::imread(template_path, IMREAD_COLOR)?;
-
-        if villager_icon_template.empty() {
-            anyhow::bail!("Failed to load template image from {}", template_path);
+        let mut hud_anchor_templates = Vec::new();
+        for anchor in ANCHORS.iter().filter(|anchor| anchor.name != "villager") {
+            let template = IconTemplate::load(anchor.template_path, &config.icon_scales)?;
+            hud_anchor_templates.push((*anchor, template));
         }
 
+        let icons = Self::load_icons(&config.icon_dir, &config.icon_scales)?;
+        let ages = AgeTemplates::load(&config.age_dir, &config.icon_scales)?;
+
         Ok(Self {
             ocr_engine,
-            villager_icon_template,
+            ocr_cache: OcrCache::new(&config.ocr_cache),
+            hud_anchor_templates,
+            icons,
+            ages,
+            keep_preprocess_stages: false,
+            config: config.clone(),
         })
     }
 
-    pub fn analyze(&mut self, mut cv_mat: Mat) -> Result<AnalysisResult> {
+    /// Load the icon registry, which must contain the villager icon
+    fn load_icons(dir: &Path, scales: &[f64]) -> Result<IconRegistry> {
+        let icons = IconRegistry::load(dir, scales)?;
+        if icons.get(VILLAGER_ICON).is_none() {
+            anyhow::bail!(
+                "The icon directory {} has no {}.toml",
+                dir.display(),
+                VILLAGER_ICON
+            );
+        }
+        Ok(icons)
+    }
+
+    pub fn config(&self) -> &AnalyzerConfig {
+        &self.config
+    }
+
+    /// Hits of the OCR cache since the last call
+    pub fn take_ocr_cache_stats(&mut self) -> OcrCacheStats {
+        self.ocr_cache.take_stats()
+    }
+
+    /// Keep the intermediate preprocessing images, e.g. while the debug window is shown
+    pub fn set_keep_preprocess_stages(&mut self, keep: bool) {
+        self.keep_preprocess_stages = keep;
+    }
+
+    /// Apply changed thresholds. A different OCR engine requires a new analyzer.
+    pub fn set_config(&mut self, config: &AnalyzerConfig) {
+        let mut config = config.clone();
+        if let Err(e) = config.preprocessing.validate() {
+            log::error!("Keeping previous preprocessing: {:#}", e);
+            config.preprocessing = self.config.preprocessing.clone();
+        }
+        let config = &config;
+        if *config != self.config {
+            self.ocr_cache.set_config(&config.ocr_cache);
+        }
+        self.ocr_engine.set_template_matching_config(&config.template_matching);
+        if config.icon_scales != self.config.icon_scales {
+            for (_, template) in &mut self.hud_anchor_templates {
+                if let Err(e) = template.set_scales(&config.icon_scales) {
+                    log::error!("Failed to resize the anchor templates: {}", e);
+                }
+            }
+        }
+        if config.icon_dir != self.config.icon_dir {
+            match Self::load_icons(&config.icon_dir, &config.icon_scales) {
+                Ok(icons) => self.icons = icons,
+                Err(e) => log::error!("Failed to load the icons: {:#}", e),
+            }
+        } else if config.icon_scales != self.config.icon_scales {
+            if let Err(e) = self.icons.set_scales(&config.icon_scales) {
+                log::error!("Failed to resize the icon templates: {}", e);
+            }
+        }
+        if config.age_dir != self.config.age_dir {
+            match AgeTemplates::load(&config.age_dir, &config.icon_scales) {
+                Ok(ages) => self.ages = ages,
+                Err(e) => log::error!("Failed to load the age emblems: {:#}", e),
+            }
+        } else if config.icon_scales != self.config.icon_scales {
+            if let Err(e) = self.ages.set_scales(&config.icon_scales) {
+                log::error!("Failed to resize the age emblems: {}", e);
+            }
+        }
+        self.config = config.clone();
+    }
+
+    /// Crop the HUD panel out of a full frame and resize it to the normalized layout size
+    pub fn extract_hud_area(frame: &Mat, layout: &ResolvedHudLayout) -> Result<Mat> {
+        let area = Rect::new(
+            layout.area.x as i32,
+            layout.area.y as i32,
+            layout.area.width as i32,
+            layout.area.height as i32,
+        );
+        let roi = Mat::roi(frame, area)?;
+        if !layout.needs_resize() {
+            return Ok(roi.try_clone()?);
+        }
+
+        let mut resized = Mat::default();
+        imgproc::resize(
+            &roi,
+            &mut resized,
+            Size::new(layout.normalized_size.0 as i32, layout.normalized_size.1 as i32),
+            0.0,
+            0.0,
+            imgproc::INTER_AREA,
+        )?;
+        Ok(resized)
+    }
+
+    /// Analyze a normalized HUD panel, see [`Self::extract_hud_area`]
+    pub fn analyze(&mut self, cv_mat: Mat, layout: &ResolvedHudLayout) -> Result<AnalysisResult> {
         let width = cv_mat.cols() as u32;
         let height = cv_mat.rows() as u32;
 
@@ -109,7 +375,7 @@ impl ImageAnalyzerInner {
 
         // OpenCV Mat is in BGR format, convert to grayscale and then to RGB
         let mut rgb_mat = Mat::default();
-        let has_villager_icon = if cv_mat.channels() == 4 {
+        if cv_mat.channels() == 4 {
             imgproc::cvt_color(
                 &cv_mat,
                 &mut rgb_mat,
@@ -117,55 +383,74 @@ impl ImageAnalyzerInner {
                 0,
                 AlgorithmHint::ALGO_HINT_DEFAULT,
             )?;
-            self.detect_icon(&rgb_mat, &self.villager_icon_template)?
+        }
+        let bgr_mat = if cv_mat.channels() == 4 {
+            &rgb_mat
         } else {
-            self.detect_icon(&cv_mat, &self.villager_icon_template)?
+            &cv_mat
         };
+        let mut icons = self.detect_hud_icons(bgr_mat)?;
+        let villager_icon = match self.icons.get(VILLAGER_ICON) {
+            Some(icon) => self.detect_icon(
+                bgr_mat,
+                &icon.template,
+                layout.villager_icon_area,
+                icon.definition.threshold.unwrap_or(self.config.villager_icon_threshold),
+            )?,
+            None => IconDetection::default(),
+        };
+        icons.insert(VILLAGER_ICON.to_string(), villager_icon);
+        let has_villager_icon = villager_icon.found;
+        let hud_anchors = self.count_hud_anchors(bgr_mat)?;
         let detect_villager_time = now.elapsed();
 
-        if cv_mat.channels() == 4 {
-            imgproc::cvt_color(
-                &cv_mat,
-                &mut rgb_mat,
-                imgproc::COLOR_BGRA2GRAY,
-                0,
-                AlgorithmHint::ALGO_HINT_DEFAULT,
-            )?;
-        } else {
-            imgproc::cvt_color(
-                &cv_mat,
-                &mut rgb_mat,
-                imgproc::COLOR_BGR2GRAY,
-                0,
-                AlgorithmHint::ALGO_HINT_DEFAULT,
-            )?;
+        // Preprocess every region with its own chain
+        let mut images = Vec::with_capacity(AOE4_STATS_POS.len());
+        let mut stages = Vec::new();
+        for (index, rect) in layout.stat_regions.iter().enumerate() {
+            // Clipped to the image
+            let x = rect.x.min(width.saturating_sub(1));
+            let y = rect.y.min(height.saturating_sub(1));
+            let region = Rect::new(
+                x as i32,
+                y as i32,
+                rect.width.min(width - x) as i32,
+                rect.height.min(height - y) as i32,
+            );
+            let mut region_stages = Vec::new();
+            images.push(preprocessing::run(
+                self.config.preprocessing.chain(index),
+                &Mat::roi(bgr_mat, region)?.try_clone()?,
+                self.keep_preprocess_stages.then_some(&mut region_stages),
+            )?);
+            stages.push(region_stages);
         }
-        imgproc::cvt_color(
-            &rgb_mat,
-            &mut cv_mat,
-            imgproc::COLOR_GRAY2RGB,
-            0,
-            AlgorithmHint::ALGO_HINT_DEFAULT,
-        )?;
-
-        let rgb_buffer = cv_mat.data_bytes()?;
-        let mut img = RgbImage::from_raw(width, height, rgb_buffer.to_vec()).unwrap();
-        image::imageops::colorops::brighten_in_place(&mut img, 30);
+        let preprocess_stages = self
+            .keep_preprocess_stages
+            .then(|| PreprocessStages::new(&stages))
+            .transpose()?;
 
         let convert_color_time = now.elapsed() - detect_villager_time;
 
-        // Prepare regions for OCR
-        let image_height = img.height() as f32;
-        let regions: Vec<(u32, u32, u32, u32)> = AOE4_STATS_POS
-            .iter()
-            .map(|stat_pos| {
-                let y = (image_height + stat_pos.y) as u32;
-                (stat_pos.x as u32, y, STAT_RECT.width, STAT_RECT.height)
-            })
-            .collect();
-
-        // Perform OCR using the selected engine
-        let detected_texts = self.ocr_engine.recognize_text::<{AOE4_STATS_POS.len()}>(&img, &regions)?;
+        // Unchanged regions keep their text, the others are stacked for the OCR engine
+        let mut detected_texts = [fixedstr::str8::new(); AOE4_STATS_POS.len()];
+        let mut pending = Vec::new();
+        for (index, image) in images.iter().enumerate() {
+            match self.ocr_cache.lookup(index, image) {
+                Some(text) => detected_texts[index] = text,
+                None => pending.push(index),
+            }
+        }
+        if !pending.is_empty() {
+            let (img, regions) = preprocessing::stack(pending.iter().map(|&index| &images[index]));
+            let texts = self
+                .ocr_engine
+                .recognize_text::<{ AOE4_STATS_POS.len() }>(&img, &regions)?;
+            for (text, index) in texts.into_iter().zip(pending) {
+                detected_texts[index] = text;
+                self.ocr_cache.insert(index, std::mem::take(&mut images[index]), text);
+            }
+        }
 
         let ocr_time = now.elapsed() - convert_color_time - detect_villager_time;
         if ocr_time > Duration::from_millis(100) {
@@ -173,38 +458,265 @@ impl ImageAnalyzerInner {
         }
 
         Ok(AnalysisResult {
+            game_state: GameState::from_texts(&detected_texts, has_villager_icon),
+            game_time_ms: None,
+            preprocess_stages,
             detected_texts,
             has_villager_icon,
+            villager_icon,
+            icons,
+            hud_anchors,
             detect_villager_time,
             convert_color_time,
             ocr_time,
         })
     }
 
-    /// Detect villager icon using template matching
+    /// RGB image of a BGR image for the OCR engines, with the default preprocessing of the stat
+    /// regions
+    pub fn ocr_image(img: &Mat) -> Result<RgbImage> {
+        preprocessing::run(&PreprocessConfig::default().default, img, None)
+    }
+
+    /// Analyze the parts of a full BGR(A) frame outside of the HUD panel: the registry icons of
+    /// the frame region, the match timer and the age emblem
+    pub fn analyze_frame(
+        &mut self,
+        frame: &Mat,
+        layout: &ResolvedHudLayout,
+        analysis: &mut AnalysisResult,
+    ) -> Result<()> {
+        analysis.icons.extend(self.detect_frame_icons(frame, layout)?);
+        analysis.game_time_ms = self.read_clock(frame, layout)?;
+        let (age, aging_up) = self.detect_age(frame, layout)?;
+        analysis.game_state.age = age;
+        analysis.game_state.aging_up = aging_up;
+        Ok(())
+    }
+
+    /// Crop an area of a full BGR(A) frame and resize it like the HUD panel, so the templates see
+    /// the scale they were cut at. Returns a BGR image.
+    fn normalized_frame_area(frame: &Mat, area: Rect, scale: f32) -> Result<Mat> {
+        let roi = Mat::roi(frame, area)?;
+        let size = Size::new(
+            ((area.width as f32 / scale).round() as i32).max(1),
+            ((area.height as f32 / scale).round() as i32).max(1),
+        );
+        let mut normalized = Mat::default();
+        imgproc::resize(&roi, &mut normalized, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+        if normalized.channels() == 4 {
+            let mut bgr = Mat::default();
+            imgproc::cvt_color(
+                &normalized,
+                &mut bgr,
+                imgproc::COLOR_BGRA2BGR,
+                0,
+                AlgorithmHint::ALGO_HINT_DEFAULT,
+            )?;
+            normalized = bgr;
+        }
+        Ok(normalized)
+    }
+
+    fn frame_rect(area: image::math::Rect) -> Rect {
+        Rect::new(area.x as i32, area.y as i32, area.width as i32, area.height as i32)
+    }
+
+    /// Read the match timer, in milliseconds of game time. `None` if it is not readable, e.g. in
+    /// menus.
+    fn read_clock(&mut self, frame: &Mat, layout: &ResolvedHudLayout) -> Result<Option<u64>> {
+        let area = Self::frame_rect(layout.clock_area);
+        let img = Self::ocr_image(&Self::normalized_frame_area(frame, area, layout.scale)?)?;
+        let region = (0, 0, img.width(), img.height());
+        let [text] = self.ocr_engine.recognize_text::<1>(&img, &[region])?;
+        Ok(parse_clock(text.as_str()))
+    }
+
+    /// Detect the age from the best matching age emblem and whether an age-up is in progress
+    fn detect_age(&self, frame: &Mat, layout: &ResolvedHudLayout) -> Result<(Reading<Age>, bool)> {
+        if self.ages.ages.is_empty() {
+            return Ok((Reading::Unknown, false));
+        }
+        let area = Self::frame_rect(layout.age_area);
+        let img = Self::normalized_frame_area(frame, area, layout.scale)?;
+        let search_area = Rect::new(0, 0, img.cols(), img.rows());
+        let threshold = self.config.age_threshold;
+
+        let mut best: Option<(Age, f64)> = None;
+        for (age, template) in &self.ages.ages {
+            let Some(found) = template.find(&img, search_area)? else {
+                continue;
+            };
+            if found.score >= threshold && best.is_none_or(|(_, score)| found.score > score) {
+                best = Some((*age, found.score));
+            }
+        }
+        let aging_up = match &self.ages.advancing {
+            Some(template) => template
+                .find(&img, search_area)?
+                .is_some_and(|found| found.score >= threshold),
+            None => false,
+        };
+        Ok((best.map(|(age, _)| age).into(), aging_up))
+    }
+
+    /// Detect an icon using template matching at all configured scales
     ///
     /// # Arguments
     ///
     /// * `img`: &Mat - Input image in BGR format
+    /// * `icon`: Template to search for
+    /// * `search_area`: Area of the normalized HUD panel to search in
+    /// * `threshold`: Minimum score of a match
     ///
-    /// returns: Result<bool, Error>
-    fn detect_icon(&self, img: &Mat, detect_icon: &Mat) -> Result<bool> {
-        let img_height = img.rows() as f32;
+    /// returns: Result<IconDetection, Error>
+    fn detect_icon(
+        &self,
+        img: &Mat,
+        icon: &IconTemplate,
+        search_area: image::math::Rect,
+        threshold: f64,
+    ) -> Result<IconDetection> {
+        let search_area = Rect::new(
+            search_area.x as i32,
+            search_area.y as i32,
+            search_area.width as i32,
+            search_area.height as i32,
+        );
+        Self::match_icon(img, icon, search_area, threshold)
+    }
 
-        // Calculate search area
-        let search_x = VILLAGER_ICON_AREA.x as i32;
-        let search_y = (img_height + AREA_Y_OFFSET) as i32 + VILLAGER_ICON_AREA.y as i32;
-        let search_width = VILLAGER_ICON_AREA.width as i32;
-        let search_height = VILLAGER_ICON_AREA.height as i32;
+    fn match_icon(
+        img: &Mat,
+        icon: &IconTemplate,
+        search_area: Rect,
+        threshold: f64,
+    ) -> Result<IconDetection> {
+        let best = icon.find(img, search_area)?;
+        Ok(IconDetection {
+            found: best.is_some_and(|best| best.score >= threshold),
+            threshold,
+            best,
+        })
+    }
 
-        // Ensure bounds
-        let search_x = search_x.max(0);
-        let search_y = search_y.max(0);
-        let search_width = search_width.min(img.cols() - search_x);
-        let search_height = search_height.min(img.rows() - search_y);
+    /// Detect the registry icons of the HUD region in the normalized HUD panel (BGR), except the
+    /// villager icon
+    fn detect_hud_icons(&self, img: &Mat) -> Result<BTreeMap<String, IconDetection>> {
+        let mut icons = BTreeMap::new();
+        for icon in self.icons.icons() {
+            if icon.definition.region != IconRegion::Hud || icon.name == VILLAGER_ICON {
+                continue;
+            }
+            let LayoutRect {
+                x,
+                y,
+                width,
+                height,
+            } = icon.definition.area;
+            let search_area = Rect::new(
+                x.round() as i32,
+                y.round() as i32,
+                width.round() as i32,
+                height.round() as i32,
+            );
+            let threshold = icon.definition.threshold.unwrap_or(self.config.icon_threshold);
+            icons.insert(
+                icon.name.clone(),
+                Self::match_icon(img, &icon.template, search_area, threshold)?,
+            );
+        }
+        Ok(icons)
+    }
+
+    /// Detect the registry icons of the frame region
+    fn detect_frame_icons(
+        &self,
+        frame: &Mat,
+        layout: &ResolvedHudLayout,
+    ) -> Result<BTreeMap<String, IconDetection>> {
+        let mut icons = BTreeMap::new();
+        for icon in self.icons.icons() {
+            if icon.definition.region != IconRegion::Frame {
+                continue;
+            }
+            let area = icon.definition.area;
+            let x = ((area.x * frame.cols() as f32) as i32).clamp(0, frame.cols() - 1);
+            let y = ((area.y * frame.rows() as f32) as i32).clamp(0, frame.rows() - 1);
+            let width = ((area.width * frame.cols() as f32) as i32).clamp(1, frame.cols() - x);
+            let height = ((area.height * frame.rows() as f32) as i32).clamp(1, frame.rows() - y);
+            let img =
+                Self::normalized_frame_area(frame, Rect::new(x, y, width, height), layout.scale)?;
+
+            let threshold = icon.definition.threshold.unwrap_or(self.config.icon_threshold);
+            let search_area = Rect::new(0, 0, img.cols(), img.rows());
+            icons.insert(
+                icon.name.clone(),
+                Self::match_icon(&img, &icon.template, search_area, threshold)?,
+            );
+        }
+        Ok(icons)
+    }
+
+    /// Count the HUD icons found at their place in the normalized HUD panel (BGR). At a UI scale
+    /// that differs from the layout the icons are smaller or larger and move along, so the search
+    /// area covers their place at all icon scales.
+    fn count_hud_anchors(&self, img: &Mat) -> Result<u32> {
+        let scales = &self.config.icon_scales;
+        let min_scale = scales.iter().copied().fold(1.0, f64::min);
+        let max_scale = scales.iter().copied().fold(1.0, f64::max);
+        let mut count = 0;
+        for (anchor, template) in &self.hud_anchor_templates {
+            let size = template.size();
+            let left = (anchor.x as f64 * min_scale) as i32 - HUD_ANCHOR_MARGIN;
+            let top = (anchor.y as f64 * min_scale) as i32 - HUD_ANCHOR_MARGIN;
+            let right = ((anchor.x as f64 + size.width as f64) * max_scale).ceil() as i32;
+            let bottom = ((anchor.y as f64 + size.height as f64) * max_scale).ceil() as i32;
+            let search_area = Rect::new(
+                left,
+                top,
+                right + HUD_ANCHOR_MARGIN - left,
+                bottom + HUD_ANCHOR_MARGIN - top,
+            );
+            if template
+                .find(img, search_area)?
+                .is_some_and(|found| found.score >= self.config.hud_anchor_threshold)
+            {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Mean brightness of a BGR(A) frame, 0 - 255
+    pub fn frame_brightness(frame: &Mat) -> Result<f32> {
+        let mean = core::mean(frame, &core::no_array())?;
+        Ok(((mean[0] + mean[1] + mean[2]) / 3.0) as f32)
+    }
+
+    /// Find the best match of a template in an area of the image
+    ///
+    /// # Arguments
+    ///
+    /// * `img`: &Mat - Input image, same channel count as the template
+    /// * `template`: &Mat - Template to search for
+    /// * `search_area`: Rect - Area to search in, clipped to the image
+    ///
+    /// returns: Result<Option<(f64, Rect)>, Error> - Score and bounding box in image coordinates
+    /// of the best match, `None` if the template does not fit into the search area
+    pub fn find_template(
+        img: &Mat,
+        template: &Mat,
+        search_area: Rect,
+    ) -> Result<Option<(f64, Rect)>> {
+        // Ensure bounds, the parts outside of the image are cut off
+        let search_x = search_area.x.max(0);
+        let search_y = search_area.y.max(0);
+        let search_width = (search_area.x + search_area.width).min(img.cols()) - search_x;
+        let search_height = (search_area.y + search_area.height).min(img.rows()) - search_y;
 
-        if search_width <= 0 || search_height <= 0 {
-            return Ok(false);
+        if search_width < template.cols() || search_height < template.rows() {
+            return Ok(None);
         }
 
         // Extract ROI
@@ -217,7 +729,7 @@ impl ImageAnalyzerInner {
         let mut result = Mat::default();
         imgproc::match_template(
             &roi,
-            detect_icon,
+            template,
             &mut result,
             imgproc::TM_CCOEFF_NORMED,
             &Mat::default(),
@@ -238,7 +750,12 @@ impl ImageAnalyzerInner {
             &Mat::default(),
         )?;
 
-        let threshold = 0.6;
-        Ok(max_val >= threshold)
+        let bbox = Rect::new(
+            search_x + max_loc.x,
+            search_y + max_loc.y,
+            template.cols(),
+            template.rows(),
+        );
+        Ok(Some((max_val, bbox)))
     }
 }
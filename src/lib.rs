@@ -2,6 +2,7 @@
 #![feature(stmt_expr_attributes)]
 
 pub mod consts {
+    use crate::game_state::Resource;
     use image::math::Rect;
 
     pub const STAT_RECT: Rect = Rect{ x: 0, y: 0, width: 80, height: 34 };
@@ -9,6 +10,8 @@ pub mod consts {
     pub const AREA_Y_OFFSET: f32 = -486.0;
     pub const AREA_HEIGHT: i32 = -AREA_Y_OFFSET as i32;
     pub const AREA_WIDTH: i32 = 267;
+    /// Frame height the villager icon and digit templates were cut at (100% UI scale)
+    pub const TEMPLATE_REFERENCE_HEIGHT: f32 = 1440.0;
 
 
     #[derive(Debug, Default, PartialEq, Clone, Copy)]
@@ -17,6 +20,8 @@ pub mod consts {
         Unassigned,
         Idle,
         Population,
+        Resource(Resource),
+        Workers(Resource),
     }
 
     #[derive(Debug, Clone, Copy)]
@@ -29,16 +34,16 @@ pub mod consts {
 
     pub const AOE4_STATS_POS: [Aoe4StatPos; 10] = [
         Aoe4StatPos { x: 50.0, y: 190.0 + AREA_Y_OFFSET, name: "Pop", text_type: TextType::Population },
-        Aoe4StatPos { x: 50.0, y: 265.0 + AREA_Y_OFFSET, name: "Food", text_type: TextType::Unassigned  },
-        Aoe4StatPos { x: 50.0, y: 318.0 + AREA_Y_OFFSET, name: "Wood", text_type: TextType::Unassigned  },
-        Aoe4StatPos { x: 50.0, y: 369.0 + AREA_Y_OFFSET, name: "Gold", text_type: TextType::Unassigned  },
-        Aoe4StatPos { x: 50.0, y: 421.0 + AREA_Y_OFFSET, name: "Stone", text_type: TextType::Unassigned  },
+        Aoe4StatPos { x: 50.0, y: 265.0 + AREA_Y_OFFSET, name: "Food", text_type: TextType::Resource(Resource::Food) },
+        Aoe4StatPos { x: 50.0, y: 318.0 + AREA_Y_OFFSET, name: "Wood", text_type: TextType::Resource(Resource::Wood) },
+        Aoe4StatPos { x: 50.0, y: 369.0 + AREA_Y_OFFSET, name: "Gold", text_type: TextType::Resource(Resource::Gold) },
+        Aoe4StatPos { x: 50.0, y: 421.0 + AREA_Y_OFFSET, name: "Stone", text_type: TextType::Resource(Resource::Stone) },
 
         Aoe4StatPos { x: 187.0, y: 190.0 + AREA_Y_OFFSET, name: "Idle", text_type: TextType::Idle  },
-        Aoe4StatPos { x: 187.0, y: 262.0 + AREA_Y_OFFSET, name: "Food Worker", text_type: TextType::Unassigned },
-        Aoe4StatPos { x: 187.0, y: 315.0 + AREA_Y_OFFSET, name: "Wood Worker", text_type: TextType::Unassigned },
-        Aoe4StatPos { x: 187.0, y: 366.0 + AREA_Y_OFFSET, name: "Gold Worker", text_type: TextType::Unassigned },
-        Aoe4StatPos { x: 187.0, y: 419.0 + AREA_Y_OFFSET, name: "Stone Worker", text_type: TextType::Unassigned },
+        Aoe4StatPos { x: 187.0, y: 262.0 + AREA_Y_OFFSET, name: "Food Worker", text_type: TextType::Workers(Resource::Food) },
+        Aoe4StatPos { x: 187.0, y: 315.0 + AREA_Y_OFFSET, name: "Wood Worker", text_type: TextType::Workers(Resource::Wood) },
+        Aoe4StatPos { x: 187.0, y: 366.0 + AREA_Y_OFFSET, name: "Gold Worker", text_type: TextType::Workers(Resource::Gold) },
+        Aoe4StatPos { x: 187.0, y: 419.0 + AREA_Y_OFFSET, name: "Stone Worker", text_type: TextType::Workers(Resource::Stone) },
     ];
 
     pub const INDEX_IDLE: usize = 5;
@@ -47,3 +52,21 @@ pub mod consts {
 
 pub mod ocr;
 pub mod image_analyzer;
+pub mod icon_registry;
+pub mod hud_layout;
+pub mod calibration;
+pub mod corpus;
+pub mod digit_extraction;
+pub mod preprocessing;
+pub mod ocr_cache;
+pub mod game_state;
+pub mod game_state_tracker;
+pub mod alerts;
+pub mod i18n;
+pub mod match_stats;
+pub mod resource_float;
+pub mod worker_advisor;
+pub mod build_order;
+pub mod match_report;
+pub mod session;
+pub mod game_clock;
@@ -4,25 +4,29 @@
 #![feature(stmt_expr_attributes)]
 
 use crate::{
-    overlay_window_gtk::{OverlayConfig},
+    config::{AppConfig, CaptureMode, ConfigOverrides, ConfigWatcher},
+    image_analyzer::OCRModel,
     process_monitor::WaitForProcessResult,
     system_tray::{Base, Menu},
 };
 use anyhow::{anyhow, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use libappindicator_zbus::{tray, utils::Category};
 use log::{error, info};
 use std::sync::mpsc as std_mpsc;
 use tokio::{signal, task};
 
+mod config;
 mod dbus_portal_screen_cast;
 mod frame_processor;
+mod frame_recorder;
 mod image_analyzer;
 pub mod ocr;
 mod overlay_window_gtk;
 mod pipewire_stream;
 mod pixelbuf_wrapper;
 mod process_monitor;
+mod replay;
 mod system_menu;
 mod system_tray;
 mod utils;
@@ -32,28 +36,284 @@ use crate::{
     overlay_window_gtk::GuiCommand,
     pixelbuf_wrapper::PixelBufWrapperWithDroppedFramesTS,
 };
-pub use aoe4_overlay::consts;
+pub use aoe4_overlay::{
+    alerts, build_order, calibration, consts, corpus, digit_extraction, game_clock, game_state,
+    game_state_tracker, hud_layout, i18n, icon_registry, match_report, match_stats, ocr_cache,
+    preprocessing, session, worker_advisor,
+};
 
 /// AOE4 Overlay - Screen capture and overlay for Age of Empires IV
 #[derive(Parser, Debug)]
 #[command(name = "aoe4_overlay")]
 #[command(about = "Screen capture overlay for AoE4 on Wayland", long_about = None)]
 struct Args {
+    /// Configuration file [default: $XDG_CONFIG_HOME/aoe4_overlay/config.toml]. All following
+    /// options override the values of the configuration file.
+    #[arg(short = 'c', long)]
+    config: Option<std::path::PathBuf>,
+
     /// Capture mode: "monitor" for full screen, "window" for application window
-    #[arg(short = 'm', long, default_value = "window", value_parser = ["monitor", "window"])]
-    capture_mode: String,
+    #[arg(short = 'm', long, value_enum)]
+    capture_mode: Option<CaptureMode>,
 
-    /// No debug window, only show overlay
+    /// Show the debug window with the captured HUD and the recognized values
     #[arg(short = 'd', long, default_value_t = false)]
     debug_window: bool,
 
-    /// Process name to monitor (if set, capture only starts when this process is running)
-    #[arg(short = 'p', long, default_value = "RelicCardinal.")]
+    /// Process name to monitor (if not empty, capture only starts when this process is running)
+    #[arg(short = 'p', long)]
     process_name: Option<String>,
 
     /// Process check interval in milliseconds
-    #[arg(short = 'i', long, default_value = "3000")]
-    check_interval: u64,
+    #[arg(short = 'i', long)]
+    check_interval: Option<u64>,
+
+    /// Minimum time between two analyzed frames in milliseconds
+    #[arg(short = 'f', long)]
+    frame_interval: Option<u64>,
+
+    /// OCR engine: pp, onnx, onnx_par or template_matching
+    #[arg(long)]
+    ocr_engine: Option<OCRModel>,
+
+    /// HUD layout file (.toml or .json). Defaults to a builtin profile picked by frame size
+    #[arg(short = 'l', long)]
+    layout: Option<std::path::PathBuf>,
+
+    /// In-game UI scale (1.0 = 100%), overrides the value of the layout
+    #[arg(short = 'u', long)]
+    ui_scale: Option<f32>,
+
+    /// Build order file (.json in the format of the community build order overlays, or .toml)
+    #[arg(short = 'b', long)]
+    build_order: Option<std::path::PathBuf>,
+
+    /// Replay recorded frames (PNG/JPEG) from a directory instead of capturing the screen
+    #[arg(long)]
+    replay: Option<std::path::PathBuf>,
+
+    /// Replay speed relative to the original pace, 0 to process frames as fast as possible
+    #[arg(long, default_value_t = 1.0)]
+    replay_speed: f32,
+
+    /// Record the analyzed frames and their results to a directory
+    #[arg(long)]
+    record: Option<std::path::PathBuf>,
+
+    /// Only record the HUD panel instead of full frames
+    #[arg(long, default_value_t = false, requires = "record")]
+    record_hud_only: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Find the HUD in a screenshot via anchor icons and write the derived layout file
+    Calibrate {
+        /// Full-size screenshot or recorded frame of a running match (PNG/JPEG)
+        frame: std::path::PathBuf,
+
+        /// Layout file to write (.toml or .json)
+        #[arg(short = 'o', long, default_value = "layout.toml")]
+        output: std::path::PathBuf,
+    },
+    /// Cut digit templates for the template matching OCR from annotated screenshots. The stat
+    /// regions are preprocessed as configured in the configuration file.
+    ExtractDigits {
+        /// Annotation file in the format of src_images/annotations.toml
+        #[arg(default_value = "src_images/annotations.toml")]
+        annotations: std::path::PathBuf,
+
+        /// Template directory, existing templates are kept and new variants added
+        #[arg(short = 'o', long, default_value = "src_images/digits")]
+        output: std::path::PathBuf,
+
+        /// Minimum correlation with a template of the same character to drop a glyph as duplicate
+        #[arg(long, default_value_t = 0.95)]
+        duplicate_threshold: f64,
+
+        /// Minimum correlation of templates of different characters to warn about
+        #[arg(long, default_value_t = 0.85)]
+        ambiguity_threshold: f64,
+    },
+}
+
+/// Calibrate the HUD layout from a screenshot and write it to `output`
+fn run_calibration(frame: &std::path::Path, output: &std::path::Path) -> Result<()> {
+    let frame_path = frame.to_string_lossy();
+    let frame = opencv::imgcodecs::imread(&frame_path, opencv::imgcodecs::IMREAD_COLOR)?;
+    if opencv::prelude::MatTraitConst::empty(&frame) {
+        anyhow::bail!("Failed to load frame from {}", frame_path);
+    }
+
+    let calibrator = calibration::HudCalibrator::new(Default::default())?;
+    let calibration = calibrator.calibrate(&frame)?;
+    info!(
+        "Found {} of {} anchors, HUD panel at {:?} with scale {:.3}",
+        calibration.matches.len(),
+        calibration::ANCHORS.len(),
+        calibration.origin,
+        calibration.scale
+    );
+
+    calibration.layout.save(output)?;
+    info!("Layout written to {}, use it with --layout", output.display());
+    Ok(())
+}
+
+/// Extract digit templates from the annotated screenshots of `annotations` into `output`
+fn run_digit_extraction(
+    annotations: &std::path::Path,
+    output: &std::path::Path,
+    config: digit_extraction::ExtractionConfig,
+    preprocessing: &preprocessing::PreprocessConfig,
+) -> Result<()> {
+    let corpus = corpus::Corpus::load(annotations)?;
+    let report =
+        digit_extraction::extract_digit_templates(&corpus, output, &config, preprocessing)?;
+    info!(
+        "Wrote {} templates to {} ({} duplicates, {} regions skipped): {}",
+        report.written.len(),
+        output.display(),
+        report.duplicates,
+        report.skipped_regions,
+        report.written.join(", ")
+    );
+    if !report.ambiguities.is_empty() {
+        info!(
+            "{} ambiguous template pairs, consider removing the weaker variants",
+            report.ambiguities.len()
+        );
+    }
+    info!("The templates are embedded at build time, rebuild to use them");
+    Ok(())
+}
+
+/// Where the frames for the frame processor come from
+enum FrameSource {
+    /// Screen cast via the desktop portal and PipeWire
+    Capture {
+        process_monitor_quitter: tokio::sync::oneshot::Sender<()>,
+        pipewire_control_handler: pipewire_stream::PipeWireStopHandler,
+        pipewire_join_handler: task::JoinHandle<()>,
+        wayland_stop_handler: wayland_record::WaylandStopHandler,
+        process_monitor_handler: task::JoinHandle<()>,
+    },
+    /// Recorded frames, see [`replay`]
+    Replay(replay::ReplayStopHandler),
+}
+
+impl FrameSource {
+    async fn stop(self) -> Result<()> {
+        match self {
+            FrameSource::Capture {
+                process_monitor_quitter,
+                pipewire_control_handler,
+                pipewire_join_handler,
+                wayland_stop_handler,
+                process_monitor_handler,
+            } => {
+                let _ = process_monitor_quitter.send(());
+                pipewire_control_handler.stop();
+                pipewire_join_handler.await.map_err(|_| anyhow!("Failed to join pipewire thread"))?;
+                wayland_stop_handler.stop().await;
+                process_monitor_handler.await?;
+            }
+            FrameSource::Replay(replay_stop_handler) => {
+                task::spawn_blocking(move || replay_stop_handler.stop()).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Start the process monitor, the Wayland recorder and the PipeWire stream. Also returns whether
+/// the overlay waits for the monitored process.
+async fn start_capture(
+    config: &AppConfig,
+    pipewire_sender: std_mpsc::SyncSender<bool>,
+    pixelbuf_content: PixelBufWrapperWithDroppedFramesTS,
+    config_receiver: tokio::sync::watch::Receiver<AppConfig>,
+    gtk_sender_clone: tokio::sync::mpsc::Sender<GuiCommand>,
+) -> Result<(FrameSource, bool)> {
+    // Determine record type based on capture mode
+    let record_type = match config.capture.mode {
+        CaptureMode::Window => wayland_record::RecordTypes::Window,
+        CaptureMode::Monitor => wayland_record::RecordTypes::Monitor,
+    };
+
+    let (mut process_monitor, process_monitor_quitter) = process_monitor::ProcessMonitor::new(
+        config.capture.process_name.clone(),
+        config.capture.check_interval_ms,
+    );
+
+    // Start the Wayland recorder
+    let mut wayland_recorder = wayland_record::WaylandRecorder::new("aoe4_screen2").await?;
+
+    // Start PipeWire stream
+    let (pipewire_control_handler, pipewire_join_handler) =
+        pipewire_stream::run(pipewire_sender, pixelbuf_content, config_receiver);
+
+    let gtk_sender = gtk_sender_clone.clone();
+    let pipewire_join_handler = tokio::spawn(async move {
+        let _ = task::spawn_blocking(move || {
+            let _ = pipewire_join_handler.join().map_err(|_| anyhow!("Failed to join pipewire thread"));
+        })
+            .await;
+        let _ = gtk_sender.try_send(GuiCommand::Quit);
+    });
+
+    let enable_waiting = process_monitor.armed;
+
+    let wayland_stop_handler = wayland_recorder.get_stop_handler();
+
+    let gtk_sender = gtk_sender_clone;
+    let pipewire_sender_frames = pipewire_control_handler.get_frame_sender();
+    let process_monitor_handler = tokio::spawn(async move {
+        if process_monitor.armed {
+            info!("Waiting for process {}", process_monitor.process_name);
+        }
+        if let WaitForProcessResult::ProcessFound = process_monitor
+            .act_on_process(process_monitor::WaitForProcessTask::WaitForProcess)
+            .await
+        {
+            let _ = gtk_sender.try_send(GuiCommand::AboutToProcessFrames);
+            if let Err(e) = wayland_recorder
+                .run(
+                    record_type,
+                    wayland_record::CursorModeTypes::Hidden,
+                    pipewire_sender_frames,
+                )
+                .await
+            {
+                let _ = gtk_sender.try_send(GuiCommand::Quit);
+                error!("Failed to start Wayland recorder: {}", e);
+            }
+        }
+
+        if process_monitor.armed {
+            if let WaitForProcessResult::ProcessNotFound = process_monitor
+                .act_on_process(process_monitor::WaitForProcessTask::WaitForProcessEnd)
+                .await
+            {
+                info!("Monitored process ended, shutting down...");
+                let _ = gtk_sender.try_send(GuiCommand::Quit);
+            }
+        }
+    });
+
+    Ok((
+        FrameSource::Capture {
+            process_monitor_quitter,
+            pipewire_control_handler,
+            pipewire_join_handler,
+            wayland_stop_handler,
+            process_monitor_handler,
+        },
+        enable_waiting,
+    ))
 }
 
 #[tokio::main]
@@ -65,33 +325,68 @@ async fn main() -> Result<()> {
 
     let args = Args::parse();
 
-    if !utils::is_wayland() {
-        anyhow::bail!("This program only works in a Wayland session.");
+    match &args.command {
+        Some(Command::Calibrate { frame, output }) => return run_calibration(frame, output),
+        Some(Command::ExtractDigits {
+            annotations,
+            output,
+            duplicate_threshold,
+            ambiguity_threshold,
+        }) => {
+            let config = digit_extraction::ExtractionConfig {
+                duplicate_threshold: *duplicate_threshold,
+                ambiguity_threshold: *ambiguity_threshold,
+                ..Default::default()
+            };
+            // The regions are preprocessed like by the analyzer of the configuration file
+            let config_path = args.config.clone().unwrap_or_else(AppConfig::default_path);
+            let preprocessing = if config_path.exists() {
+                AppConfig::load(&config_path)?.analysis.preprocessing
+            } else {
+                Default::default()
+            };
+            return run_digit_extraction(annotations, output, config, &preprocessing);
+        }
+        None => {}
     }
 
-    // Determine record type based on capture mode
-    let record_type = match args.capture_mode.as_str() {
-        "window" => wayland_record::RecordTypes::Window,
-        "monitor" => wayland_record::RecordTypes::Monitor,
-        _ => wayland_record::RecordTypes::Monitor,
-    };
+    if args.replay.is_none() && !utils::is_wayland() {
+        anyhow::bail!("This program only works in a Wayland session.");
+    }
 
-    // Create overlay configuration
-    let overlay_config = OverlayConfig {
-        show_debug_window: args.debug_window,
+    // Load the configuration file and apply the command line on top of it
+    let config_path = args.config.clone().unwrap_or_else(AppConfig::default_path);
+    let overrides = ConfigOverrides {
+        capture_mode: args.capture_mode,
+        process_name: args.process_name.clone(),
+        check_interval_ms: args.check_interval,
+        frame_interval_ms: args.frame_interval,
+        ocr_engine: args.ocr_engine,
+        layout: args.layout.clone(),
+        ui_scale: args.ui_scale,
+        build_order: args.build_order.clone(),
+        show_debug_window: args.debug_window.then_some(true),
     };
+    let mut config = AppConfig::load_or_create(&config_path)?;
+    overrides.apply(&mut config);
+    i18n::init(config.language.as_deref());
+    let config_receiver = ConfigWatcher::spawn(config_path.clone(), overrides, config.clone());
 
     info!(
-        "Starting AOE4 Overlay with configuration: {:?}",
-        overlay_config
+        "Starting AOE4 Overlay with configuration from {}: {:?}",
+        config_path.display(),
+        config
     );
-    info!("Capture mode: {}", args.capture_mode);
+    match &args.replay {
+        Some(replay_dir) => info!("Replaying frames from {}", replay_dir.display()),
+        None => info!("Capture mode: {:?}", config.capture.mode),
+    }
 
 
     let _connection = tray(
         Base::boot,
         "com.aoe4.overlay.tray",
-        "Age of Empires IV Overlay",
+        &i18n::tr("tray.title"),
         Menu::boot,
         Menu::menu,
         1,
@@ -107,13 +402,19 @@ async fn main() -> Result<()> {
 
     // Start frame processor
     info!("Initializing frame processor...");
-    let frame_processor = match frame_processor::FrameProcessor::new() {
-        Ok(processor) => processor,
-        Err(e) => {
-            error!("Failed to initialize frame processor: {}", e);
-            anyhow::bail!("Frame processor initialization failed: {}", e);
-        }
-    };
+    let recorder = args
+        .record
+        .clone()
+        .map(|dir| frame_recorder::FrameRecorder::new(dir, args.record_hud_only))
+        .transpose()?;
+    let frame_processor =
+        match frame_processor::FrameProcessor::new(config_receiver.clone(), recorder) {
+            Ok(processor) => processor,
+            Err(e) => {
+                error!("Failed to initialize frame processor: {}", e);
+                anyhow::bail!("Frame processor initialization failed: {}", e);
+            }
+        };
 
     // Create std_mpsc channel for GTK (since GTK needs to run in its own thread)
     let (gtk_sender, gtk_receiver) = tokio::sync::mpsc::channel::<GuiCommand>(2);
@@ -138,28 +439,29 @@ async fn main() -> Result<()> {
         let _ = gtk_sender.try_send(GuiCommand::Quit);
     });
 
-    let (mut process_monitor, process_monitor_quitter) = process_monitor::ProcessMonitor::new(
-        args.process_name.unwrap_or_default(),
-        args.check_interval,
-    );
-
-    // Start the Wayland recorder
-    let mut wayland_recorder = wayland_record::WaylandRecorder::new("aoe4_screen2").await?;
-
-    // Start PipeWire stream
-    let (pipewire_control_handler, pipewire_join_handler) =
-        pipewire_stream::run(pipewire_sender, pixelbuf_content_clone);
-
-    let gtk_sender = gtk_sender_clone.clone();
-    let pipewire_join_handler = tokio::spawn(async move {
-        let _ = task::spawn_blocking(move || {
-            let _ = pipewire_join_handler.join().map_err(|_| anyhow!("Failed to join pipewire thread"));
-        })
-            .await;
-        let _ = gtk_sender.try_send(GuiCommand::Quit);
-    });
-
-    let enable_waiting = process_monitor.armed;
+    // Feed frames either from recorded files or from the screen cast
+    let (frame_source, enable_waiting) = if let Some(replay_dir) = &args.replay {
+        let frames = replay::load_frames(
+            replay_dir,
+            std::time::Duration::from_millis(config.capture.frame_interval_ms),
+        )?;
+        let replay_stop_handler = replay::run(
+            frames,
+            args.replay_speed.max(0.0),
+            pipewire_sender,
+            pixelbuf_content_clone,
+        );
+        (FrameSource::Replay(replay_stop_handler), false)
+    } else {
+        start_capture(
+            &config,
+            pipewire_sender,
+            pixelbuf_content_clone,
+            config_receiver.clone(),
+            gtk_sender_clone.clone(),
+        )
+        .await?
+    };
 
     let gtk_sender = gtk_sender_clone.clone();
     tokio::spawn(async move {
@@ -174,47 +476,10 @@ async fn main() -> Result<()> {
         }
     });
 
-    let wayland_stop_handler = wayland_recorder.get_stop_handler();
-
-    let gtk_sender = gtk_sender_clone.clone();
-    let pipewire_sender_frames = pipewire_control_handler.get_frame_sender();
-    let process_monitor_handler = tokio::spawn(async move {
-        if process_monitor.armed {
-            info!("Waiting for process {}", process_monitor.process_name);
-        }
-        if let WaitForProcessResult::ProcessFound = process_monitor
-            .act_on_process(process_monitor::WaitForProcessTask::WaitForProcess)
-            .await
-        {
-            let _ = gtk_sender.try_send(GuiCommand::AboutToProcessFrames);
-            if let Err(e) = wayland_recorder
-                .run(
-                    record_type,
-                    wayland_record::CursorModeTypes::Hidden,
-                    pipewire_sender_frames,
-                )
-                .await
-            {
-                let _ = gtk_sender.try_send(GuiCommand::Quit);
-                error!("Failed to start Wayland recorder: {}", e);
-            }
-        }
-
-        if process_monitor.armed {
-            if let WaitForProcessResult::ProcessNotFound = process_monitor
-                .act_on_process(process_monitor::WaitForProcessTask::WaitForProcessEnd)
-                .await
-            {
-                info!("Monitored process ended, shutting down...");
-                let _ = gtk_sender.try_send(GuiCommand::Quit);
-            }
-        }
-    });
-
     match overlay_window_gtk::run(
         gtk_sender_clone,
         gtk_receiver,
-        overlay_config,
+        config_receiver,
         enable_waiting,
     )
         .await
@@ -227,11 +492,7 @@ async fn main() -> Result<()> {
         }
     }
 
-    let _ = process_monitor_quitter.send(());
-    pipewire_control_handler.stop();
-    pipewire_join_handler.await.map_err(|_| anyhow!("Failed to join pipewire thread"))?;
-    wayland_stop_handler.stop().await;
-    let _ = process_monitor_handler.await?;
+    frame_source.stop().await?;
     let _ = processor_join_handle.await;
     Ok(())
 }
@@ -0,0 +1,546 @@
+// Report of a finished match with timelines of the game state and alert statistics
+
+use crate::{
+    alerts::Alert,
+    game_state::{GameState, Reading, Resource},
+    i18n,
+    match_stats::{MatchStats, format_duration},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// Single page with SVG charts
+    #[default]
+    Html,
+    /// Tables only, one timeline row per minute
+    Markdown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportConfig {
+    pub enabled: bool,
+    pub format: ReportFormat,
+    /// Directory of the reports [default: $XDG_DATA_HOME/aoe4_overlay/reports]
+    pub dir: Option<PathBuf>,
+    /// Time between two timeline samples, in milliseconds of match time
+    pub sample_interval_ms: u64,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            format: ReportFormat::Html,
+            dir: None,
+            sample_interval_ms: 5000,
+        }
+    }
+}
+
+/// Game state at a point of the match
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimelineSample {
+    /// Match time, see `MatchStats::duration_ms`
+    pub time_ms: u64,
+    pub state: GameState,
+}
+
+/// How often and how long an alert was shown
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AlertSummary {
+    pub name: String,
+    pub count: u32,
+    pub total_ms: u64,
+    pub longest_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchReport {
+    /// Start of the match as local time, e.g. "2025-01-31 20:15:00"
+    pub started: String,
+    pub stats: MatchStats,
+    pub timeline: Vec<TimelineSample>,
+    /// Sorted by total duration, longest first
+    pub alerts: Vec<AlertSummary>,
+}
+
+/// Collects the timeline and alert statistics of the running match
+#[derive(Debug, Clone, Default)]
+pub struct MatchReportBuilder {
+    sample_interval_ms: u64,
+    timeline: Vec<TimelineSample>,
+    alerts: BTreeMap<String, AlertSummary>,
+    /// Active alerts and the match time at which they were raised
+    active_alerts: BTreeMap<String, u64>,
+    /// Match time of the last update
+    last_time_ms: u64,
+}
+
+impl MatchReportBuilder {
+    pub fn new(config: &ReportConfig) -> Self {
+        Self {
+            sample_interval_ms: config.sample_interval_ms,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_config(&mut self, config: &ReportConfig) {
+        self.sample_interval_ms = config.sample_interval_ms;
+    }
+
+    /// Add the state and alerts of a frame at the given match time. A time before the previous
+    /// update, e.g. when the match timer becomes readable again after the capture time was used,
+    /// counts as the previous time, so the timeline never goes back.
+    pub fn update(&mut self, time_ms: u64, state: &GameState, alerts: &[Alert]) {
+        let time_ms = time_ms.max(self.last_time_ms);
+        self.last_time_ms = time_ms;
+        if self
+            .timeline
+            .last()
+            .is_none_or(|last| time_ms >= last.time_ms + self.sample_interval_ms)
+        {
+            self.timeline.push(TimelineSample {
+                time_ms,
+                state: *state,
+            });
+        }
+
+        for alert in alerts {
+            if !self.active_alerts.contains_key(&alert.name) {
+                self.active_alerts.insert(alert.name.clone(), time_ms);
+                let summary = self.alerts.entry(alert.name.clone()).or_default();
+                summary.name = alert.name.clone();
+                summary.count += 1;
+            }
+        }
+        let ended: Vec<String> = self
+            .active_alerts
+            .keys()
+            .filter(|name| !alerts.iter().any(|alert| &alert.name == *name))
+            .cloned()
+            .collect();
+        for name in ended {
+            self.end_alert(&name, time_ms);
+        }
+    }
+
+    fn end_alert(&mut self, name: &str, time_ms: u64) {
+        let Some(since_ms) = self.active_alerts.remove(name) else {
+            return;
+        };
+        let duration_ms = time_ms.saturating_sub(since_ms);
+        if let Some(summary) = self.alerts.get_mut(name) {
+            summary.total_ms += duration_ms;
+            summary.longest_ms = summary.longest_ms.max(duration_ms);
+        }
+    }
+
+    /// Create the report and start collecting for the next match
+    pub fn finish(&mut self, started: String, stats: MatchStats) -> MatchReport {
+        let names: Vec<String> = self.active_alerts.keys().cloned().collect();
+        for name in names {
+            self.end_alert(&name, self.last_time_ms);
+        }
+        let mut alerts: Vec<AlertSummary> = std::mem::take(&mut self.alerts).into_values().collect();
+        alerts.sort_by_key(|alert| std::cmp::Reverse(alert.total_ms));
+        self.last_time_ms = 0;
+        MatchReport {
+            started,
+            stats,
+            timeline: std::mem::take(&mut self.timeline),
+            alerts,
+        }
+    }
+}
+
+/// A line of a chart or a column of the timeline table
+struct Series {
+    /// Key of the translation catalogs
+    name: &'static str,
+    color: &'static str,
+    value: fn(&GameState) -> Reading<u32>,
+}
+
+const fn resource_series(resource: Resource, name: &'static str, color: &'static str) -> Series {
+    let value: fn(&GameState) -> Reading<u32> = match resource {
+        Resource::Food => |state| state.resources.food,
+        Resource::Wood => |state| state.resources.wood,
+        Resource::Gold => |state| state.resources.gold,
+        Resource::Stone => |state| state.resources.stone,
+    };
+    Series { name, color, value }
+}
+
+const fn worker_series(resource: Resource, name: &'static str, color: &'static str) -> Series {
+    let value: fn(&GameState) -> Reading<u32> = match resource {
+        Resource::Food => |state| state.workers.food,
+        Resource::Wood => |state| state.workers.wood,
+        Resource::Gold => |state| state.workers.gold,
+        Resource::Stone => |state| state.workers.stone,
+    };
+    Series { name, color, value }
+}
+
+const POPULATION: Series = Series {
+    name: "report.population",
+    color: "#1f77b4",
+    value: |state| state.population.known().map(|p| p.current).into(),
+};
+const POPULATION_CAP: Series = Series {
+    name: "report.population_cap",
+    color: "#aec7e8",
+    value: |state| state.population.known().map(|p| p.cap).into(),
+};
+const VILLAGERS: Series = Series {
+    name: "report.villagers",
+    color: "#2ca02c",
+    value: GameState::villagers,
+};
+const IDLE_VILLAGERS: Series = Series {
+    name: "report.idle_villagers",
+    color: "#d62728",
+    value: |state| state.idle_villagers,
+};
+
+/// Catalog key of the title and the series of every chart
+const CHARTS: [(&str, &[Series]); 4] = [
+    ("report.population", &[POPULATION, POPULATION_CAP]),
+    ("report.villagers", &[VILLAGERS, IDLE_VILLAGERS]),
+    (
+        "report.resources",
+        &[
+            resource_series(Resource::Food, "stat.food", "#e6550d"),
+            resource_series(Resource::Wood, "stat.wood", "#8c6d31"),
+            resource_series(Resource::Gold, "stat.gold", "#e7ba52"),
+            resource_series(Resource::Stone, "stat.stone", "#7f7f7f"),
+        ],
+    ),
+    (
+        "report.workers",
+        &[
+            worker_series(Resource::Food, "stat.food_worker", "#e6550d"),
+            worker_series(Resource::Wood, "stat.wood_worker", "#8c6d31"),
+            worker_series(Resource::Gold, "stat.gold_worker", "#e7ba52"),
+            worker_series(Resource::Stone, "stat.stone_worker", "#7f7f7f"),
+        ],
+    ),
+];
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 200.0;
+const CHART_MARGIN: f64 = 40.0;
+
+impl MatchReport {
+    /// File name without extension, e.g. "match_2025-01-31_20-15-00"
+    pub fn file_stem(&self) -> String {
+        let started: String = self
+            .started
+            .chars()
+            .map(|c| match c {
+                ' ' => '_',
+                c if c.is_ascii_alphanumeric() => c,
+                _ => '-',
+            })
+            .collect();
+        format!("match_{}", started)
+    }
+
+    /// Write the report to `dir` and return its path. Existing reports are kept, the file name
+    /// gets a number instead, e.g. "match_unknown_2.html".
+    pub fn save(&self, dir: &Path, format: ReportFormat) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create report directory {}", dir.display()))?;
+        let (content, extension) = match format {
+            ReportFormat::Html => (self.to_html(), "html"),
+            ReportFormat::Markdown => (self.to_markdown(), "md"),
+        };
+        let stem = self.file_stem();
+        let mut number = 1;
+        loop {
+            let path = match number {
+                1 => dir.join(format!("{}.{}", stem, extension)),
+                _ => dir.join(format!("{}_{}.{}", stem, number, extension)),
+            };
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    std::io::Write::write_all(&mut file, content.as_bytes())
+                        .with_context(|| format!("Failed to write report {}", path.display()))?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => number += 1,
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to create report {}", path.display()));
+                }
+            }
+        }
+    }
+
+    fn summary_rows(&self) -> Vec<(String, String)> {
+        let mut rows = vec![
+            (
+                i18n::tr("report.duration"),
+                format_duration(self.stats.duration_ms),
+            ),
+            (
+                i18n::tr("stats.tc_idle"),
+                format!(
+                    "{} ({:.0}%)",
+                    format_duration(self.stats.tc_idle_ms),
+                    self.stats.tc_idle_share() * 100.0
+                ),
+            ),
+        ];
+        for phase in &self.stats.phases {
+            rows.push((
+                format!(
+                    "{} {}",
+                    i18n::tr("stats.tc_idle"),
+                    i18n::tr_or_literal(&phase.name)
+                ),
+                format!(
+                    "{} {} {}",
+                    format_duration(phase.tc_idle_ms),
+                    i18n::tr("report.of"),
+                    format_duration(phase.duration_ms)
+                ),
+            ));
+        }
+        rows
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# {} {}\n", i18n::tr("report.match"), self.started);
+        let _ = writeln!(out, "| | |\n|---|---|");
+        for (name, value) in self.summary_rows() {
+            let _ = writeln!(
+                out,
+                "| {} | {} |",
+                escape_markdown_cell(&name),
+                escape_markdown_cell(&value)
+            );
+        }
+
+        let _ = writeln!(out, "\n## {}\n", i18n::tr("report.alerts"));
+        if self.alerts.is_empty() {
+            let _ = writeln!(out, "{}", i18n::tr("report.no_alerts"));
+        } else {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} |\n|---|---|---|---|",
+                i18n::tr("report.alert"),
+                i18n::tr("report.count"),
+                i18n::tr("report.total"),
+                i18n::tr("report.longest")
+            );
+            for alert in &self.alerts {
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {} | {} |",
+                    escape_markdown_cell(&alert.name),
+                    alert.count,
+                    format_duration(alert.total_ms),
+                    format_duration(alert.longest_ms)
+                );
+            }
+        }
+
+        let _ = writeln!(out, "\n## {}\n", i18n::tr("report.timeline"));
+        let columns: Vec<&Series> = CHARTS.iter().flat_map(|(_, series)| series.iter()).collect();
+        let header: Vec<String> = columns
+            .iter()
+            .map(|series| escape_markdown_cell(&i18n::tr(series.name)))
+            .collect();
+        let _ = writeln!(
+            out,
+            "| {} | {} |",
+            i18n::tr("report.time"),
+            header.join(" | ")
+        );
+        let _ = writeln!(out, "|---|{}", "---|".repeat(columns.len()));
+        let mut next_minute_ms = 0;
+        for sample in &self.timeline {
+            if sample.time_ms < next_minute_ms {
+                continue;
+            }
+            next_minute_ms = (sample.time_ms / 60_000 + 1) * 60_000;
+            let values: Vec<String> = columns
+                .iter()
+                .map(|series| (series.value)(&sample.state).to_string())
+                .collect();
+            let _ = writeln!(
+                out,
+                "| {} | {} |",
+                format_duration(sample.time_ms),
+                values.join(" | ")
+            );
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{match_} {started}</title>\n\
+             <style>body {{ font-family: sans-serif; margin: 2em; }} \
+             table {{ border-collapse: collapse; }} \
+             td, th {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; }} \
+             svg {{ display: block; margin-bottom: 1em; }}</style>\n</head>\n<body>\n\
+             <h1>{match_} {started}</h1>",
+            match_ = escape_html(&i18n::tr("report.match")),
+            started = escape_html(&self.started)
+        );
+
+        let _ = writeln!(out, "<table>");
+        for (name, value) in self.summary_rows() {
+            let _ = writeln!(
+                out,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                escape_html(&name),
+                escape_html(&value)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "</table>\n<h2>{}</h2>",
+            escape_html(&i18n::tr("report.alerts"))
+        );
+        if self.alerts.is_empty() {
+            let _ = writeln!(out, "<p>{}</p>", escape_html(&i18n::tr("report.no_alerts")));
+        } else {
+            let _ = writeln!(
+                out,
+                "<table>\n<tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr>",
+                escape_html(&i18n::tr("report.alert")),
+                escape_html(&i18n::tr("report.count")),
+                escape_html(&i18n::tr("report.total")),
+                escape_html(&i18n::tr("report.longest"))
+            );
+            for alert in &self.alerts {
+                let _ = writeln!(
+                    out,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape_html(&alert.name),
+                    alert.count,
+                    format_duration(alert.total_ms),
+                    format_duration(alert.longest_ms)
+                );
+            }
+            let _ = writeln!(out, "</table>");
+        }
+
+        let _ = writeln!(
+            out,
+            "<h2>{}</h2>",
+            escape_html(&i18n::tr("report.timeline"))
+        );
+        for (title, series) in CHARTS {
+            let _ = writeln!(out, "<h3>{}</h3>", escape_html(&i18n::tr(title)));
+            out.push_str(&self.svg_chart(series));
+        }
+        let _ = writeln!(out, "</body>\n</html>");
+        out
+    }
+
+    /// Line chart of the given series over the match time. Unknown values interrupt the lines.
+    fn svg_chart(&self, series: &[Series]) -> String {
+        let duration_ms = self.timeline.last().map_or(0, |s| s.time_ms).max(1);
+        let max_value = series
+            .iter()
+            .flat_map(|s| self.timeline.iter().filter_map(|sample| (s.value)(&sample.state).known()))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let plot_width = CHART_WIDTH - 2.0 * CHART_MARGIN;
+        let plot_height = CHART_HEIGHT - 2.0 * CHART_MARGIN;
+        let x = |time_ms: u64| CHART_MARGIN + time_ms as f64 / duration_ms as f64 * plot_width;
+        let y = |value: u32| CHART_MARGIN + plot_height - value as f64 / max_value as f64 * plot_height;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            "<svg width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" xmlns=\"http://www.w3.org/2000/svg\">",
+            w = CHART_WIDTH,
+            h = CHART_HEIGHT
+        );
+        // Axes with the maximum value and one tick per 5 minutes
+        let _ = writeln!(
+            svg,
+            "<path d=\"M{l} {t} V{b} H{r}\" stroke=\"#333\" fill=\"none\"/>\
+             <text x=\"{tx}\" y=\"{t}\" font-size=\"11\" text-anchor=\"end\">{max}</text>\
+             <text x=\"{tx}\" y=\"{b}\" font-size=\"11\" text-anchor=\"end\">0</text>",
+            l = CHART_MARGIN,
+            t = CHART_MARGIN,
+            b = CHART_MARGIN + plot_height,
+            r = CHART_MARGIN + plot_width,
+            tx = CHART_MARGIN - 4.0,
+            max = max_value
+        );
+        for minute in (0..=duration_ms / 60_000).step_by(5) {
+            let _ = writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"11\" text-anchor=\"middle\">{}:00</text>",
+                x(minute * 60_000),
+                CHART_MARGIN + plot_height + 14.0,
+                minute
+            );
+        }
+
+        for (index, s) in series.iter().enumerate() {
+            let mut path = String::new();
+            let mut pen_down = false;
+            for sample in &self.timeline {
+                match (s.value)(&sample.state) {
+                    Reading::Known(value) => {
+                        let command = if pen_down { 'L' } else { 'M' };
+                        let _ = write!(path, "{}{:.1} {:.1} ", command, x(sample.time_ms), y(value));
+                        pen_down = true;
+                    }
+                    Reading::Unknown => pen_down = false,
+                }
+            }
+            let _ = writeln!(
+                svg,
+                "<path d=\"{}\" stroke=\"{}\" stroke-width=\"2\" fill=\"none\"/>\
+                 <text x=\"{:.1}\" y=\"{:.1}\" font-size=\"12\" fill=\"{}\">{}</text>",
+                path.trim_end(),
+                s.color,
+                CHART_MARGIN + index as f64 * 120.0,
+                CHART_MARGIN - 12.0,
+                s.color,
+                escape_html(&i18n::tr(s.name))
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Text usable in a cell of a Markdown table
+fn escape_markdown_cell(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace(['\n', '\r'], " ")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,218 @@
+// Per-match statistics, currently the idle time of villager production
+
+use crate::game_state::{Age, GameState};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Section of a match the statistics are broken down by
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GamePhase {
+    /// Key of the translation catalogs (e.g. `phase.opening`) or literal text
+    pub name: String,
+    /// Match time in minutes at which the phase ends, not set for the last phase
+    #[serde(default)]
+    pub until_min: Option<f32>,
+    /// Age the phase stands for. While the age is detected it selects the phase instead of the
+    /// match time.
+    #[serde(default)]
+    pub age: Option<Age>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Phases in match order
+    pub phases: Vec<GamePhase>,
+    /// Longer gaps between two frames, e.g. while the game was minimized, are counted only up to
+    /// this long, in milliseconds
+    pub max_frame_gap_ms: u64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        let phase = |name: &str, until_min, age| GamePhase {
+            name: name.to_string(),
+            until_min,
+            age: Some(age),
+        };
+        Self {
+            phases: vec![
+                phase("phase.opening", Some(5.0), Age::Dark),
+                phase("phase.early", Some(12.0), Age::Feudal),
+                phase("phase.mid", Some(25.0), Age::Castle),
+                phase("phase.late", None, Age::Imperial),
+            ],
+            max_frame_gap_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseStats {
+    pub name: String,
+    pub duration_ms: u64,
+    pub tc_idle_ms: u64,
+}
+
+/// Statistics of one match
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchStats {
+    /// Match time in which the HUD was visible
+    pub duration_ms: u64,
+    /// Time in which no villager was in production
+    pub tc_idle_ms: u64,
+    /// Phases reached so far, in match order
+    pub phases: Vec<PhaseStats>,
+}
+
+impl MatchStats {
+    /// Share of the match time in which no villager was in production, 0 - 1
+    pub fn tc_idle_share(&self) -> f32 {
+        if self.duration_ms == 0 {
+            return 0.0;
+        }
+        self.tc_idle_ms as f32 / self.duration_ms as f32
+    }
+
+    fn phase_mut(&mut self, name: &str) -> &mut PhaseStats {
+        match self.phases.iter().position(|phase| phase.name == name) {
+            Some(index) => &mut self.phases[index],
+            None => {
+                self.phases.push(PhaseStats {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.phases.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+impl fmt::Display for MatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "duration {}, TC idle {} ({:.0}%)",
+            format_duration(self.duration_ms),
+            format_duration(self.tc_idle_ms),
+            self.tc_idle_share() * 100.0
+        )?;
+        for phase in &self.phases {
+            write!(
+                f,
+                ", {} {} of {}",
+                phase.name,
+                format_duration(phase.tc_idle_ms),
+                format_duration(phase.duration_ms)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Format milliseconds as "m:ss"
+pub fn format_duration(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Adds up the statistics of the current match. Matches are started and ended by the session
+/// detection, see [`crate::session::SessionDetector`].
+#[derive(Debug, Clone, Default)]
+pub struct MatchStatsTracker {
+    config: StatsConfig,
+    current: Option<MatchStats>,
+    last_timestamp_ms: u64,
+    /// Last detected age of the running match
+    age: Option<Age>,
+    /// Last game time of the match timer
+    game_time_ms: Option<u64>,
+}
+
+impl MatchStatsTracker {
+    pub fn new(config: StatsConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_config(&mut self, config: &StatsConfig) {
+        self.config = config.clone();
+    }
+
+    /// Statistics of the running match
+    pub fn current(&self) -> Option<&MatchStats> {
+        self.current.as_ref()
+    }
+
+    /// Phase of the running match
+    pub fn current_phase(&self) -> Option<&str> {
+        self.current
+            .as_ref()
+            .map(|stats| self.phase(self.game_time_ms.unwrap_or(stats.duration_ms), self.age))
+    }
+
+    /// Start a new match, the statistics of a running match are discarded
+    pub fn start(&mut self, timestamp_ms: u64) {
+        self.current = Some(MatchStats::default());
+        self.last_timestamp_ms = timestamp_ms;
+        self.age = None;
+        self.game_time_ms = None;
+    }
+
+    /// End the running match and return its statistics
+    pub fn finish(&mut self) -> Option<MatchStats> {
+        self.current.take()
+    }
+
+    /// Phase of the age if it is known and has a phase, otherwise the phase at the given match
+    /// time
+    fn phase(&self, time_ms: u64, age: Option<Age>) -> &str {
+        let phases = &self.config.phases;
+        let minutes = time_ms as f32 / 60_000.0;
+        age.and_then(|age| phases.iter().find(|phase| phase.age == Some(age)))
+            .or_else(|| {
+                phases
+                    .iter()
+                    .find(|phase| phase.until_min.is_none_or(|until_min| minutes < until_min))
+            })
+            .or(phases.last())
+            .map_or("", |phase| phase.name.as_str())
+    }
+
+    /// Add an in-game frame captured at `timestamp_ms`. Frames with an unreadable HUD, e.g. while
+    /// the game menu is open, do not count towards the match time. `game_time_ms` is the time of
+    /// the match timer, which selects the phase instead of the counted match time while it is
+    /// known, e.g. for a match joined late or a replay.
+    pub fn update(&mut self, state: &GameState, game_time_ms: Option<u64>, timestamp_ms: u64) {
+        let Some(stats) = self.current.as_ref() else {
+            return;
+        };
+        let elapsed_ms = timestamp_ms
+            .saturating_sub(self.last_timestamp_ms)
+            .min(self.config.max_frame_gap_ms);
+        self.last_timestamp_ms = timestamp_ms;
+        if !state.population.is_known() {
+            return;
+        }
+        if let Some(age) = state.age.known() {
+            self.age = Some(age);
+        }
+        if game_time_ms.is_some() {
+            self.game_time_ms = game_time_ms;
+        }
+
+        let idle = !state.villager_in_production;
+        let time_ms = self.game_time_ms.unwrap_or(stats.duration_ms);
+        let phase = self.phase(time_ms, self.age).to_string();
+        let stats = self.current.as_mut().unwrap();
+        stats.duration_ms += elapsed_ms;
+        let phase = stats.phase_mut(&phase);
+        phase.duration_ms += elapsed_ms;
+        if idle {
+            phase.tc_idle_ms += elapsed_ms;
+            stats.tc_idle_ms += elapsed_ms;
+        }
+    }
+}
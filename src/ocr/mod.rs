@@ -38,6 +38,18 @@ pub enum OcrEngineWrapper {
     // Fallback(fallback_ocr::FallbackOcrEngine),
 }
 
+impl OcrEngineWrapper {
+    /// Update the template matching thresholds. Other engines have no thresholds to update.
+    pub fn set_template_matching_config(
+        &mut self,
+        config: &template_matching_ocr::TemplateMatchingConfig,
+    ) {
+        if let OcrEngineWrapper::TemplateMatching(engine) = self {
+            engine.set_config(config.clone());
+        }
+    }
+}
+
 impl OcrEngine for OcrEngineWrapper {
     fn recognize_text<const N: usize>(
         &mut self,
@@ -58,15 +58,14 @@ impl OcrEngine for OnnxOcrEngine {
         let ocr_results = self.predictor.predict(subviews, None)?;
 
         let mut detected_texts: [fixedstr::str8; N] = [fixedstr::str8::new(); N];
-        for i in 0..detected_texts.len() {
-            let ocr_result = &ocr_results.rec_text[i];
-
+        // One result per region, the regions may be fewer than N
+        for (i, ocr_result) in ocr_results.rec_text.iter().enumerate().take(N) {
             if ocr_result.is_empty() {
                 continue;
             }
 
-            // Only accept numeric results with '/' character
-            if ocr_result.chars().all(|c| c.is_ascii_digit() || c == '/') {
+            // Only accept numeric results with '/' or the ':' of the match timer
+            if ocr_result.chars().all(|c| c.is_ascii_digit() || "/:".contains(c)) {
                 detected_texts[i] = ocr_result.as_str().into();
             }
         }
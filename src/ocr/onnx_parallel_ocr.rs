@@ -65,9 +65,10 @@ impl OcrEngine for OnnxParallelOcrEngine {
                 if let Ok(results) = ocr_results {
                     let ocr_result = &results.rec_text[0];
 
-                    // Only accept numeric results with '/' character and good confidence
+                    // Only accept numeric results with '/' or the ':' of the match timer and good
+                    // confidence
                     if !ocr_result.is_empty()
-                        && ocr_result.chars().all(|c| c.is_ascii_digit() || c == '/')
+                        && ocr_result.chars().all(|c| c.is_ascii_digit() || "/:".contains(c))
                         && results.rec_score[0] > 0.5
                     {
                         *entry = ocr_result.as_str().into();
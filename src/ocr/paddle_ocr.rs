@@ -45,8 +45,8 @@ impl OcrEngine for PaddleOcrEngine {
                 continue;
             }
 
-            // Only accept numeric results with '/' character
-            if text.chars().all(|c| c.is_ascii_digit() || c == '/') && confidence > 0.5 {
+            // Only accept numeric results with '/' or the ':' of the match timer
+            if text.chars().all(|c| c.is_ascii_digit() || "/:".contains(c)) && confidence > 0.5 {
                 detected_texts[i] = text.into();
             }
         }
@@ -5,15 +5,17 @@ use anyhow::Result;
 use image::{GenericImageView, RgbImage};
 use include_directory::{Dir, include_directory};
 use opencv::{
-    core::Mat,
+    core::{self, Mat, Point, Size},
     imgcodecs::{self, IMREAD_GRAYSCALE},
     imgproc::{self},
     prelude::*,
 };
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Configuration for template matching OCR
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TemplateMatchingConfig {
     pub match_threshold: f64,
     pub min_confidence: f64,
@@ -37,13 +39,35 @@ pub struct TemplateMatchingOcrEngine {
     fallback_engine: Option<onnx_ocr::OnnxOcrEngine>,
 }
 
+/// Character of a template file stem, e.g. `7-1` for the second variant of a 7. The separators
+/// of the population ("12/200") and the match timer ("12:34") are `slash` and `colon`.
+pub fn template_char(file_stem: &str) -> Option<char> {
+    let name = file_stem.split_once('-').map_or(file_stem, |(name, _)| name);
+    match name {
+        "slash" => Some('/'),
+        "colon" => Some(':'),
+        _ if name.len() == 1 && name.as_bytes()[0].is_ascii_digit() => name.chars().next(),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone)]
 struct DigitMatch {
     digit: char,
     x: i32,
+    /// Width of the matched template
+    width: i32,
     confidence: f64,
 }
 
+impl DigitMatch {
+    /// Whether two matches cover more than half of the narrower glyph
+    fn overlaps(&self, other: &DigitMatch) -> bool {
+        let intersection = (self.x + self.width).min(other.x + other.width) - self.x.max(other.x);
+        intersection * 2 > self.width.min(other.width)
+    }
+}
+
 impl TemplateMatchingOcrEngine {
     /// Create a new template matching OCR engine
     pub fn new(config: TemplateMatchingConfig) -> Result<Self> {
@@ -66,6 +90,11 @@ impl TemplateMatchingOcrEngine {
         Ok(engine)
     }
 
+    /// Replace the thresholds, e.g. after the configuration file changed
+    pub fn set_config(&mut self, config: TemplateMatchingConfig) {
+        self.config = config;
+    }
+
     /// Load digit templates from directory
     fn load_templates() -> Result<HashMap<char, Vec<Mat>>> {
         let mut templates: HashMap<char, Vec<Mat>> = HashMap::new();
@@ -76,30 +105,14 @@ impl TemplateMatchingOcrEngine {
                 .file_stem()
                 .and_then(|n| n.to_str())
                 .unwrap_or_default();
-            if file_name == "slash" {
-                let data = file.as_file().unwrap().contents();
-                let mat = imgcodecs::imdecode(&Mat::from_slice(data)?, IMREAD_GRAYSCALE)?;
-                if !mat.empty() {
-                    templates.entry('/').or_default().push(mat);
-                    //log::info!("Loaded template for '/'");
-                }
-                continue;
-            }
-
-            let (digit_char, _variant) = if let Some((d, v)) = file_name.split_once('-') {
-                (d, v)
-            } else {
+            let Some(character) = template_char(file_name) else {
                 log::warn!("Ignoring file: '{}'", file_path.display());
                 continue;
             };
             let data = file.as_file().unwrap().contents();
             let mat = imgcodecs::imdecode(&Mat::from_slice(data)?, IMREAD_GRAYSCALE)?;
             if !mat.empty() {
-                templates
-                    .entry(digit_char.chars().next().unwrap())
-                    .or_default()
-                    .push(mat);
-                //log::info!("Loaded template for digit '{}'", digit_char);
+                templates.entry(character).or_default().push(mat);
             }
         }
 
@@ -122,9 +135,6 @@ impl TemplateMatchingOcrEngine {
             }
         }
 
-        // Sort matches by x-coordinate (left to right)
-        matches.sort_by_key(|m| m.x);
-
         // Remove overlapping matches (keep highest confidence)
         let filtered_matches = self.filter_overlapping_matches(matches);
 
@@ -153,8 +163,12 @@ impl TemplateMatchingOcrEngine {
         Ok((text, avg_confidence))
     }
 
-    /// Match a single template in the image
+    /// Match a single template in the image. Returns the peaks of the response above the match
+    /// threshold, at most one per template width.
     fn match_template(&self, img: &Mat, template: &Mat, digit: char) -> Result<Vec<DigitMatch>> {
+        if template.cols() > img.cols() || template.rows() > img.rows() {
+            return Ok(Vec::new());
+        }
         let mut result = Mat::default();
         imgproc::match_template(
             img,
@@ -164,43 +178,66 @@ impl TemplateMatchingOcrEngine {
             &Mat::default(),
         )?;
 
-        let mut matches = Vec::new();
-
-        // Find all matches above threshold
-        for y in 0..result.rows() {
-            for x in 0..result.cols() {
-                let confidence = *result.at_2d::<f32>(y, x)?;
-
-                if confidence as f64 >= self.config.match_threshold {
-                    matches.push(DigitMatch {
-                        digit,
-                        x,
-                        confidence: confidence as f64,
-                    });
-                }
-            }
+        // Only the horizontal position matters, so the best response per column is enough
+        let mut response = Mat::default();
+        core::reduce(&result, &mut response, 0, core::REDUCE_MAX, -1)?;
+        let mut max_response = 0.0;
+        core::min_max_loc(
+            &response,
+            None,
+            Some(&mut max_response),
+            None,
+            None,
+            &Mat::default(),
+        )?;
+        if max_response < self.config.match_threshold {
+            return Ok(Vec::new());
         }
 
+        // Non-maximum suppression: peaks are the maximum of the window of a template width
+        let mut local_max = Mat::default();
+        let kernel = imgproc::get_structuring_element(
+            imgproc::MORPH_RECT,
+            Size::new(template.cols(), 1),
+            Point::new(-1, -1),
+        )?;
+        imgproc::dilate(
+            &response,
+            &mut local_max,
+            &kernel,
+            Point::new(-1, -1),
+            1,
+            core::BORDER_CONSTANT,
+            imgproc::morphology_default_border_value()?,
+        )?;
+
+        let matches = response
+            .data_typed::<f32>()?
+            .iter()
+            .zip(local_max.data_typed::<f32>()?)
+            .enumerate()
+            .filter(|&(_, (&confidence, &peak))| {
+                confidence >= peak && confidence as f64 >= self.config.match_threshold
+            })
+            .map(|(x, (&confidence, _))| DigitMatch {
+                digit,
+                x: x as i32,
+                width: template.cols(),
+                confidence: confidence as f64,
+            })
+            .collect();
         Ok(matches)
     }
 
     /// Filter overlapping matches, keeping only the one with highest confidence
     fn filter_overlapping_matches(&self, matches: Vec<DigitMatch>) -> Vec<DigitMatch> {
-        if matches.is_empty() {
-            return matches;
-        }
-
-        let mut filtered = Vec::new();
         let mut sorted_matches = matches;
-        sorted_matches.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap());
+        sorted_matches.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
 
+        // Only compared with the kept matches, which are a few glyphs at most
+        let mut filtered: Vec<DigitMatch> = Vec::new();
         for current in sorted_matches {
-            let overlaps = filtered.iter().any(|existing: &DigitMatch| {
-                let distance = (current.x - existing.x).abs();
-                distance < 10 // Assume digits are at least 15 pixels apart
-            });
-
-            if !overlaps {
+            if !filtered.iter().any(|existing| current.overlaps(existing)) {
                 filtered.push(current);
             }
         }
@@ -210,15 +247,8 @@ impl TemplateMatchingOcrEngine {
         filtered
     }
 
-    /// Convert RGB image to OpenCV Mat in grayscale
-    fn rgb_to_gray_mat(
-        &self,
-        img: &RgbImage,
-        x: u32,
-        y: u32,
-        width: u32,
-        height: u32,
-    ) -> Result<Mat> {
+    /// Convert a region of an RGB image to an OpenCV Mat in grayscale
+    pub fn rgb_to_gray_mat(img: &RgbImage, x: u32, y: u32, width: u32, height: u32) -> Result<Mat> {
         let subview = img.view(x, y, width, height).to_image();
 
         // Convert to grayscale
@@ -244,7 +274,7 @@ impl OcrEngine for TemplateMatchingOcrEngine {
 
         for (i, &(x, y, width, height)) in regions.iter().enumerate() {
             // Convert region to grayscale Mat
-            let gray_mat = self.rgb_to_gray_mat(img, x, y, width, height)?;
+            let gray_mat = Self::rgb_to_gray_mat(img, x, y, width, height)?;
 
             // Recognize digits using template matching
             let (text, confidence) = self.recognize_digits(&gray_mat)?;
@@ -252,13 +282,15 @@ impl OcrEngine for TemplateMatchingOcrEngine {
             // Check if we should use fallback
             let should_use_fallback = text.is_empty()
                 || confidence < self.config.min_confidence
-                || !text.chars().all(|c| c.is_ascii_digit() || c == '/');
+                || !text.chars().all(|c| c.is_ascii_digit() || "/:".contains(c));
 
             if should_use_fallback && self.fallback_engine.is_some() {
                 // We need to call fallback with just this region
                 // For now, skip fallback in this implementation - can be enhanced later
                 detected_texts[i] = Default::default();
-            } else if !text.is_empty() && text.chars().all(|c| c.is_ascii_digit() || c == '/') {
+            } else if !text.is_empty()
+                && text.chars().all(|c| c.is_ascii_digit() || "/:".contains(c))
+            {
                 detected_texts[i] = text.into();
                 // log::debug!(
                 //     "Region {}: detected '{}' with confidence {:.2}",
@@ -0,0 +1,165 @@
+// Reuse of the OCR results of stat regions whose pixels did not change
+//
+// Most HUD numbers stay the same for seconds. Every region is compared with the image its cached
+// text was read from, so slow changes like a moving background add up until the OCR runs again.
+
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OcrCacheConfig {
+    pub enabled: bool,
+    /// Pixels differing by more than this (0 - 255) in any channel count as changed
+    pub pixel_threshold: u8,
+    /// A region with at most this many changed pixels keeps its text
+    pub max_changed_pixels: u32,
+    /// Read a region again after its text was reused this many times, 0 to never refresh
+    pub max_reuse_frames: u32,
+}
+
+impl Default for OcrCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pixel_threshold: 40,
+            max_changed_pixels: 4,
+            max_reuse_frames: 50,
+        }
+    }
+}
+
+/// Lookups since the statistics were last taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OcrCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl OcrCacheStats {
+    /// Share of the lookups that reused a text, 0 - 1
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f32 / lookups as f32
+    }
+}
+
+impl fmt::Display for OcrCacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} ({:.0}%)",
+            self.hits,
+            self.hits + self.misses,
+            self.hit_rate() * 100.0
+        )
+    }
+}
+
+struct CachedRegion {
+    /// Preprocessed image the text was read from
+    image: RgbImage,
+    text: fixedstr::str8,
+    /// Lookups that reused the text
+    reused: u32,
+}
+
+/// Texts of the stat regions, by their index in `AOE4_STATS_POS`
+#[derive(Default)]
+pub struct OcrCache {
+    config: OcrCacheConfig,
+    regions: Vec<Option<CachedRegion>>,
+    stats: OcrCacheStats,
+}
+
+/// Number of pixels differing by more than `threshold`, `None` for different sizes
+fn changed_pixels(first: &RgbImage, second: &RgbImage, threshold: u8) -> Option<u32> {
+    if first.dimensions() != second.dimensions() {
+        return None;
+    }
+    let changed = first
+        .pixels()
+        .zip(second.pixels())
+        .filter(|(a, b)| a.0.iter().zip(b.0).any(|(a, b)| a.abs_diff(b) > threshold))
+        .count();
+    Some(changed as u32)
+}
+
+impl OcrCache {
+    pub fn new(config: &OcrCacheConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Apply a changed configuration. The cached texts are dropped, because the preprocessing or
+    /// the OCR thresholds may have changed as well.
+    pub fn set_config(&mut self, config: &OcrCacheConfig) {
+        self.config = config.clone();
+        self.clear();
+    }
+
+    /// Forget all texts
+    pub fn clear(&mut self) {
+        self.regions.clear();
+    }
+
+    /// Cached text of a region if `image` did not change since the text was read
+    pub fn lookup(&mut self, index: usize, image: &RgbImage) -> Option<fixedstr::str8> {
+        if !self.config.enabled {
+            return None;
+        }
+        let config = &self.config;
+        let text = self
+            .regions
+            .get_mut(index)
+            .and_then(Option::as_mut)
+            .and_then(|cached| {
+                let refresh =
+                    config.max_reuse_frames > 0 && cached.reused >= config.max_reuse_frames;
+                let unchanged = changed_pixels(&cached.image, image, config.pixel_threshold)
+                    .is_some_and(|changed| changed <= config.max_changed_pixels);
+                (unchanged && !refresh).then(|| {
+                    cached.reused += 1;
+                    cached.text
+                })
+            });
+        match text {
+            Some(_) => self.stats.hits += 1,
+            None => self.stats.misses += 1,
+        }
+        text
+    }
+
+    /// Store the text read from the image of a region. Empty texts are not stored, so that a
+    /// failed read is retried with the next frame.
+    pub fn insert(&mut self, index: usize, image: RgbImage, text: fixedstr::str8) {
+        if !self.config.enabled {
+            return;
+        }
+        if text.as_str().trim().is_empty() {
+            if let Some(cached) = self.regions.get_mut(index) {
+                *cached = None;
+            }
+            return;
+        }
+        if self.regions.len() <= index {
+            self.regions.resize_with(index + 1, || None);
+        }
+        self.regions[index] = Some(CachedRegion {
+            image,
+            text,
+            reused: 0,
+        });
+    }
+
+    /// Statistics since the last call
+    pub fn take_stats(&mut self) -> OcrCacheStats {
+        std::mem::take(&mut self.stats)
+    }
+}
@@ -1,21 +1,77 @@
 use crate::{frame_processor::ProcessedFrame, system_menu::SystemTray};
+use crate::alerts::Alert;
+use crate::match_stats::{MatchStats, format_duration};
+use crate::worker_advisor::WorkerAdvice;
+use crate::build_order::BuildOrderProgress;
 use anyhow::Result;
-use aoe4_overlay::consts::{AOE4_STATS_POS, AREA_HEIGHT, AREA_WIDTH, INDEX_IDLE, INDEX_POP};
-use gtk::{Application, Button, IconTheme, Label, cairo, glib, prelude::*};
+use aoe4_overlay::{
+    consts::{AOE4_STATS_POS, AREA_HEIGHT, AREA_WIDTH},
+    i18n,
+};
+use crate::config::AppConfig;
+use gtk::{Application, Button, CssProvider, IconTheme, Label, cairo, glib, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use tokio::{
-    sync::mpsc::{Receiver, Sender},
+    sync::{
+        mpsc::{Receiver, Sender},
+        watch,
+    },
     task,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct OverlayConfig {
     pub show_debug_window: bool,
+    /// Show the idle time of villager production during a match and a summary after it
+    pub show_match_stats: bool,
+    /// Show the biggest deviation of the workers from the target distribution
+    pub show_worker_advice: bool,
+    /// Show the current and next step of the build order
+    pub show_build_order: bool,
+    /// Number of alerts shown at the same time, highest priority first
+    pub max_alerts: usize,
+    /// Opacity of the whole overlay window, 0.0 - 1.0
+    pub opacity: f64,
+    pub style: OverlayStyle,
 }
 
 impl Default for OverlayConfig {
     fn default() -> Self {
         Self {
             show_debug_window: false,
+            show_match_stats: true,
+            show_worker_advice: true,
+            show_build_order: true,
+            max_alerts: 3,
+            opacity: 1.0,
+            style: OverlayStyle::default(),
+        }
+    }
+}
+
+/// Colors are CSS colors, sizes are in pixels
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlayStyle {
+    pub alert_font_size: u32,
+    pub alert_color: String,
+    pub alert_background: String,
+    pub stat_font_size: u32,
+    pub stat_color: String,
+    pub stat_background: String,
+}
+
+impl Default for OverlayStyle {
+    fn default() -> Self {
+        Self {
+            alert_font_size: 50,
+            alert_color: "white".to_string(),
+            alert_background: "rgba(0, 128, 0, 0.7)".to_string(),
+            stat_font_size: 12,
+            stat_color: "white".to_string(),
+            stat_background: "rgba(0, 0, 0, 0.7)".to_string(),
         }
     }
 }
@@ -23,6 +79,8 @@ impl Default for OverlayConfig {
 pub enum GuiCommand {
     AboutToProcessFrames,
     ProcessedFrame(ProcessedFrame),
+    /// Summary of the match that just ended, shown until the next match starts
+    MatchFinished(MatchStats),
     Quit,
 }
 
@@ -30,11 +88,49 @@ pub struct OverlayWindow {
     window: gtk::ApplicationWindow,
     image_widget: gtk::Picture,
     _overlay_container: gtk::Overlay,
-    _text_labels_box: gtk::Box,
+    text_labels_box: gtk::Box,
     _icon_labels_box: gtk::Box,
-    config: OverlayConfig,
+    icon_theme: IconTheme,
+    config: RefCell<OverlayConfig>,
     pub centered_label: Label,
+    alerts_box: gtk::Box,
+    alert_rows: RefCell<Vec<AlertRow>>,
+    /// Running match statistics, or the summary of the last match
+    stats_label: Label,
+    /// Statistics of the last match, until the next one starts
+    finished_match: RefCell<Option<MatchStats>>,
+    worker_advice_label: Label,
+    build_order_label: Label,
     pub labels: [Label; AOE4_STATS_POS.len()],
+    /// Results of the icon searches, in the debug window
+    icons_label: Label,
+    /// Reading of the match timer, in the debug window
+    game_time_label: Label,
+    /// Intermediate images of the preprocessing, in the debug window
+    stages_widget: gtk::Picture,
+}
+
+/// Icon and text of one shown alert
+struct AlertRow {
+    row: gtk::Box,
+    icon: gtk::Image,
+    label: Label,
+}
+
+impl AlertRow {
+    fn new(parent: &gtk::Box) -> Self {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        row.set_halign(gtk::Align::Center);
+        let icon = gtk::Image::new();
+        icon.set_visible(false);
+        row.append(&icon);
+        let label = gtk::Label::new(None);
+        label.add_css_class("icon-label");
+        label.set_xalign(0.0);
+        row.append(&label);
+        parent.append(&row);
+        Self { row, icon, label }
+    }
 }
 
 pub struct InteractWindow {
@@ -45,7 +141,7 @@ pub struct InteractWindow {
 impl InteractWindow {
     pub fn new(sender: Sender<GuiCommand>, app: &Application) -> Result<Self> {
         let window = gtk::Window::builder()
-            .title("AOE4 Overlay Interaction")
+            .title(i18n::tr("overlay.interaction_title"))
             .maximized(false)
             .decorated(false)
             .resizable(false)
@@ -57,7 +153,7 @@ impl InteractWindow {
             .build();
 
         // Create quit button
-        let quit_button = gtk::Button::with_label("Quit");
+        let quit_button = gtk::Button::with_label(&i18n::tr("overlay.quit"));
         quit_button.set_halign(gtk::Align::Start);
         quit_button.set_valign(gtk::Align::Start);
         quit_button.set_child_visible(true);
@@ -84,13 +180,8 @@ impl InteractWindow {
     }
 }
 
-fn gtk_init_with_style() -> Result<IconTheme> {
-    // Initialize GTK
-    gtk::init()?;
-
-    // Set up CSS for transparency and styling
-    let css_provider = gtk::CssProvider::new();
-    let css_content = format!(
+fn style_css(style: &OverlayStyle) -> String {
+    format!(
         ".main-window {{
                 background-color: transparent;
             }}
@@ -102,25 +193,39 @@ fn gtk_init_with_style() -> Result<IconTheme> {
                 border-radius: 5px;
             }}
             .stat-label {{
-                background-color: rgba(0, 0, 0, 0.7);
-                color: white;
+                background-color: {stat_background};
+                color: {stat_color};
                 padding: 2px 5px;
                 margin: 2px;
                 font-family: monospace;
-                font-size: 12px;
+                font-size: {stat_font_size}px;
                 border-radius: 3px;
             }}
             .icon-label {{
-                background-color: rgba(0, 128, 0, 0.7);
-                color: white;
+                background-color: {alert_background};
+                color: {alert_color};
                 padding: 2px 5px;
                 margin: 2px;
                 font-weight: bold;
-                font-size: 50px;
+                font-size: {alert_font_size}px;
                 border-radius: 3px;
-            }}"
-    );
-    css_provider.load_from_string(&css_content);
+            }}",
+        stat_background = style.stat_background,
+        stat_color = style.stat_color,
+        stat_font_size = style.stat_font_size,
+        alert_background = style.alert_background,
+        alert_color = style.alert_color,
+        alert_font_size = style.alert_font_size,
+    )
+}
+
+fn gtk_init_with_style(style: &OverlayStyle) -> Result<(IconTheme, CssProvider)> {
+    // Initialize GTK
+    gtk::init()?;
+
+    // Set up CSS for transparency and styling
+    let css_provider = gtk::CssProvider::new();
+    css_provider.load_from_string(&style_css(style));
 
     gtk::style_context_add_provider_for_display(
         &gdk::Display::default().expect("Could not connect to display"),
@@ -136,11 +241,11 @@ fn gtk_init_with_style() -> Result<IconTheme> {
         .build();
     log::info!("icon_theme: {:?} {:?}", icon_theme, icon_theme.icon_names());
 
-    Ok(icon_theme)
+    Ok((icon_theme, css_provider))
 }
 
 impl OverlayWindow {
-    pub fn new(config: OverlayConfig, app: &Application) -> Result<Self> {
+    pub fn new(config: OverlayConfig, app: &Application, icon_theme: IconTheme) -> Result<Self> {
         let monitors: gdk::gio::ListModel = gdk::Display::default().unwrap().monitors();
         let monitor = monitors
             .item(0)
@@ -150,7 +255,7 @@ impl OverlayWindow {
 
         // Create the main window with configured size
         let window = gtk::ApplicationWindow::builder()
-            .title("AOE4 Overlay")
+            .title(i18n::tr("overlay.title"))
             .default_width(monitor.geometry().width())
             .default_height(monitor.geometry().height())
             .maximized(false)
@@ -184,16 +289,27 @@ impl OverlayWindow {
         overlay_container.add_overlay(&text_labels_box);
 
         let mut labels: [gtk::Label; AOE4_STATS_POS.len()] = Default::default();
-        if config.show_debug_window {
-            for (index, stat) in aoe4_overlay::consts::AOE4_STATS_POS.iter().enumerate() {
-                let label_text = format!("{}: --", stat.name);
-                let label = gtk::Label::new(Some(&label_text));
-                label.add_css_class("stat-label");
-                label.set_xalign(0.0);
-                text_labels_box.append(&label);
-                labels[index] = label;
-            }
+        for (index, stat) in aoe4_overlay::consts::AOE4_STATS_POS.iter().enumerate() {
+            let label_text = format!("{}: --", i18n::tr(&i18n::stat_key(stat.name)));
+            let label = gtk::Label::new(Some(&label_text));
+            label.add_css_class("stat-label");
+            label.set_xalign(0.0);
+            text_labels_box.append(&label);
+            labels[index] = label;
         }
+        let icons_label = gtk::Label::new(None);
+        icons_label.add_css_class("stat-label");
+        icons_label.set_xalign(0.0);
+        text_labels_box.append(&icons_label);
+        let game_time_label = gtk::Label::new(None);
+        game_time_label.add_css_class("stat-label");
+        game_time_label.set_xalign(0.0);
+        text_labels_box.append(&game_time_label);
+        let stages_widget = gtk::Picture::new();
+        stages_widget.set_halign(gtk::Align::Start);
+        stages_widget.set_can_shrink(false);
+        text_labels_box.append(&stages_widget);
+        text_labels_box.set_visible(config.show_debug_window);
 
         // Create vertical box for icon labels (top-right)
         let icon_labels_box = gtk::Box::new(gtk::Orientation::Vertical, 2);
@@ -206,30 +322,86 @@ impl OverlayWindow {
         let centered_label = gtk::Label::new(None);
         centered_label.add_css_class("icon-label");
         centered_label.set_xalign(0.0);
-        //centered_label.set_visible(false);
+        centered_label.set_visible(false);
         icon_labels_box.append(&centered_label);
 
+        // Rows of the active alerts, created when needed
+        let alerts_box = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        icon_labels_box.append(&alerts_box);
+
+        // Create vertical box for match statistics and advice (top-center)
+        let top_box = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        top_box.set_halign(gtk::Align::Center);
+        top_box.set_valign(gtk::Align::Start);
+        top_box.set_margin_top(5);
+        overlay_container.add_overlay(&top_box);
+
+        let stats_label = gtk::Label::new(None);
+        stats_label.add_css_class("stat-label");
+        stats_label.set_visible(false);
+        top_box.append(&stats_label);
+
+        let worker_advice_label = gtk::Label::new(None);
+        worker_advice_label.add_css_class("stat-label");
+        worker_advice_label.set_visible(false);
+        top_box.append(&worker_advice_label);
+
+        let build_order_label = gtk::Label::new(None);
+        build_order_label.add_css_class("stat-label");
+        build_order_label.set_xalign(0.0);
+        build_order_label.set_visible(false);
+        top_box.append(&build_order_label);
+
         // Add overlay container to window
         window.set_child(Some(&overlay_container));
+        window.set_opacity(config.opacity);
 
         Ok(Self {
             window,
             image_widget,
             _overlay_container: overlay_container,
-            _text_labels_box: text_labels_box,
+            text_labels_box,
             _icon_labels_box: icon_labels_box,
+            icon_theme,
             labels,
+            icons_label,
+            game_time_label,
+            stages_widget,
             centered_label,
-            config,
+            alerts_box,
+            alert_rows: RefCell::new(Vec::new()),
+            stats_label,
+            finished_match: RefCell::new(None),
+            worker_advice_label,
+            build_order_label,
+            config: RefCell::new(config),
         })
     }
 
+    /// Apply a changed configuration. Styling is applied via the CSS provider.
+    pub fn set_config(&self, config: OverlayConfig) {
+        self.image_widget.set_child_visible(config.show_debug_window);
+        self.text_labels_box.set_visible(config.show_debug_window);
+        self.window.set_opacity(config.opacity);
+        if !config.show_match_stats {
+            self.stats_label.set_visible(false);
+        }
+        if !config.show_worker_advice {
+            self.worker_advice_label.set_visible(false);
+        }
+        if !config.show_build_order {
+            self.build_order_label.set_visible(false);
+        }
+        *self.config.borrow_mut() = config;
+    }
+
     pub fn enable_waiting(&self, enable: bool) {
         if enable {
-            self.centered_label.set_text("Waiting...");
+            self.centered_label.set_text(&i18n::tr("overlay.waiting"));
         } else {
             self.centered_label.set_text("");
         }
+        self.centered_label.set_visible(enable);
     }
 
     pub fn show(&self) {
@@ -242,63 +414,191 @@ impl OverlayWindow {
         }
     }
 
-    pub fn update_image_from_processed_frame(&self, frame: ProcessedFrame) {
-        let mut parts = frame.analysis.detected_texts[INDEX_POP].split("/");
-        let current = parts
-            .next()
-            .unwrap_or_default()
-            .parse::<i32>()
-            .unwrap_or_default();
-        let total = parts
-            .next()
-            .unwrap_or_default()
-            .parse::<i32>()
-            .unwrap_or_default();
-        let is_useful = total > 0;
-
-        if !is_useful {
-            self.centered_label.set_text("");
-        } else {
-            let is_pop = current + 2 >= total;
-            let is_idle = frame.analysis.detected_texts[INDEX_IDLE]
-                .parse::<i32>()
-                .unwrap_or_default()
-                > 0;
-            let has_villager = frame.analysis.has_villager_icon;
-
-            if is_pop {
-                self.centered_label.set_text("Haus!");
-                //self.centered_label.set_visible(true);
-            } else if is_idle {
-                self.centered_label.set_text("Idle!");
-                //self.centered_label.set_visible(true);
-            } else if !has_villager {
-                self.centered_label.set_text("Villager!");
-                //self.centered_label.set_visible(true);
-            } else {
-                self.centered_label.set_text("");
-                // self.centered_label.set_visible(false);
-                // self.centered_label.set_child_visible(false);
+    /// Show an icon of the overlay icon theme next to an alert text
+    fn set_alert_icon(&self, image: &gtk::Image, icon: Option<&str>) {
+        let Some(icon) = icon else {
+            image.set_visible(false);
+            return;
+        };
+        let size = self.config.borrow().style.alert_font_size as i32;
+        let paintable = self.icon_theme.lookup_icon(
+            icon,
+            &[],
+            size,
+            1,
+            gtk::TextDirection::None,
+            gtk::IconLookupFlags::empty(),
+        );
+        image.set_pixel_size(size);
+        image.set_paintable(Some(&paintable));
+        image.set_visible(true);
+    }
+
+    /// Show the alerts below each other, at most `max_alerts` of them. The alerts are sorted by
+    /// priority already.
+    fn update_alerts(&self, alerts: &[Alert]) {
+        let shown = alerts.len().min(self.config.borrow().max_alerts);
+        let mut rows = self.alert_rows.borrow_mut();
+        while rows.len() < shown {
+            rows.push(AlertRow::new(&self.alerts_box));
+        }
+        for (index, row) in rows.iter().enumerate() {
+            let Some(alert) = alerts.get(index).filter(|_| index < shown) else {
+                row.row.set_visible(false);
+                continue;
+            };
+            let message = alert.text();
+            row.label.set_text(message.as_deref().unwrap_or_default());
+            row.label.set_visible(message.is_some());
+            self.set_alert_icon(&row.icon, alert.icon.as_deref());
+            row.row.set_visible(true);
+        }
+    }
+
+    /// Show the summary of a finished match
+    pub fn set_finished_match(&self, stats: MatchStats) {
+        *self.finished_match.borrow_mut() = Some(stats);
+        self.update_match_stats(None);
+    }
+
+    /// Show the running idle time, or the summary once a match ended. The summary stays until the
+    /// next match starts.
+    fn update_match_stats(&self, running: Option<&MatchStats>) {
+        if running.is_some() {
+            self.finished_match.borrow_mut().take();
+        }
+        if !self.config.borrow().show_match_stats {
+            return;
+        }
+        if let Some(stats) = running {
+            self.stats_label.set_text(&format!(
+                "{}: {}",
+                i18n::tr("stats.tc_idle"),
+                format_duration(stats.tc_idle_ms)
+            ));
+            self.stats_label.set_visible(true);
+        } else if let Some(stats) = self.finished_match.borrow().as_ref() {
+            let mut summary = format!(
+                "{} ({})\n{}: {} ({:.0}%)",
+                i18n::tr("stats.match_summary"),
+                format_duration(stats.duration_ms),
+                i18n::tr("stats.tc_idle"),
+                format_duration(stats.tc_idle_ms),
+                stats.tc_idle_share() * 100.0
+            );
+            for phase in &stats.phases {
+                summary.push_str(&format!(
+                    "\n  {}: {} / {}",
+                    i18n::tr_or_literal(&phase.name),
+                    format_duration(phase.tc_idle_ms),
+                    format_duration(phase.duration_ms)
+                ));
             }
+            self.stats_label.set_text(&summary);
+            self.stats_label.set_visible(true);
         }
+    }
 
-        if self.config.show_debug_window {
+    fn update_worker_advice(&self, advice: Option<&WorkerAdvice>) {
+        match advice {
+            Some(advice) if self.config.borrow().show_worker_advice => {
+                self.worker_advice_label.set_text(&format!(
+                    "{}: {}",
+                    i18n::tr("stats.workers"),
+                    advice
+                ));
+                self.worker_advice_label.set_visible(true);
+            }
+            _ => self.worker_advice_label.set_visible(false),
+        }
+    }
+
+    fn update_build_order(&self, progress: Option<&BuildOrderProgress>) {
+        let Some(progress) = progress.filter(|_| self.config.borrow().show_build_order) else {
+            self.build_order_label.set_visible(false);
+            return;
+        };
+        let mut text = match &progress.current {
+            Some(current) => format!(
+                "{} {}/{}: {}",
+                i18n::tr("build_order.step"),
+                progress.step + 1,
+                progress.steps,
+                current
+            ),
+            None => i18n::tr("build_order.done"),
+        };
+        if let Some(next) = &progress.next {
+            text.push_str(&format!("\n{}: {}", i18n::tr("build_order.next"), next));
+        }
+        self.build_order_label.set_text(&text);
+        self.build_order_label.set_visible(true);
+    }
+
+    pub fn update_image_from_processed_frame(&self, frame: ProcessedFrame) {
+        self.update_match_stats(frame.match_stats.as_ref());
+        self.update_worker_advice(frame.worker_advice.as_ref());
+        self.update_build_order(frame.build_order.as_ref());
+
+        self.update_alerts(&frame.alerts);
+
+        if self.config.borrow().show_debug_window {
             for (index, stat) in AOE4_STATS_POS.iter().enumerate() {
                 let text = &frame.analysis.detected_texts[index];
                 let label = &self.labels[index];
+                let confidence = frame.tracked.confidence.for_text_type(stat.text_type);
+                let name = i18n::tr(&i18n::stat_key(stat.name));
                 if text.is_empty() || text == "--" {
-                    label.set_text(&format!("{}: --", stat.name));
+                    label.set_text(&format!("{}: -- ({:.0}%)", name, confidence * 100.0));
                 } else {
-                    label.set_text(&format!("{}: {}", stat.name, text));
+                    label.set_text(&format!(
+                        "{}: {} ({:.0}%)",
+                        name,
+                        text,
+                        confidence * 100.0
+                    ));
                 }
             }
 
-            // Crop to region of interest (bottom 500px)
+            let mut icons = format!(
+                "{}: {}",
+                i18n::tr("overlay.villager_icon"),
+                frame.analysis.villager_icon
+            );
+            for (name, detection) in &frame.analysis.icons {
+                icons.push_str(&format!("\n{}: {}", name, detection));
+            }
+            self.icons_label.set_text(&icons);
+            // The reading of this frame and the filtered game time
+            let reading = frame.analysis.game_time_ms.map(format_duration);
+            let game_time = frame.game_time_ms.map(format_duration);
+            self.game_time_label.set_text(&format!(
+                "{}: {} ({})",
+                i18n::tr("overlay.game_time"),
+                game_time.as_deref().unwrap_or("--"),
+                reading.as_deref().unwrap_or("--")
+            ));
+
+            // Input and output of every preprocessing step, a row per region
+            if let Some(stages) = &frame.analysis.preprocess_stages {
+                let image = &stages.0;
+                let texture = gdk::MemoryTexture::new(
+                    image.width() as i32,
+                    image.height() as i32,
+                    gdk::MemoryFormat::R8g8b8,
+                    &glib::Bytes::from(image.as_raw().as_slice()),
+                    image.width() as usize * 3,
+                );
+                self.stages_widget.set_paintable(Some(&texture));
+            }
+
+            // Crop to the HUD panel
             let pixbuf = frame.original.to_pixbuf();
-            let crop_height = pixbuf.height().min(500);
-            let crop_width = pixbuf.width().min(300);
+            let area = frame.hud_area;
+            let crop_width = (area.width as i32).min(pixbuf.width() - area.x as i32);
+            let crop_height = (area.height as i32).min(pixbuf.height() - area.y as i32);
             let pixbuf =
-                pixbuf.new_subpixbuf(0, pixbuf.height() - crop_height, crop_width, crop_height);
+                pixbuf.new_subpixbuf(area.x as i32, area.y as i32, crop_width, crop_height);
 
             let texture = gdk::Texture::for_pixbuf(&pixbuf);
             self.image_widget.set_paintable(Some(&texture));
@@ -318,12 +618,13 @@ impl OverlayWindow {
 pub async fn run(
     gtk_sender: Sender<GuiCommand>,
     mut gtk_receiver: Receiver<GuiCommand>,
-    config: OverlayConfig,
+    mut config_receiver: watch::Receiver<AppConfig>,
     enable_waiting: bool,
 ) -> Result<()> {
     // Start the GTK thread
     let gtk_handle = std::thread::spawn(move || -> Result<()> {
-        let _icon_theme = gtk_init_with_style()?;
+        let config = config_receiver.borrow_and_update().overlay.clone();
+        let (icon_theme, css_provider) = gtk_init_with_style(&config.style)?;
         let main_context = glib::MainContext::default();
         let main_loop = glib::MainLoop::new(Some(&main_context), false);
 
@@ -332,7 +633,7 @@ pub async fn run(
             .version("0.1")
             .build();
 
-        let window = OverlayWindow::new(config, &app)?;
+        let window = OverlayWindow::new(config, &app, icon_theme)?;
         let interactive_window = InteractWindow::new(gtk_sender.clone(), &app)?;
 
         if enable_waiting {
@@ -356,6 +657,9 @@ pub async fn run(
                     GuiCommand::ProcessedFrame(processed_frame) => {
                         window_for_image_updates.update_image_from_processed_frame(processed_frame);
                     }
+                    GuiCommand::MatchFinished(stats) => {
+                        window_for_image_updates.set_finished_match(stats);
+                    }
                     GuiCommand::Quit => {
                         log::info!("Quit command received from channel, quitting...");
                         main_loop_quit.quit();
@@ -369,6 +673,16 @@ pub async fn run(
             }
         });
 
+        // Apply configuration changes
+        let window_for_config_updates = std::rc::Rc::clone(&window_rc);
+        main_context.spawn_local(async move {
+            while config_receiver.changed().await.is_ok() {
+                let config = config_receiver.borrow_and_update().overlay.clone();
+                css_provider.load_from_string(&style_css(&config.style));
+                window_for_config_updates.set_config(config);
+            }
+        });
+
         // React to window close request
         let main_loop_quit_clone = main_loop.clone();
         window_rc.window.connect_close_request(move |_| {
@@ -1,4 +1,7 @@
-use crate::pixelbuf_wrapper::PixelBufWrapperWithDroppedFramesTS;
+use crate::{
+    config::AppConfig,
+    pixelbuf_wrapper::{PixelBufWrapperWithDroppedFramesTS, PixelFormat},
+};
 use anyhow::Result;
 use log::info;
 use pipewire::{
@@ -18,6 +21,8 @@ use spa::{
     param::{
         ParamType,
         format::{MediaSubtype, MediaType},
+        format_utils,
+        video::{VideoFormat, VideoInfoRaw},
     },
     pod::{Object, Pod, Property, Value},
 };
@@ -26,10 +31,14 @@ use std::{
     thread,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use tokio::sync::watch;
 
 struct UserData {
     last_time: u64,
+    /// Negotiated video format
+    format: VideoInfoRaw,
     pw_sender_quit: Sender<PipewireMessage>,
+    config: watch::Receiver<AppConfig>,
 }
 /// Manages a PipeWire stream for screen capturing and sends images via a channel.
 pub struct PipeWireStream {
@@ -40,6 +49,7 @@ pub struct PipeWireStream {
     image_sender: mpsc::SyncSender<bool>,
     image_sender_content: PixelBufWrapperWithDroppedFramesTS,
     pub pw_sender_quit: Sender<PipewireMessage>,
+    config: watch::Receiver<AppConfig>,
 }
 
 impl PipeWireStream {
@@ -48,6 +58,7 @@ impl PipeWireStream {
         image_sender: mpsc::SyncSender<bool>,
         image_sender_content: PixelBufWrapperWithDroppedFramesTS,
         pw_sender_quit: Sender<PipewireMessage>,
+        config: watch::Receiver<AppConfig>,
     ) -> Result<Self> {
         pipewire::init();
 
@@ -62,6 +73,7 @@ impl PipeWireStream {
             image_sender,
             image_sender_content,
             pw_sender_quit,
+            config,
         })
     }
 
@@ -88,7 +100,9 @@ impl PipeWireStream {
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or(Duration::from_secs(0))
                 .as_millis() as u64,
+            format: VideoInfoRaw::new(),
             pw_sender_quit: self.pw_sender_quit.clone(),
+            config: self.config.clone(),
         };
 
         // Set up stream listener
@@ -111,10 +125,26 @@ impl PipeWireStream {
                     }
                 },
             )
-            .param_changed(|_stream, _user_data: &mut UserData, id, param| {
-                if let Some(_param) = param {
+            .param_changed(|_stream, user_data: &mut UserData, id, param| {
+                if let Some(param) = param {
                     if id == ParamType::Format.as_raw() {
-                        log::info!("Stream format changed");
+                        let Ok((media_type, media_subtype)) = format_utils::parse_format(param)
+                        else {
+                            return;
+                        };
+                        if media_type != MediaType::Video || media_subtype != MediaSubtype::Raw {
+                            return;
+                        }
+                        if user_data.format.parse(param).is_err() {
+                            log::error!("Failed to parse stream format");
+                            return;
+                        }
+                        log::info!(
+                            "Stream format changed: {:?} {}x{}",
+                            user_data.format.format(),
+                            user_data.format.size().width,
+                            user_data.format.size().height
+                        );
                     } else if id == ParamType::Latency.as_raw() {
                         log::info!("Stream latency params changed");
                     } else if id == ParamType::Props.as_raw() {
@@ -149,18 +179,24 @@ impl PipeWireStream {
                     }
                     Some(buffer) => buffer,
                 };
-                // Reduce framerate to every 100ms (10fps) by comparing timestamps
-                {
+                // Reduce framerate to the configured frame interval by comparing timestamps
+                let now = {
                     use std::time::{Duration, SystemTime, UNIX_EPOCH};
                     let now = SystemTime::now()
                         .duration_since(UNIX_EPOCH)
                         .unwrap_or(Duration::from_secs(0))
                         .as_millis() as u64;
-                    if now.saturating_sub(user_data.last_time) < 250 {
+                    let frame_interval_ms = user_data.config.borrow().capture.frame_interval_ms;
+                    if now.saturating_sub(user_data.last_time) < frame_interval_ms {
                         return;
                     }
                     user_data.last_time = now;
-                }
+                    now
+                };
+                let format = match user_data.format.format() {
+                    VideoFormat::BGRA => PixelFormat::BGRA,
+                    _ => PixelFormat::BGRx,
+                };
 
                 let data = buffer.datas_mut();
                 if data.is_empty() {
@@ -197,6 +233,8 @@ impl PipeWireStream {
                     content
                         .pixbuf
                         .copy_from_slice(&slice[..size], width, height, stride);
+                    content.pixbuf.format = format;
+                    content.pixbuf.timestamp_ms = now;
                     content.frames_written += 1;
                 }
 
@@ -322,6 +360,7 @@ impl PipeWireStopHandler {
 pub fn run(
     sender: SyncSender<bool>,
     image_sender_content: PixelBufWrapperWithDroppedFramesTS,
+    config: watch::Receiver<AppConfig>,
 ) -> (PipeWireStopHandler, thread::JoinHandle<()>) {
     let (pw_sender, pw_receiver) = pipewire::channel::channel::<PipewireMessage>();
     let pw_sender_clone = pw_sender.clone();
@@ -329,7 +368,8 @@ pub fn run(
         PipeWireStopHandler { pw_sender },
         thread::spawn(move || {
             let pipewire_stream =
-                PipeWireStream::new(sender, image_sender_content, pw_sender_clone).unwrap();
+                PipeWireStream::new(sender, image_sender_content, pw_sender_clone, config)
+                    .unwrap();
             let mainloop = pipewire_stream.main_loop.clone();
             let mainloop_clone = pipewire_stream.main_loop.clone();
             let pipewire_stream_arc = Arc::new(Mutex::new(pipewire_stream));
@@ -1,6 +1,15 @@
 use std::sync::{Arc, Mutex};
 use gdk::gdk_pixbuf::Pixbuf;
 use gtk::gdk_pixbuf;
+use serde::{Deserialize, Serialize};
+
+/// Pixel format of the captured frames, both with 4 bytes per pixel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PixelFormat {
+    #[default]
+    BGRx,
+    BGRA,
+}
 
 #[derive(Clone, Default)]
 pub struct PixbufWrapper {
@@ -8,6 +17,9 @@ pub struct PixbufWrapper {
     pub width: i32,
     pub height: i32,
     pub stride: i32,
+    pub format: PixelFormat,
+    /// Capture time in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
 }
 
 impl PixbufWrapper {
@@ -24,6 +36,8 @@ impl PixbufWrapper {
         self.width = other.width;
         self.height = other.height;
         self.stride = other.stride;
+        self.format = other.format;
+        self.timestamp_ms = other.timestamp_ms;
     }
 }
 
@@ -0,0 +1,375 @@
+// Configurable preprocessing of the HUD stat regions before OCR
+//
+// Every region runs through a chain of steps, configured per stat name or text type, e.g.:
+//
+//   [analysis.preprocessing]
+//   default = [{ step = "grayscale" }, { step = "brighten", amount = 30 }]
+//
+//   [analysis.preprocessing.regions]
+//   workers = [
+//     { step = "hsv_range", low = [0, 0, 170], high = [180, 60, 255] },
+//     { step = "grayscale" },
+//     { step = "upscale", factor = 2.0 },
+//   ]
+//
+// The HUD background is semi-transparent, so the best chain depends on the terrain behind it.
+// The debug window shows the input and the output of every step per region.
+
+use crate::consts::{AOE4_STATS_POS, TextType};
+use anyhow::Result;
+use image::RgbImage;
+use opencv::{
+    core::{self, AlgorithmHint, Mat, Scalar, Size},
+    imgproc,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum PreprocessStep {
+    /// Gray values of a colour image
+    Grayscale,
+    /// Add to every value, negative to darken
+    Brighten { amount: i32 },
+    /// Stretch the values between two percentiles (0 - 100) to the full range
+    ContrastStretch {
+        low_percentile: f32,
+        high_percentile: f32,
+    },
+    /// Black and white against the Gaussian weighted mean of a `block_size` neighbourhood, which
+    /// must be odd. Converts colour images to gray first.
+    AdaptiveThreshold {
+        block_size: i32,
+        offset: f64,
+        #[serde(default)]
+        invert: bool,
+    },
+    /// Keep the pixels of a text colour and make all others black. HSV values as in OpenCV, the
+    /// hue is 0 - 180. Needs a colour image, so it must come before the grayscale steps.
+    HsvRange { low: [u8; 3], high: [u8; 3] },
+    /// Resize by a factor, e.g. for OCR models trained on larger text
+    Upscale { factor: f64 },
+    /// Unsharp mask: add `amount` times the difference to a Gaussian blur of `sigma`
+    Sharpen { amount: f64, sigma: f64 },
+}
+
+impl PreprocessStep {
+    /// Whether the step needs a colour image
+    fn needs_colour(&self) -> bool {
+        matches!(self, PreprocessStep::HsvRange { .. })
+    }
+
+    /// Whether the output is a gray image
+    fn makes_gray(&self) -> bool {
+        matches!(
+            self,
+            PreprocessStep::Grayscale | PreprocessStep::AdaptiveThreshold { .. }
+        )
+    }
+
+    /// Run the step on a BGR or gray image
+    pub fn apply(&self, img: &Mat) -> Result<Mat> {
+        let mut output = Mat::default();
+        match self {
+            PreprocessStep::Grayscale => return to_gray(img),
+            PreprocessStep::Brighten { amount } => {
+                img.convert_to(&mut output, -1, 1.0, *amount as f64)?
+            }
+            PreprocessStep::ContrastStretch {
+                low_percentile,
+                high_percentile,
+            } => {
+                let (low, high) = percentiles(img, *low_percentile, *high_percentile)?;
+                let alpha = 255.0 / (high as i32 - low as i32).max(1) as f64;
+                img.convert_to(&mut output, -1, alpha, -(low as f64) * alpha)?
+            }
+            PreprocessStep::AdaptiveThreshold {
+                block_size,
+                offset,
+                invert,
+            } => {
+                let threshold_type = if *invert {
+                    imgproc::THRESH_BINARY_INV
+                } else {
+                    imgproc::THRESH_BINARY
+                };
+                imgproc::adaptive_threshold(
+                    &to_gray(img)?,
+                    &mut output,
+                    255.0,
+                    imgproc::ADAPTIVE_THRESH_GAUSSIAN_C,
+                    threshold_type,
+                    (*block_size).max(3) | 1,
+                    *offset,
+                )?
+            }
+            PreprocessStep::HsvRange { low, high } => {
+                if img.channels() != 3 {
+                    anyhow::bail!("The HSV range needs a colour image");
+                }
+                let mut hsv = Mat::default();
+                imgproc::cvt_color(
+                    img,
+                    &mut hsv,
+                    imgproc::COLOR_BGR2HSV,
+                    0,
+                    AlgorithmHint::ALGO_HINT_DEFAULT,
+                )?;
+                let scalar =
+                    |hsv: &[u8; 3]| Scalar::new(hsv[0] as f64, hsv[1] as f64, hsv[2] as f64, 0.0);
+                let mut mask = Mat::default();
+                core::in_range(&hsv, &scalar(low), &scalar(high), &mut mask)?;
+                output = Mat::new_rows_cols_with_default(
+                    img.rows(),
+                    img.cols(),
+                    img.typ(),
+                    Scalar::all(0.0),
+                )?;
+                img.copy_to_masked(&mut output, &mask)?
+            }
+            PreprocessStep::Upscale { factor } => {
+                let size = Size::new(
+                    ((img.cols() as f64 * factor).round() as i32).max(1),
+                    ((img.rows() as f64 * factor).round() as i32).max(1),
+                );
+                imgproc::resize(img, &mut output, size, 0.0, 0.0, imgproc::INTER_CUBIC)?
+            }
+            PreprocessStep::Sharpen { amount, sigma } => {
+                let mut blurred = Mat::default();
+                imgproc::gaussian_blur(
+                    img,
+                    &mut blurred,
+                    Size::new(0, 0),
+                    *sigma,
+                    *sigma,
+                    core::BORDER_DEFAULT,
+                    AlgorithmHint::ALGO_HINT_DEFAULT,
+                )?;
+                core::add_weighted(img, 1.0 + amount, &blurred, -amount, 0.0, &mut output, -1)?
+            }
+        }
+        Ok(output)
+    }
+}
+
+fn to_gray(img: &Mat) -> Result<Mat> {
+    if img.channels() == 1 {
+        return Ok(img.try_clone()?);
+    }
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        img,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(gray)
+}
+
+/// Values below which `low` and `high` percent of all channel values are
+fn percentiles(img: &Mat, low: f32, high: f32) -> Result<(u8, u8)> {
+    let img = img.try_clone()?;
+    let mut histogram = [0usize; 256];
+    for &value in img.data_bytes()? {
+        histogram[value as usize] += 1;
+    }
+    let total: usize = histogram.iter().sum();
+    let value_at = |percentile: f32| {
+        let target = (total as f32 * percentile.clamp(0.0, 100.0) / 100.0) as usize;
+        let mut count = 0;
+        for (value, &n) in histogram.iter().enumerate() {
+            count += n;
+            if count > target {
+                return value as u8;
+            }
+        }
+        255
+    };
+    Ok((value_at(low), value_at(high)))
+}
+
+/// Preprocessing chains of the stat regions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreprocessConfig {
+    /// Chain of all regions without their own
+    pub default: Vec<PreprocessStep>,
+    /// Chains by stat name (e.g. "Food Worker", see `AOE4_STATS_POS`) or by text type
+    /// ("population", "idle", "resource" or "workers"). The stat name takes precedence.
+    pub regions: BTreeMap<String, Vec<PreprocessStep>>,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        Self {
+            default: vec![
+                PreprocessStep::Grayscale,
+                PreprocessStep::Brighten { amount: 30 },
+            ],
+            regions: BTreeMap::new(),
+        }
+    }
+}
+
+fn text_type_key(text_type: TextType) -> &'static str {
+    match text_type {
+        TextType::Population => "population",
+        TextType::Idle => "idle",
+        TextType::Resource(_) => "resource",
+        TextType::Workers(_) => "workers",
+        TextType::Unassigned => "",
+    }
+}
+
+impl PreprocessConfig {
+    /// Chain of the stat region with the given index in `AOE4_STATS_POS`
+    pub fn chain(&self, index: usize) -> &[PreprocessStep] {
+        let stat = &AOE4_STATS_POS[index];
+        self.regions
+            .get(stat.name)
+            .or_else(|| self.regions.get(text_type_key(stat.text_type)))
+            .unwrap_or(&self.default)
+    }
+
+    /// Check the region names and that no colour step follows a grayscale step
+    pub fn validate(&self) -> Result<()> {
+        for name in self.regions.keys() {
+            let known = AOE4_STATS_POS.iter().any(|stat| {
+                stat.name == name.as_str() || text_type_key(stat.text_type) == name.as_str()
+            });
+            if !known {
+                anyhow::bail!("Unknown preprocessing region '{}'", name);
+            }
+        }
+        let chains = std::iter::once(("default", &self.default)).chain(
+            self.regions
+                .iter()
+                .map(|(name, chain)| (name.as_str(), chain)),
+        );
+        for (name, chain) in chains {
+            let mut gray = false;
+            for step in chain {
+                if gray && step.needs_colour() {
+                    anyhow::bail!(
+                        "Preprocessing of '{}': {:?} after a grayscale step",
+                        name,
+                        step
+                    );
+                }
+                gray |= step.makes_gray();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run `steps` on a BGR image and convert the result for the OCR engines. With `stages`, the
+/// input and the output of every step are added to it.
+pub fn run(
+    steps: &[PreprocessStep],
+    img: &Mat,
+    mut stages: Option<&mut Vec<Mat>>,
+) -> Result<RgbImage> {
+    let mut current = img.try_clone()?;
+    if let Some(stages) = stages.as_deref_mut() {
+        stages.push(current.try_clone()?);
+    }
+    for step in steps {
+        current = step.apply(&current)?;
+        if let Some(stages) = stages.as_deref_mut() {
+            stages.push(current.try_clone()?);
+        }
+    }
+    to_rgb_image(&current)
+}
+
+/// RGB image of a BGR or gray image
+pub fn to_rgb_image(img: &Mat) -> Result<RgbImage> {
+    let code = if img.channels() == 1 {
+        imgproc::COLOR_GRAY2RGB
+    } else {
+        imgproc::COLOR_BGR2RGB
+    };
+    let mut rgb = Mat::default();
+    imgproc::cvt_color(img, &mut rgb, code, 0, AlgorithmHint::ALGO_HINT_DEFAULT)?;
+    RgbImage::from_raw(
+        rgb.cols() as u32,
+        rgb.rows() as u32,
+        rgb.data_bytes()?.to_vec(),
+    )
+    .ok_or_else(|| anyhow::anyhow!("Unexpected image size"))
+}
+
+/// Images stacked top to bottom, left aligned, with their positions as OCR regions
+pub fn stack<'a>(
+    images: impl IntoIterator<Item = &'a RgbImage>,
+) -> (RgbImage, Vec<(u32, u32, u32, u32)>) {
+    let images: Vec<&RgbImage> = images.into_iter().collect();
+    let width = images.iter().map(|image| image.width()).max().unwrap_or(0);
+    let height = images.iter().map(|image| image.height()).sum();
+    let mut stacked = RgbImage::new(width, height);
+    let mut regions = Vec::with_capacity(images.len());
+    let mut y = 0;
+    for image in images {
+        image::imageops::replace(&mut stacked, image, 0, y as i64);
+        regions.push((0, y, image.width(), image.height()));
+        y += image.height();
+    }
+    (stacked, regions)
+}
+
+/// Intermediate images of the preprocessing for the debug window: a row per region with the
+/// input and the output of every step, left to right
+#[derive(Clone)]
+pub struct PreprocessStages(pub RgbImage);
+
+impl fmt::Debug for PreprocessStages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PreprocessStages({}x{})",
+            self.0.width(),
+            self.0.height()
+        )
+    }
+}
+
+/// Gap between the images of the stage grid, in pixels
+const STAGE_GAP: u32 = 2;
+
+impl PreprocessStages {
+    /// Lay out the stages of all regions, see [`run`]
+    pub fn new(regions: &[Vec<Mat>]) -> Result<Self> {
+        let mut rows = Vec::with_capacity(regions.len());
+        for stages in regions {
+            let images = stages
+                .iter()
+                .map(to_rgb_image)
+                .collect::<Result<Vec<_>>>()?;
+            rows.push(images);
+        }
+        let row_width = |row: &Vec<RgbImage>| {
+            row.iter()
+                .map(|image| image.width() + STAGE_GAP)
+                .sum::<u32>()
+        };
+        let row_height = |row: &Vec<RgbImage>| row.iter().map(RgbImage::height).max().unwrap_or(0);
+        let width = rows.iter().map(row_width).max().unwrap_or(0);
+        let height = rows.iter().map(|row| row_height(row) + STAGE_GAP).sum();
+
+        let mut grid = RgbImage::new(width.max(1), height.max(1));
+        let mut y = 0;
+        for row in &rows {
+            let mut x = 0;
+            for image in row {
+                image::imageops::replace(&mut grid, image, x as i64, y as i64);
+                x += image.width() + STAGE_GAP;
+            }
+            y += row_height(row) + STAGE_GAP;
+        }
+        Ok(Self(grid))
+    }
+}
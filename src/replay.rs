@@ -0,0 +1,196 @@
+// Offline replay of recorded frames, replacing the portal and PipeWire
+
+use crate::{
+    frame_recorder::{FrameArea, RecordedFrame, SIDECAR_FILE},
+    pixelbuf_wrapper::{PixelBufWrapperWithDroppedFramesTS, PixelFormat},
+};
+use anyhow::Result;
+use log::{error, info, warn};
+use opencv::{
+    core::{AlgorithmHint, CV_8UC4, Mat, Rect, Scalar},
+    imgcodecs::{self, IMREAD_COLOR},
+    imgproc,
+    prelude::*,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::SyncSender,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A frame on disk and when it was captured, relative to the first frame
+pub struct ReplayFrame {
+    pub path: PathBuf,
+    pub timestamp: Duration,
+    /// Size of the original frame and the part of it stored in the file, if only a part was
+    /// recorded
+    pub crop: Option<((i32, i32), FrameArea)>,
+}
+
+/// Collect the frames of a directory. Recordings with a sidecar file are replayed with their
+/// original timestamps, otherwise all PNG/JPEG files are taken in file name order and spaced by
+/// `frame_interval`.
+pub fn load_frames(dir: &Path, frame_interval: Duration) -> Result<Vec<ReplayFrame>> {
+    if dir.join(SIDECAR_FILE).exists() {
+        let recorded = RecordedFrame::load_sidecar(dir)?;
+        let Some(first_timestamp) = recorded.first().map(|frame| frame.timestamp_ms) else {
+            anyhow::bail!("{} of {} is empty", SIDECAR_FILE, dir.display());
+        };
+        return Ok(recorded
+            .into_iter()
+            .map(|frame| ReplayFrame {
+                path: dir.join(&frame.file),
+                timestamp: Duration::from_millis(
+                    frame.timestamp_ms.saturating_sub(first_timestamp),
+                ),
+                crop: frame.crop.map(|crop| ((frame.width, frame.height), crop)),
+            })
+            .collect());
+    }
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| matches!(e.to_ascii_lowercase().as_str(), "png" | "jpg" | "jpeg"))
+        })
+        .collect();
+    paths.sort();
+
+    if paths.is_empty() {
+        anyhow::bail!("No PNG/JPEG frames found in {}", dir.display());
+    }
+
+    Ok(paths
+        .into_iter()
+        .enumerate()
+        .map(|(index, path)| ReplayFrame {
+            path,
+            timestamp: frame_interval * index as u32,
+            crop: None,
+        })
+        .collect())
+}
+
+pub struct ReplayStopHandler {
+    stop: Arc<AtomicBool>,
+    join_handle: thread::JoinHandle<()>,
+}
+
+impl ReplayStopHandler {
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.join_handle.join();
+    }
+}
+
+/// Load a frame as BGRA, the format PipeWire delivers. A recorded part of a frame is placed at
+/// its original position in an otherwise black frame.
+fn load_bgra(frame: &ReplayFrame) -> Result<Mat> {
+    let path = &frame.path;
+    let bgr = imgcodecs::imread(&path.to_string_lossy(), IMREAD_COLOR)?;
+    if bgr.empty() {
+        anyhow::bail!("Failed to load frame from {}", path.display());
+    }
+    let mut bgra = Mat::default();
+    imgproc::cvt_color(
+        &bgr,
+        &mut bgra,
+        imgproc::COLOR_BGR2BGRA,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+
+    let Some(((width, height), crop)) = frame.crop else {
+        return Ok(bgra);
+    };
+    let mut full = Mat::new_rows_cols_with_default(height, width, CV_8UC4, Scalar::all(0.0))?;
+    let mut roi = Mat::roi_mut(
+        &mut full,
+        Rect::new(crop.x as i32, crop.y as i32, bgra.cols(), bgra.rows()),
+    )?;
+    bgra.copy_to(&mut roi)?;
+    Ok(full)
+}
+
+/// Push the frames into the frame processor, like the PipeWire `process` callback does.
+///
+/// `speed` is the playback speed relative to the original pace. With a speed of 0 frames are
+/// pushed as fast as the frame processor consumes them, none are dropped. The frame processor is
+/// told to quit after the last frame.
+pub fn run(
+    frames: Vec<ReplayFrame>,
+    speed: f32,
+    sender: SyncSender<bool>,
+    image_sender_content: PixelBufWrapperWithDroppedFramesTS,
+) -> ReplayStopHandler {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_clone = stop.clone();
+
+    let join_handle = thread::spawn(move || {
+        info!("Replaying {} frames at speed {}", frames.len(), speed);
+        let start = Instant::now();
+
+        for frame in frames {
+            if stop_clone.load(Ordering::Relaxed) {
+                break;
+            }
+
+            if speed > 0.0 {
+                let due = frame.timestamp.div_f32(speed);
+                if let Some(wait) = due.checked_sub(start.elapsed()) {
+                    thread::sleep(wait);
+                }
+            } else {
+                // Wait until the frame processor picked up the previous frame
+                while image_sender_content.lock().unwrap().frames_written > 0 {
+                    if stop_clone.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+
+            let mat = match load_bgra(&frame) {
+                Ok(mat) => mat,
+                Err(e) => {
+                    warn!("Skipping frame: {}", e);
+                    continue;
+                }
+            };
+            let width = mat.cols();
+            let height = mat.rows();
+            let data = match mat.data_bytes() {
+                Ok(data) => data,
+                Err(e) => {
+                    error!("Failed to access frame data of {}: {}", frame.path.display(), e);
+                    continue;
+                }
+            };
+
+            if let Ok(mut content) = image_sender_content.lock() {
+                content.pixbuf.copy_from_slice(data, width, height, width * 4);
+                content.pixbuf.format = PixelFormat::BGRA;
+                content.pixbuf.timestamp_ms = frame.timestamp.as_millis() as u64;
+                content.frames_written += 1;
+            }
+
+            if speed > 0.0 {
+                let _ = sender.try_send(true);
+            } else if sender.send(true).is_err() {
+                break;
+            }
+        }
+
+        info!("Replay finished");
+        let _ = sender.send(false);
+    });
+
+    ReplayStopHandler { stop, join_handle }
+}
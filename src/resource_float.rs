@@ -0,0 +1,115 @@
+// Detection of resources that stay banked instead of being spent
+
+use crate::{
+    alerts::{Alert, AlertTiming},
+    game_state::{GameState, PerResource, Reading, Resource},
+    i18n,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FloatConfig {
+    pub enabled: bool,
+    /// A resource floats when its stock is above the threshold
+    pub thresholds: PerResource<u32>,
+    /// `min_duration_ms` is how long a stock must stay above its threshold before the warning
+    #[serde(flatten)]
+    pub timing: AlertTiming,
+    /// Priority of the warning among the alerts
+    pub priority: i32,
+}
+
+impl Default for FloatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            thresholds: PerResource {
+                food: 800,
+                wood: 800,
+                gold: 600,
+                stone: 600,
+            },
+            timing: AlertTiming {
+                min_duration_ms: 30_000,
+                ..Default::default()
+            },
+            priority: 15,
+        }
+    }
+}
+
+/// Tracks how long every resource stock has been above its threshold
+#[derive(Debug, Clone, Default)]
+pub struct FloatDetector {
+    config: FloatConfig,
+    /// Since when the stock is above the threshold
+    above_since_ms: PerResource<Option<u64>>,
+}
+
+impl FloatDetector {
+    pub fn new(config: &FloatConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_config(&mut self, config: &FloatConfig) {
+        if self.config != *config {
+            *self = Self::new(config);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.above_since_ms = PerResource::default();
+    }
+
+    /// Resources floating for at least `min_duration_ms`, and since when the first of them does
+    fn update(&mut self, state: &GameState, timestamp_ms: u64) -> (Vec<Resource>, u64) {
+        let mut floating = Vec::new();
+        let mut floating_since_ms = u64::MAX;
+        if !self.config.enabled {
+            return (floating, floating_since_ms);
+        }
+
+        for resource in Resource::ALL {
+            let threshold = *self.config.thresholds.get(resource);
+            let above_since_ms = self.above_since_ms.get_mut(resource);
+            match state.resources.get(resource) {
+                Reading::Known(stock) if *stock > threshold => {
+                    let since = *above_since_ms.get_or_insert(timestamp_ms);
+                    let floating_from = since + self.config.timing.min_duration_ms;
+                    if timestamp_ms >= floating_from {
+                        floating.push(resource);
+                        floating_since_ms = floating_since_ms.min(floating_from);
+                    }
+                }
+                Reading::Known(_) => *above_since_ms = None,
+                // Unreadable stocks keep their timer but are not listed
+                _ => {}
+            }
+        }
+        (floating, floating_since_ms)
+    }
+
+    /// The warning for the floating resources. The message and the resource names are catalog
+    /// keys, which the overlay translates, e.g. to "Spend your resources! Food, Wood".
+    pub fn alert(&mut self, state: &GameState, timestamp_ms: u64) -> Option<Alert> {
+        let (floating, since_ms) = self.update(state, timestamp_ms);
+        if floating.is_empty() {
+            return None;
+        }
+        Some(Alert {
+            name: "float".to_string(),
+            priority: self.config.priority,
+            message: Some("alert.float".to_string()),
+            message_args: floating
+                .iter()
+                .map(|resource| i18n::stat_key(&format!("{:?}", resource)))
+                .collect(),
+            icon: None,
+            since_ms,
+        })
+    }
+}
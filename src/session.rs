@@ -0,0 +1,144 @@
+// Match session detection from the presence of the in-game HUD
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Without detection every frame counts as in-game and the whole run is one match
+    pub enabled: bool,
+    /// Minimum number of HUD icons (population and resources) found for the in-game HUD
+    pub min_hud_anchors: u32,
+    /// Frames without HUD darker than this (mean brightness 0 - 255) are loading screens
+    pub loading_max_brightness: f32,
+    /// Consecutive frames a screen must be seen before a match starts or a loading screen ends it
+    pub confirm_frames: u32,
+    /// The match ends when the HUD was not seen for this long, in milliseconds
+    pub end_timeout_ms: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_hud_anchors: 3,
+            loading_max_brightness: 35.0,
+            confirm_frames: 3,
+            end_timeout_ms: 30_000,
+        }
+    }
+}
+
+/// What a frame shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Screen {
+    InGame,
+    Loading,
+    /// Main menu, lobby or anything else without HUD
+    #[default]
+    Menu,
+}
+
+impl Screen {
+    /// Classify a frame by the number of HUD icons found in it and its mean brightness, which is
+    /// only computed for frames without HUD
+    pub fn classify(
+        hud_anchors: u32,
+        brightness: impl FnOnce() -> f32,
+        config: &SessionConfig,
+    ) -> Self {
+        if !config.enabled || hud_anchors >= config.min_hud_anchors {
+            Screen::InGame
+        } else if brightness() <= config.loading_max_brightness {
+            Screen::Loading
+        } else {
+            Screen::Menu
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEvent {
+    MatchStarted,
+    MatchEnded,
+}
+
+/// Turns the screen classification of consecutive frames into match start and end events
+#[derive(Debug, Clone, Default)]
+pub struct SessionDetector {
+    config: SessionConfig,
+    in_match: bool,
+    /// The last screen and in how many consecutive frames it was seen
+    screen: Screen,
+    screen_frames: u32,
+    last_timestamp_ms: u64,
+    /// Timestamp of the last in-game frame
+    last_in_game_ms: u64,
+}
+
+impl SessionDetector {
+    pub fn new(config: &SessionConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_config(&mut self, config: &SessionConfig) {
+        self.config = config.clone();
+    }
+
+    pub fn in_match(&self) -> bool {
+        self.in_match
+    }
+
+    /// Screen of the last frame
+    pub fn screen(&self) -> Screen {
+        self.screen
+    }
+
+    /// End a running match, e.g. when capturing stops
+    pub fn finish(&mut self) -> Option<SessionEvent> {
+        self.screen_frames = 0;
+        std::mem::take(&mut self.in_match).then_some(SessionEvent::MatchEnded)
+    }
+
+    /// Add the classification of a frame captured at `timestamp_ms`
+    pub fn update(&mut self, screen: Screen, timestamp_ms: u64) -> Option<SessionEvent> {
+        if timestamp_ms < self.last_timestamp_ms {
+            // Time went backwards, e.g. a replay restarted
+            self.last_timestamp_ms = timestamp_ms;
+            self.screen = screen;
+            return self.finish();
+        }
+        self.last_timestamp_ms = timestamp_ms;
+
+        if screen == self.screen {
+            self.screen_frames += 1;
+        } else {
+            self.screen = screen;
+            self.screen_frames = 1;
+        }
+        let confirmed = self.screen_frames >= self.config.confirm_frames.max(1);
+
+        if screen == Screen::InGame {
+            self.last_in_game_ms = timestamp_ms;
+            if !self.in_match && confirmed {
+                self.in_match = true;
+                return Some(SessionEvent::MatchStarted);
+            }
+            return None;
+        }
+
+        // A loading screen means the next match is about to start
+        let timed_out =
+            timestamp_ms.saturating_sub(self.last_in_game_ms) > self.config.end_timeout_ms;
+        if self.in_match && ((screen == Screen::Loading && confirmed) || timed_out) {
+            self.in_match = false;
+            return Some(SessionEvent::MatchEnded);
+        }
+        None
+    }
+}
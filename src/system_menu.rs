@@ -2,7 +2,7 @@ use anyhow::Result;
 use gtk::gio;
 use log::info;
 use gtk::prelude::{ActionMapExt, ApplicationExt};
-use crate::overlay_window_gtk::GuiCommand;
+use crate::{i18n, overlay_window_gtk::GuiCommand};
 
 pub struct SystemTray {
     _app: gtk::Application,
@@ -29,9 +29,9 @@ impl SystemTray {
         app.register(None::<&gio::Cancellable>)?;
 
         // Create a notification to indicate the app is running
-        let notification = gio::Notification::new("AOE4 Overlay");
-        notification.set_body(Some("Running - Press Ctrl+C to quit"));
-        notification.add_button("Quit", "app.quit");
+        let notification = gio::Notification::new(&i18n::tr("notification.title"));
+        notification.set_body(Some(&i18n::tr("notification.running")));
+        notification.add_button(&i18n::tr("notification.quit"), "app.quit");
         app.send_notification(Some("running"), &notification);
 
         Ok(Self {
@@ -3,6 +3,7 @@ use libappindicator_zbus::{
         ButtonOptions, EventUpdate, IconPixmap, MenuStatus, MenuUnit,
     },
 };
+use crate::i18n;
 use zbus::fdo::Result;
 
 // Binary include "logo.png" as a byte array
@@ -41,7 +42,7 @@ impl Menu {
         let menu = MenuUnit::root()
             .push_sub_menu(MenuUnit::button(
                 ButtonOptions {
-                    label: "Quit".to_owned(),
+                    label: i18n::tr("tray.quit"),
                     enabled: true,
                     icon_name: "nheko".to_owned(),
                 },
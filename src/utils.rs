@@ -1,4 +1,25 @@
+use std::path::PathBuf;
+
 pub fn is_wayland() -> bool {
     std::env::var("XDG_SESSION_TYPE")
         .unwrap_or_default() == "wayland"
-}
\ No newline at end of file
+}
+
+/// `$XDG_CONFIG_HOME/aoe4_overlay`, falling back to `~/.config/aoe4_overlay`
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config").join("aoe4_overlay")
+}
+
+/// `$XDG_DATA_HOME/aoe4_overlay`, falling back to `~/.local/share/aoe4_overlay`
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share").join("aoe4_overlay")
+}
+
+fn xdg_dir(variable: &str, home_fallback: &str) -> PathBuf {
+    std::env::var_os(variable)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(home_fallback)
+        })
+}
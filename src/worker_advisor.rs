@@ -0,0 +1,172 @@
+// Comparison of the worker distribution with target ratios
+
+use crate::{
+    game_state::{GameState, PerResource, Resource},
+    i18n,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Target worker ratios in one game phase. Ratios are relative to each other and need not add
+/// up to 1.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTarget {
+    /// Name of a phase of the match statistics, e.g. `phase.early`
+    pub phase: String,
+    pub ratio: PerResource<f32>,
+}
+
+/// Targets of a civilization or strategy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerProfile {
+    pub name: String,
+    pub targets: Vec<PhaseTarget>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkerConfig {
+    pub enabled: bool,
+    /// Name of the profile to use
+    pub profile: String,
+    pub profiles: Vec<WorkerProfile>,
+    /// Smallest difference to the target worker count that is shown
+    pub min_imbalance: u32,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        let target = |phase: &str, food, wood, gold, stone| PhaseTarget {
+            phase: phase.to_string(),
+            ratio: PerResource {
+                food,
+                wood,
+                gold,
+                stone,
+            },
+        };
+        Self {
+            enabled: true,
+            profile: "standard".to_string(),
+            profiles: vec![
+                WorkerProfile {
+                    name: "standard".to_string(),
+                    targets: vec![
+                        target("phase.opening", 0.6, 0.4, 0.0, 0.0),
+                        target("phase.early", 0.5, 0.3, 0.2, 0.0),
+                        target("phase.mid", 0.4, 0.3, 0.3, 0.0),
+                        target("phase.late", 0.35, 0.3, 0.3, 0.05),
+                    ],
+                },
+                WorkerProfile {
+                    name: "fast_castle".to_string(),
+                    targets: vec![
+                        target("phase.opening", 0.5, 0.3, 0.2, 0.0),
+                        target("phase.early", 0.45, 0.2, 0.35, 0.0),
+                        target("phase.mid", 0.4, 0.25, 0.35, 0.0),
+                        target("phase.late", 0.35, 0.3, 0.3, 0.05),
+                    ],
+                },
+            ],
+            min_imbalance: 2,
+        }
+    }
+}
+
+/// Difference of the worker count of a resource to its target
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Imbalance {
+    pub resource: Resource,
+    /// Positive if there are more workers than targeted
+    pub difference: i32,
+}
+
+/// Biggest imbalances of the worker distribution, displayed as e.g. "+4 wood, -3 gold"
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkerAdvice {
+    pub surplus: Option<Imbalance>,
+    pub deficit: Option<Imbalance>,
+}
+
+impl fmt::Display for WorkerAdvice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = [self.surplus, self.deficit]
+            .into_iter()
+            .flatten()
+            .map(|imbalance| {
+                let key = format!("resource.{:?}", imbalance.resource).to_lowercase();
+                format!("{:+} {}", imbalance.difference, i18n::tr(&key))
+            })
+            .collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WorkerAdvisor {
+    config: WorkerConfig,
+}
+
+impl WorkerAdvisor {
+    pub fn new(config: &WorkerConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: &WorkerConfig) {
+        self.config = config.clone();
+    }
+
+    /// Target ratios of the configured profile for a phase. The last target applies to phases
+    /// without their own.
+    fn target(&self, phase: &str) -> Option<&PerResource<f32>> {
+        let profile = self.config.profiles.iter().find(|p| p.name == self.config.profile)?;
+        profile
+            .targets
+            .iter()
+            .find(|target| target.phase == phase)
+            .or(profile.targets.last())
+            .map(|target| &target.ratio)
+    }
+
+    /// Compare the worker counts with the targets of `phase`. `None` if disabled, a worker count
+    /// is unknown or the distribution is balanced.
+    pub fn advise(&self, state: &GameState, phase: &str) -> Option<WorkerAdvice> {
+        if !self.config.enabled {
+            return None;
+        }
+        let ratio = self.target(phase)?;
+        let ratio_sum: f32 = Resource::ALL.iter().map(|r| ratio.get(*r).max(0.0)).sum();
+        if ratio_sum <= 0.0 {
+            return None;
+        }
+        let mut workers = PerResource::default();
+        for resource in Resource::ALL {
+            *workers.get_mut(resource) = state.workers.get(resource).known()?;
+        }
+        let total: u32 = Resource::ALL.iter().map(|r| *workers.get(*r)).sum();
+
+        let mut advice = WorkerAdvice::default();
+        for resource in Resource::ALL {
+            let target = (total as f32 * ratio.get(resource).max(0.0) / ratio_sum).round() as i32;
+            let difference = *workers.get(resource) as i32 - target;
+            if difference.unsigned_abs() < self.config.min_imbalance.max(1) {
+                continue;
+            }
+            let imbalance = Imbalance {
+                resource,
+                difference,
+            };
+            let biggest = if difference > 0 {
+                &mut advice.surplus
+            } else {
+                &mut advice.deficit
+            };
+            if biggest.is_none_or(|b| b.difference.abs() < difference.abs()) {
+                *biggest = Some(imbalance);
+            }
+        }
+        (advice.surplus.is_some() || advice.deficit.is_some()).then_some(advice)
+    }
+}
@@ -0,0 +1,299 @@
+// Alert conditions and the rules of the default configuration
+
+use aoe4_overlay::{
+    alerts::{AlertConfig, AlertEngine, AlertRule, AlertTiming, Condition},
+    game_state::{GameState, Population},
+};
+use std::collections::BTreeMap;
+
+fn state(population: Option<(u32, u32)>, idle: Option<u32>, villager: bool) -> GameState {
+    GameState {
+        population: population
+            .map(|(current, cap)| Population { current, cap })
+            .into(),
+        idle_villagers: idle.into(),
+        villager_in_production: villager,
+        ..Default::default()
+    }
+}
+
+fn parse(source: &str) -> Condition {
+    Condition::parse(source).unwrap_or_else(|e| panic!("'{}': {}", source, e))
+}
+
+/// Engine with the single rule `idle_villagers > 0`
+fn idle_engine(timing: AlertTiming) -> AlertEngine {
+    AlertEngine::new(&AlertConfig {
+        rules: vec![AlertRule {
+            name: "idle".to_string(),
+            condition: parse("idle_villagers > 0"),
+            timing,
+            priority: 0,
+            message: None,
+            icon: None,
+        }],
+        ..Default::default()
+    })
+}
+
+/// Since when the idle alert is shown after a frame with `idle` idle villagers
+fn idle_since(engine: &mut AlertEngine, idle: Option<u32>, timestamp_ms: u64) -> Option<u64> {
+    let alerts = engine.update(
+        &state(Some((5, 10)), idle, true),
+        &BTreeMap::new(),
+        timestamp_ms,
+    );
+    alerts.first().map(|alert| alert.since_ms)
+}
+
+#[test]
+fn test_and_binds_stronger_than_or() {
+    // Parsed as `idle_villagers > 0 or (population > 100 and villager_in_production)`
+    for source in [
+        "idle_villagers > 0 or population > 100 and villager_in_production",
+        "idle_villagers > 0 || population > 100 && villager_in_production",
+    ] {
+        let condition = parse(source);
+        assert!(condition.is_met(&state(Some((5, 10)), Some(1), false)));
+        assert!(!condition.is_met(&state(Some((5, 10)), Some(0), true)));
+    }
+
+    let grouped = parse("(idle_villagers > 0 or population > 100) and villager_in_production");
+    assert!(!grouped.is_met(&state(Some((5, 10)), Some(1), false)));
+    assert!(grouped.is_met(&state(Some((5, 10)), Some(1), true)));
+}
+
+#[test]
+fn test_not_binds_stronger_than_and() {
+    // Parsed as `(not villager_in_production) and population > 0`
+    let condition = parse("not villager_in_production and population > 0");
+    assert!(condition.is_met(&state(Some((5, 10)), None, false)));
+    assert!(!condition.is_met(&state(Some((5, 10)), None, true)));
+    assert!(!condition.is_met(&state(Some((0, 10)), None, false)));
+
+    let negated = parse("!(villager_in_production and population > 0)");
+    assert!(negated.is_met(&state(Some((0, 10)), None, true)));
+    assert!(parse("not not villager_in_production").is_met(&state(None, None, true)));
+}
+
+#[test]
+fn test_sums_and_comparisons() {
+    let full = state(Some((10, 10)), Some(0), true);
+    let free = state(Some((7, 10)), Some(0), true);
+    for (source, on_full, on_free) in [
+        ("population == population_cap", true, false),
+        ("population = population_cap", true, false),
+        ("population != population_cap", false, true),
+        ("population + 2 >= population_cap", true, false),
+        ("population_cap - population < 3", true, false),
+        ("population <= 7", false, true),
+        ("population > 7.5", true, false),
+        ("10 - population", false, true),
+    ] {
+        let condition = parse(source);
+        assert_eq!(condition.is_met(&full), on_full, "{} on 10/10", source);
+        assert_eq!(condition.is_met(&free), on_free, "{} on 7/10", source);
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = Condition::parse("idle > 0").unwrap_err();
+    assert!(error.contains("Unknown field 'idle'"), "{}", error);
+    assert!(error.contains("idle_villagers"), "{}", error);
+
+    let error = Condition::parse("idle_villagers > 0 population").unwrap_err();
+    assert!(error.contains("Unexpected"), "{}", error);
+    assert!(Condition::parse("idle_villagers > 0 )").is_err());
+    assert!(Condition::parse("idle_villagers > 0 < 1").is_err());
+
+    for source in [
+        "",
+        "idle_villagers >",
+        "(idle_villagers > 1",
+        "idle_villagers > 0 and",
+        "food # 1",
+        "food > 1.2.3",
+        "food > < 1",
+    ] {
+        assert!(
+            Condition::parse(source).is_err(),
+            "'{}' was accepted",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_unknown_readings() {
+    let unknown = GameState::default();
+    let idle = parse("idle_villagers > 0");
+    assert_eq!(idle.eval(&unknown), None);
+    assert!(!idle.is_met(&unknown));
+    // Unknown values are neither true nor false, also when negated
+    assert_eq!(parse("not idle_villagers > 0").eval(&unknown), None);
+
+    // A known operand decides `and` and `or` on its own
+    let villager = state(None, None, true);
+    assert_eq!(
+        parse("idle_villagers > 0 and not villager_in_production").eval(&villager),
+        Some(false)
+    );
+    assert_eq!(
+        parse("idle_villagers > 0 or villager_in_production").eval(&villager),
+        Some(true)
+    );
+    assert_eq!(
+        parse("idle_villagers > 0 and villager_in_production").eval(&villager),
+        None
+    );
+}
+
+#[test]
+fn test_default_rules_need_population() {
+    let mut engine = AlertEngine::new(&AlertConfig::default());
+    // Idle villagers read, e.g. in a menu, but no population
+    let no_icons = BTreeMap::new();
+    assert!(
+        engine
+            .update(&state(None, Some(3), false), &no_icons, 0)
+            .is_empty()
+    );
+
+    let alerts = engine.update(&state(Some((9, 10)), Some(2), false), &no_icons, 100);
+    let names: Vec<_> = alerts.iter().map(|alert| alert.name.as_str()).collect();
+    assert_eq!(names, ["house", "idle", "villager"]);
+}
+
+#[test]
+fn test_icon_fields() {
+    let icons = BTreeMap::from([
+        ("idle_scout".to_string(), true),
+        ("villager".to_string(), false),
+    ]);
+    let running = state(Some((5, 10)), None, true);
+
+    let scout = parse("icon.idle_scout and population > 0");
+    assert_eq!(scout.eval_with_icons(&running, &icons), Some(true));
+    assert_eq!(
+        parse("not icon.villager").eval_with_icons(&running, &icons),
+        Some(true)
+    );
+    assert_eq!(
+        parse("icon.villager + icon.idle_scout == 1").eval_with_icons(&running, &icons),
+        Some(true)
+    );
+    // Icons that were not searched for are unknown
+    assert_eq!(
+        parse("icon.town_center_attacked").eval_with_icons(&running, &icons),
+        None
+    );
+    assert_eq!(scout.eval(&running), None);
+    assert!(Condition::parse("icon.").is_err());
+
+    let mut config = AlertConfig::default();
+    config.rules.push(AlertRule {
+        name: "scout".to_string(),
+        condition: scout,
+        timing: AlertTiming::default(),
+        priority: 40,
+        message: None,
+        icon: None,
+    });
+    let mut engine = AlertEngine::new(&config);
+    let alerts = engine.update(&running, &icons, 0);
+    assert_eq!(
+        alerts.first().map(|alert| alert.name.as_str()),
+        Some("scout")
+    );
+}
+
+#[test]
+fn test_condition_round_trip() -> anyhow::Result<()> {
+    let config = AlertConfig::default();
+    let serialized = toml::to_string(&config)?;
+    assert_eq!(toml::from_str::<AlertConfig>(&serialized)?, config);
+
+    let error =
+        toml::from_str::<AlertConfig>("[[rules]]\nname = 'x'\ncondition = 'idle >'\n").unwrap_err();
+    assert!(
+        error.to_string().contains("Unknown field 'idle'"),
+        "{}",
+        error
+    );
+    Ok(())
+}
+
+#[test]
+fn test_flicker_suppressed() {
+    let mut engine = idle_engine(AlertTiming {
+        min_duration_ms: 1000,
+        deactivation_delay_ms: 500,
+        min_display_ms: 0,
+        cooldown_ms: 0,
+    });
+    // A single false frame restarts the wait for the condition
+    assert_eq!(idle_since(&mut engine, Some(1), 0), None);
+    assert_eq!(idle_since(&mut engine, Some(0), 500), None);
+    assert_eq!(idle_since(&mut engine, Some(1), 600), None);
+    assert_eq!(idle_since(&mut engine, Some(1), 1599), None);
+    assert_eq!(idle_since(&mut engine, Some(1), 1600), Some(1600));
+
+    // A single false frame within the deactivation delay keeps the alert
+    assert_eq!(idle_since(&mut engine, Some(0), 2000), Some(1600));
+    assert_eq!(idle_since(&mut engine, Some(1), 2100), Some(1600));
+    assert_eq!(idle_since(&mut engine, Some(0), 2200), Some(1600));
+    assert_eq!(idle_since(&mut engine, Some(0), 2699), Some(1600));
+    assert_eq!(idle_since(&mut engine, Some(0), 2700), None);
+}
+
+#[test]
+fn test_min_display_hold() {
+    let mut engine = idle_engine(AlertTiming {
+        min_duration_ms: 0,
+        deactivation_delay_ms: 0,
+        min_display_ms: 2000,
+        cooldown_ms: 0,
+    });
+    assert_eq!(idle_since(&mut engine, Some(1), 1000), Some(1000));
+    assert_eq!(idle_since(&mut engine, Some(0), 1100), Some(1000));
+    assert_eq!(idle_since(&mut engine, Some(0), 2999), Some(1000));
+    assert_eq!(idle_since(&mut engine, Some(0), 3000), None);
+}
+
+#[test]
+fn test_cooldown_after_reaction() {
+    let mut engine = idle_engine(AlertTiming {
+        min_duration_ms: 0,
+        deactivation_delay_ms: 0,
+        min_display_ms: 0,
+        cooldown_ms: 5000,
+    });
+    assert_eq!(idle_since(&mut engine, Some(1), 0), Some(0));
+    // The player reacted at 100, the cooldown runs until 5100
+    assert_eq!(idle_since(&mut engine, Some(0), 100), None);
+    assert_eq!(idle_since(&mut engine, Some(1), 1000), None);
+    assert_eq!(idle_since(&mut engine, Some(1), 5099), None);
+    assert_eq!(idle_since(&mut engine, Some(1), 5100), Some(5100));
+
+    // Without reaction the alert stays regardless of the cooldown
+    assert_eq!(idle_since(&mut engine, Some(1), 20_000), Some(5100));
+}
+
+#[test]
+fn test_unknown_reading_keeps_alert_state() {
+    let mut engine = idle_engine(AlertTiming {
+        min_duration_ms: 1000,
+        deactivation_delay_ms: 0,
+        min_display_ms: 0,
+        cooldown_ms: 0,
+    });
+    assert_eq!(idle_since(&mut engine, None, 0), None);
+    // Unknown readings neither restart the wait nor count as false
+    assert_eq!(idle_since(&mut engine, Some(1), 100), None);
+    assert_eq!(idle_since(&mut engine, None, 600), None);
+    assert_eq!(idle_since(&mut engine, Some(1), 1100), Some(1100));
+    assert_eq!(idle_since(&mut engine, None, 10_000), Some(1100));
+    assert_eq!(idle_since(&mut engine, Some(0), 10_100), None);
+    assert_eq!(idle_since(&mut engine, None, 20_000), None);
+}
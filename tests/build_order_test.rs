@@ -0,0 +1,291 @@
+// Build order import, step targets and the advancement of the guide
+
+use anyhow::Result;
+use aoe4_overlay::{
+    build_order::{BuildOrder, BuildOrderGuide, BuildOrderStep, Civilization, StepResources},
+    game_state::{Age, GameState, PerResource, Population, Reading},
+};
+
+const COMMUNITY_JSON: &str = r#"{
+    "name": "English Longbow Rush",
+    "civilization": ["English", "Abbasid Dynasty"],
+    "author": "someone",
+    "build_order": [
+        {
+            "population_count": -1,
+            "villager_count": 6,
+            "age": 1,
+            "resources": { "food": 6, "wood": 0, "gold": 0, "stone": -1 },
+            "notes": ["@icon/sheep.png@ 6 on sheep"]
+        },
+        {
+            "population_count": 10,
+            "villager_count": -1,
+            "age": 2,
+            "resources": { "food": 6, "wood": 4, "gold": 0, "stone": 0, "builder": 1 },
+            "time": "2:30",
+            "notes": ["Build a house", "Age up"]
+        }
+    ]
+}"#;
+
+fn state(food: u32, wood: u32, idle: u32, population: u32) -> GameState {
+    GameState {
+        population: Reading::Known(Population {
+            current: population,
+            cap: 200,
+        }),
+        idle_villagers: Reading::Known(idle),
+        workers: PerResource {
+            food: Reading::Known(food),
+            wood: Reading::Known(wood),
+            gold: Reading::Known(0),
+            stone: Reading::Known(0),
+        },
+        ..Default::default()
+    }
+}
+
+fn step(notes: &[&str]) -> BuildOrderStep {
+    BuildOrderStep {
+        notes: notes.iter().map(|note| note.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn food_steps(targets: &[i32]) -> BuildOrder {
+    BuildOrder {
+        name: "Food".to_string(),
+        build_order: targets
+            .iter()
+            .map(|food| BuildOrderStep {
+                resources: StepResources {
+                    food: *food,
+                    ..Default::default()
+                },
+                notes: vec![format!("{} on food", food)],
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_community_json() -> Result<()> {
+    let build_order: BuildOrder = serde_json::from_str(COMMUNITY_JSON)?;
+    assert_eq!(build_order.name, "English Longbow Rush");
+    assert_eq!(
+        build_order.civilization,
+        Some(Civilization::Many(vec![
+            "English".to_string(),
+            "Abbasid Dynasty".to_string()
+        ]))
+    );
+
+    let [first, second] = build_order.build_order.as_slice() else {
+        panic!("two steps expected");
+    };
+    assert_eq!(first.population_count, -1);
+    assert_eq!(first.villager_count, 6);
+    assert_eq!(first.resources.food, 6);
+    assert_eq!(first.resources.stone, -1);
+    // Fields that are left out are unspecified as well
+    assert_eq!(first.resources.builder, -1);
+    assert_eq!(first.time, None);
+    assert_eq!(second.age, 2);
+    assert_eq!(second.resources.builder, 1);
+    assert_eq!(second.time.as_deref(), Some("2:30"));
+
+    let step: BuildOrderStep = serde_json::from_str(r#"{ "notes": [] }"#)?;
+    assert_eq!(step.population_count, -1);
+    assert_eq!(step.villager_count, -1);
+    assert_eq!(step.resources, StepResources::default());
+    Ok(())
+}
+
+#[test]
+fn test_toml_file() -> Result<()> {
+    let dir =
+        std::env::temp_dir().join(format!("aoe4_overlay_build_order_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let toml_path = dir.join("rush.toml");
+    std::fs::write(
+        &toml_path,
+        r#"
+        name = "Rush"
+        civilization = "French"
+
+        [[build_order]]
+        villager_count = 6
+        resources = { food = 6 }
+        notes = ["6 on sheep"]
+        "#,
+    )?;
+    let json_path = dir.join("rush.json");
+    std::fs::write(&json_path, COMMUNITY_JSON)?;
+    let empty_path = dir.join("empty.toml");
+    std::fs::write(&empty_path, "name = \"Empty\"\nbuild_order = []\n")?;
+
+    let from_toml = BuildOrder::load(&toml_path);
+    let from_json = BuildOrder::load(&json_path);
+    let empty = BuildOrder::load(&empty_path);
+    std::fs::remove_dir_all(&dir)?;
+
+    let from_toml = from_toml?;
+    assert_eq!(
+        from_toml.civilization,
+        Some(Civilization::One("French".to_string()))
+    );
+    assert_eq!(from_toml.build_order[0].resources.food, 6);
+    assert_eq!(from_toml.build_order[0].resources.wood, -1);
+    assert_eq!(from_toml.build_order[0].population_count, -1);
+    assert_eq!(from_json?.build_order.len(), 2);
+    assert!(empty.unwrap_err().to_string().contains("has no steps"));
+    Ok(())
+}
+
+#[test]
+fn test_text() {
+    assert_eq!(step(&["@icon/sheep.png@ 6 on sheep"]).text(), "6 on sheep");
+    assert_eq!(
+        step(&[
+            "Build a @icon/house.png@house",
+            "",
+            "@a.png@ @b.png@",
+            "Age up"
+        ])
+        .text(),
+        "Build a house\nAge up"
+    );
+    // Only references to image files are removed
+    assert_eq!(step(&["5 @ wood"]).text(), "5 wood");
+    assert_eq!(step(&[]).text(), "");
+}
+
+#[test]
+fn test_is_reached() {
+    let build_order: BuildOrder = serde_json::from_str(COMMUNITY_JSON).unwrap();
+    let [first, second] = build_order.build_order.as_slice() else {
+        panic!("two steps expected");
+    };
+
+    assert!(!first.is_reached(&state(5, 0, 0, 6)));
+    assert!(first.is_reached(&state(6, 0, 0, 7)));
+    // Unknown readings never reach a target
+    let mut unknown_workers = state(6, 0, 0, 7);
+    unknown_workers.workers.gold = Reading::Unknown;
+    assert!(!first.is_reached(&unknown_workers));
+    // Unspecified targets don't need a reading
+    let food_only = BuildOrderStep {
+        resources: StepResources {
+            food: 6,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut unknown_stone = state(6, 0, 0, 7);
+    unknown_stone.workers.stone = Reading::Unknown;
+    unknown_stone.population = Reading::Unknown;
+    assert!(food_only.is_reached(&unknown_stone));
+    assert!(!first.is_reached(&unknown_stone));
+
+    // The age is only checked once it was detected, advancing counts as the next age
+    let mut feudal = state(6, 4, 0, 10);
+    assert!(second.is_reached(&feudal));
+    feudal.age = Reading::Known(Age::Dark);
+    assert!(!second.is_reached(&feudal));
+    feudal.aging_up = true;
+    assert!(second.is_reached(&feudal));
+    feudal.age = Reading::Known(Age::Feudal);
+    feudal.aging_up = false;
+    assert!(second.is_reached(&feudal));
+    feudal.population = Reading::Known(Population {
+        current: 9,
+        cap: 200,
+    });
+    assert!(!second.is_reached(&feudal));
+}
+
+#[test]
+fn test_villager_count_with_builders() {
+    let step = BuildOrderStep {
+        villager_count: 10,
+        resources: StepResources {
+            builder: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    // 7 villagers in the HUD and no others in the population
+    assert!(!step.is_reached(&state(6, 0, 1, 7)));
+    assert!(step.is_reached(&state(6, 2, 0, 10)));
+    assert!(step.is_reached(&state(7, 0, 1, 10)));
+    // 8 villagers in the HUD, only 1 of the population is not, e.g. 1 builder or the scout
+    assert!(!step.is_reached(&state(7, 0, 1, 9)));
+    // Without a population reading the builders of the step count
+    let mut unknown_population = state(7, 0, 1, 0);
+    unknown_population.population = Reading::Unknown;
+    assert!(step.is_reached(&unknown_population));
+
+    let without_builders = BuildOrderStep {
+        villager_count: 10,
+        ..Default::default()
+    };
+    assert!(!without_builders.is_reached(&state(7, 0, 1, 10)));
+    assert!(without_builders.is_reached(&state(9, 0, 1, 10)));
+}
+
+#[test]
+fn test_guide_advancement() {
+    let mut guide = BuildOrderGuide::new(Some(food_steps(&[3, 5, 6])), 3);
+    let progress = guide.update(&state(0, 0, 0, 3)).unwrap();
+    assert_eq!(progress.name, "Food");
+    assert_eq!((progress.step, progress.steps), (0, 3));
+    assert_eq!(progress.current.as_deref(), Some("3 on food"));
+    assert_eq!(progress.next.as_deref(), Some("5 on food"));
+
+    // A single misread doesn't advance
+    assert_eq!(guide.update(&state(5, 0, 0, 6)).unwrap().step, 0);
+    assert_eq!(guide.update(&state(0, 0, 0, 6)).unwrap().step, 0);
+    assert_eq!(guide.update(&state(5, 0, 0, 6)).unwrap().step, 0);
+    assert_eq!(guide.update(&state(5, 0, 0, 6)).unwrap().step, 0);
+    // One step per confirmation, even if later steps are reached as well
+    assert_eq!(guide.update(&state(5, 0, 0, 6)).unwrap().step, 1);
+    for _ in 0..3 {
+        guide.update(&state(5, 0, 0, 6));
+    }
+    let progress = guide.update(&state(5, 0, 0, 6)).unwrap();
+    assert_eq!(progress.step, 2);
+    assert_eq!(progress.current.as_deref(), Some("6 on food"));
+    assert_eq!(progress.next, None);
+
+    // Steps never go back
+    assert_eq!(guide.update(&state(0, 0, 0, 6)).unwrap().step, 2);
+    for _ in 0..3 {
+        guide.update(&state(6, 0, 0, 6));
+    }
+    let progress = guide.update(&state(6, 0, 0, 6)).unwrap();
+    assert_eq!((progress.step, progress.current), (3, None));
+
+    guide.reset();
+    assert_eq!(guide.update(&state(0, 0, 0, 3)).unwrap().step, 0);
+}
+
+#[test]
+fn test_guide_build_order_changes() {
+    let mut guide = BuildOrderGuide::new(None, 1);
+    assert_eq!(guide.update(&state(3, 0, 0, 3)), None);
+
+    guide.set_build_order(Some(food_steps(&[3, 5])));
+    assert_eq!(guide.update(&state(3, 0, 0, 3)).unwrap().step, 1);
+    // The same build order keeps the position, another one starts over
+    guide.set_build_order(Some(food_steps(&[3, 5])));
+    assert_eq!(guide.update(&state(0, 0, 0, 3)).unwrap().step, 1);
+    guide.set_build_order(Some(food_steps(&[2, 5])));
+    assert_eq!(guide.update(&state(0, 0, 0, 3)).unwrap().step, 0);
+
+    // Without confirmation every frame counts
+    guide.set_confirm_frames(0);
+    assert_eq!(guide.update(&state(2, 0, 0, 3)).unwrap().step, 1);
+}
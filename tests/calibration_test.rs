@@ -0,0 +1,176 @@
+// HUD calibration: fit of the anchor positions and the derived layout
+
+use anyhow::Result;
+use aoe4_overlay::{
+    calibration::{ANCHORS, Anchor, AnchorMatch, HudCalibrator},
+    hud_layout::HudLayout,
+    image_analyzer::ImageAnalyzerInner,
+};
+use opencv::{
+    core::{CV_8UC3, Mat, Rect, Scalar},
+    imgproc,
+    prelude::*,
+};
+
+/// Anchor matches at `origin + scale * anchor`
+fn matches(anchors: &[Anchor], scale: f32, origin: (f32, f32)) -> Vec<AnchorMatch> {
+    anchors
+        .iter()
+        .map(|anchor| AnchorMatch {
+            anchor: *anchor,
+            score: 1.0,
+            bbox: Rect::new(
+                (origin.0 + anchor.x * scale) as i32,
+                (origin.1 + anchor.y * scale) as i32,
+                10,
+                10,
+            ),
+            scale,
+        })
+        .collect()
+}
+
+fn anchor(x: f32, y: f32) -> Anchor {
+    Anchor {
+        name: "test",
+        template_path: "",
+        x,
+        y,
+    }
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-3,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn test_fit_known_anchors() {
+    for (scale, origin) in [
+        (1.0, (0.0, 954.0)),
+        (2.0, (20.0, 100.0)),
+        (3.0, (4.0, 10.0)),
+    ] {
+        let (fit_scale, fit_origin) =
+            HudCalibrator::fit(&matches(&ANCHORS, scale, origin)).unwrap();
+        assert_close(fit_scale, scale);
+        assert_close(fit_origin.0, origin.0);
+        assert_close(fit_origin.1, origin.1);
+    }
+
+    // Two distant anchors are enough
+    let (fit_scale, fit_origin) =
+        HudCalibrator::fit(&matches(&ANCHORS[..2], 2.0, (20.0, 100.0))).unwrap();
+    assert_close(fit_scale, 2.0);
+    assert_close(fit_origin.0, 20.0);
+    assert_close(fit_origin.1, 100.0);
+}
+
+#[test]
+fn test_fit_needs_spread_anchors() {
+    assert!(HudCalibrator::fit(&[]).is_none());
+    assert!(HudCalibrator::fit(&matches(&ANCHORS[..1], 1.0, (0.0, 954.0))).is_none());
+
+    // The variance of the anchor positions must be at least 100
+    let close = [anchor(0.0, 0.0), anchor(5.0, 5.0)];
+    assert!(HudCalibrator::fit(&matches(&close, 1.0, (0.0, 0.0))).is_none());
+    let close = [anchor(0.0, 0.0), anchor(14.0, 0.0)];
+    assert!(HudCalibrator::fit(&matches(&close, 1.0, (0.0, 0.0))).is_none());
+    let spread = [anchor(0.0, 0.0), anchor(15.0, 0.0)];
+    assert!(HudCalibrator::fit(&matches(&spread, 1.0, (0.0, 0.0))).is_some());
+}
+
+#[test]
+fn test_derive_layout_reproduces_default() {
+    let layout = HudCalibrator::derive_layout(2560, 1440, 1.0, (0.0, 954.0));
+    let default = HudLayout::default();
+    layout.validate().unwrap();
+
+    assert_eq!(
+        (layout.reference_width, layout.reference_height),
+        (2560, 1440)
+    );
+    assert_close(layout.ui_scale, 1.0);
+    assert_close(layout.reference_ui_scale, 1.0);
+    assert_close(layout.area_width, default.area_width);
+    assert_close(layout.area_height, default.area_height);
+    assert_close(layout.stat_width, default.stat_width);
+    assert_close(layout.stat_height, default.stat_height);
+    assert_eq!(layout.villager_icon_area, default.villager_icon_area);
+    assert_eq!(layout.clock_area, default.clock_area);
+    assert_eq!(layout.age_area, default.age_area);
+    for (stat, expected) in layout.stats.iter().zip(&default.stats) {
+        assert_eq!(stat.name, expected.name);
+        assert_close(stat.x, expected.x);
+        assert_close(stat.y, expected.y);
+    }
+    for (width, height) in [(2560, 1440), (1920, 1080), (3840, 2160)] {
+        assert_eq!(
+            layout.resolve(width, height),
+            default.resolve(width, height)
+        );
+    }
+
+    // The same HUD in a 1080p frame
+    let full_hd = HudCalibrator::derive_layout(1920, 1080, 0.75, (0.0, 715.5));
+    assert_close(full_hd.ui_scale, 1.0);
+    let (resolved, expected) = (full_hd.resolve(1920, 1080), default.resolve(1920, 1080));
+    assert_eq!(resolved.area, expected.area);
+    assert_eq!(resolved.normalized_size, expected.normalized_size);
+    assert_eq!(resolved.stat_regions, expected.stat_regions);
+    assert_eq!(resolved.villager_icon_area, expected.villager_icon_area);
+    assert_eq!(resolved.clock_area, expected.clock_area);
+}
+
+#[test]
+fn test_search_area_clipped_at_frame_edges() -> Result<()> {
+    // A patterned square at x 30..40
+    let mut img = Mat::new_rows_cols_with_default(50, 100, CV_8UC3, Scalar::all(0.0))?;
+    let white = Scalar::all(255.0);
+    imgproc::rectangle(
+        &mut img,
+        Rect::new(30, 10, 10, 10),
+        white,
+        -1,
+        imgproc::LINE_8,
+        0,
+    )?;
+    imgproc::rectangle(
+        &mut img,
+        Rect::new(32, 12, 4, 3),
+        Scalar::all(80.0),
+        -1,
+        imgproc::LINE_8,
+        0,
+    )?;
+    let template = Mat::roi(&img, Rect::new(30, 10, 10, 10))?.try_clone()?;
+
+    let (score, bbox) =
+        ImageAnalyzerInner::find_template(&img, &template, Rect::new(0, 0, 100, 50))?.unwrap();
+    assert!(score > 0.99);
+    assert_eq!(bbox, Rect::new(30, 10, 10, 10));
+
+    // -30..30 is cut to 0..30 instead of being shifted to 0..60
+    let (_, bbox) =
+        ImageAnalyzerInner::find_template(&img, &template, Rect::new(-30, -10, 60, 70))?.unwrap();
+    assert!(bbox.x + bbox.width <= 30, "{:?}", bbox);
+
+    // Past the right and bottom edges
+    let (score, bbox) =
+        ImageAnalyzerInner::find_template(&img, &template, Rect::new(25, 5, 200, 200))?.unwrap();
+    assert!(score > 0.99);
+    assert_eq!(bbox, Rect::new(30, 10, 10, 10));
+
+    // Too little of the area is left for the template
+    assert!(
+        ImageAnalyzerInner::find_template(&img, &template, Rect::new(-25, 0, 30, 50))?.is_none()
+    );
+    assert!(
+        ImageAnalyzerInner::find_template(&img, &template, Rect::new(95, 0, 30, 50))?.is_none()
+    );
+    Ok(())
+}
@@ -0,0 +1,36 @@
+// Digit template extraction from the annotated screenshots of `src_images/annotations.toml`
+
+use anyhow::Result;
+use aoe4_overlay::{
+    corpus::Corpus,
+    digit_extraction::{ExtractionConfig, extract_digit_templates},
+    ocr::template_matching_ocr::template_char,
+    preprocessing::PreprocessConfig,
+};
+use std::path::Path;
+
+const ANNOTATIONS: &str = "src_images/annotations.toml";
+
+#[test]
+fn test_extract_digit_templates() -> Result<()> {
+    let corpus = Corpus::load(Path::new(ANNOTATIONS))?;
+    let output = std::env::temp_dir().join(format!("aoe4_overlay_digits_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&output);
+    let config = ExtractionConfig::default();
+    let preprocessing = PreprocessConfig::default();
+
+    let report = extract_digit_templates(&corpus, &output, &config, &preprocessing)?;
+    assert!(!report.written.is_empty(), "no glyph was assigned to its digit");
+    for name in &report.written {
+        assert!(template_char(name).is_some(), "unexpected template name {}", name);
+        assert!(output.join(format!("{}.png", name)).is_file());
+    }
+
+    // Extracting again only finds duplicates of the written templates
+    let again = extract_digit_templates(&corpus, &output, &config, &preprocessing)?;
+    assert!(again.written.is_empty(), "written again: {:?}", again.written);
+    assert_eq!(again.skipped_regions, report.skipped_regions);
+
+    std::fs::remove_dir_all(&output)?;
+    Ok(())
+}
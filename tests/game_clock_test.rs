@@ -0,0 +1,114 @@
+// Parsing and filtering of the match timer readings
+
+use aoe4_overlay::game_clock::{GameClock, parse_clock};
+
+#[test]
+fn test_parse_clock() {
+    assert_eq!(parse_clock("12:34"), Some((12 * 60 + 34) * 1000));
+    assert_eq!(parse_clock(" 0:05 "), Some(5000));
+    assert_eq!(parse_clock("1:02:03"), Some((3600 + 2 * 60 + 3) * 1000));
+    // Without the colon the last two digits are seconds
+    assert_eq!(parse_clock("1234"), Some((12 * 60 + 34) * 1000));
+    assert_eq!(parse_clock("105"), Some(65_000));
+    assert_eq!(parse_clock("10203"), Some((3600 + 2 * 60 + 3) * 1000));
+}
+
+#[test]
+fn test_parse_clock_rejects_invalid() {
+    for text in [
+        "", "12:60", "1:60:00", "12:3", "12:345", "1:2:3:4", ":12", "12:", "12::34", "1a:34", "60",
+        "1234567", "12/34",
+    ] {
+        assert_eq!(parse_clock(text), None, "'{}' was accepted", text);
+    }
+}
+
+/// A clock that accepted `game_ms` at `timestamp_ms`
+fn started(game_ms: u64, timestamp_ms: u64) -> GameClock {
+    let mut clock = GameClock::default();
+    clock.update(Some(game_ms - 100), timestamp_ms - 100);
+    assert_eq!(clock.update(Some(game_ms), timestamp_ms), Some(game_ms));
+    clock
+}
+
+#[test]
+fn test_follows_wall_time() {
+    let mut clock = GameClock::default();
+    assert_eq!(clock.update(None, 0), None);
+    // The first reading is confirmed by the next one
+    assert_eq!(clock.update(Some(60_000), 1000), None);
+    assert_eq!(clock.update(Some(60_000), 1500), Some(60_000));
+    assert_eq!(clock.update(Some(61_000), 2000), Some(61_000));
+    // Paused: the timer may stand still
+    assert_eq!(clock.update(Some(61_000), 10_000), Some(61_000));
+    assert_eq!(clock.game_time_ms(), Some(61_000));
+}
+
+#[test]
+fn test_extrapolated_while_unreadable() {
+    let mut clock = started(61_000, 2000);
+    assert_eq!(clock.update(None, 2500), Some(61_500));
+    assert_eq!(clock.update(None, 7000), Some(66_000));
+    // At most 10 s, the game may be paused behind a menu
+    assert_eq!(clock.update(None, 12_000), Some(71_000));
+    assert_eq!(clock.update(None, 30_000), Some(71_000));
+    assert_eq!(clock.game_time_ms(), Some(61_000));
+
+    // The readings continue from the last accepted one
+    assert_eq!(clock.update(Some(64_000), 31_000), Some(64_000));
+    assert_eq!(clock.update(None, 32_000), Some(65_000));
+}
+
+#[test]
+fn test_first_reading_confirmed() {
+    // A misread first reading is replaced by the following consistent ones
+    let mut clock = GameClock::default();
+    assert_eq!(clock.update(Some(860_000), 1000), None);
+    assert_eq!(clock.update(Some(60_000), 1100), None);
+    assert_eq!(clock.update(Some(60_000), 1200), Some(60_000));
+    assert_eq!(clock.update(Some(860_000), 1300), Some(60_000));
+    assert_eq!(clock.update(Some(61_000), 2200), Some(61_000));
+
+    // Nothing is extrapolated before a reading was confirmed
+    let mut clock = GameClock::default();
+    clock.update(Some(60_000), 1000);
+    assert_eq!(clock.update(None, 2000), None);
+}
+
+#[test]
+fn test_misreads_rejected() {
+    let mut clock = started(60_000, 1000);
+
+    // Far ahead of the wall time and back in time
+    assert_eq!(clock.update(Some(860_000), 2000), Some(60_000));
+    assert_eq!(clock.update(Some(61_000), 2100), Some(61_000));
+    assert_eq!(clock.update(Some(10_000), 2200), Some(61_000));
+    assert_eq!(clock.update(Some(62_000), 3000), Some(62_000));
+
+    // Within the tolerance for the resolution of the timer
+    assert_eq!(clock.update(Some(64_000), 3100), Some(64_000));
+}
+
+#[test]
+fn test_seek_confirmed_by_next_reading() {
+    let mut clock = started(600_000, 1000);
+
+    // A replay seek back to 2:00, confirmed by the following frame
+    assert_eq!(clock.update(Some(120_000), 1100), Some(600_000));
+    assert_eq!(clock.update(Some(120_000), 1200), Some(120_000));
+    assert_eq!(clock.update(Some(121_000), 2200), Some(121_000));
+
+    // A seek forward, the next reading must follow the candidate, not the old time
+    assert_eq!(clock.update(Some(900_000), 2300), Some(121_000));
+    assert_eq!(clock.update(Some(900_500), 2800), Some(900_500));
+}
+
+#[test]
+fn test_reset() {
+    let mut clock = started(600_000, 1000);
+    clock.reset();
+    assert_eq!(clock.game_time_ms(), None);
+    assert_eq!(clock.update(None, 1100), None);
+    assert_eq!(clock.update(Some(5000), 1200), None);
+    assert_eq!(clock.update(Some(5000), 1300), Some(5000));
+}
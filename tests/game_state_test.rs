@@ -0,0 +1,124 @@
+// Parsing of the recognized HUD texts into the game state
+
+use aoe4_overlay::{
+    consts::AOE4_STATS_POS,
+    game_state::{GameState, Population, Reading},
+};
+
+/// Texts of all stat regions, the ones not given are empty
+fn texts(values: &[(&str, &str)]) -> [fixedstr::str8; AOE4_STATS_POS.len()] {
+    AOE4_STATS_POS.map(|stat| {
+        let text = values
+            .iter()
+            .find(|(name, _)| *name == stat.name)
+            .map_or("", |(_, text)| *text);
+        fixedstr::str8::from(text)
+    })
+}
+
+fn population(text: &str) -> Reading<Population> {
+    GameState::from_texts(&texts(&[("Pop", text)]), false).population
+}
+
+fn known(current: u32, cap: u32) -> Reading<Population> {
+    Reading::Known(Population { current, cap })
+}
+
+#[test]
+fn test_from_texts() {
+    let state = GameState::from_texts(
+        &texts(&[
+            ("Pop", "12/200"),
+            ("Food", "210"),
+            ("Wood", " 50 "),
+            ("Gold", "0"),
+            ("Stone", "1200"),
+            ("Idle", "2"),
+            ("Food Worker", "6"),
+            ("Wood Worker", "3"),
+            ("Gold Worker", "0"),
+            ("Stone Worker", "1"),
+        ]),
+        true,
+    );
+    assert_eq!(state.population, known(12, 200));
+    assert_eq!(state.resources.food, Reading::Known(210));
+    assert_eq!(state.resources.wood, Reading::Known(50));
+    assert_eq!(state.resources.gold, Reading::Known(0));
+    assert_eq!(state.resources.stone, Reading::Known(1200));
+    assert_eq!(state.idle_villagers, Reading::Known(2));
+    assert_eq!(state.workers.food, Reading::Known(6));
+    assert_eq!(state.workers.wood, Reading::Known(3));
+    assert_eq!(state.workers.gold, Reading::Known(0));
+    assert_eq!(state.workers.stone, Reading::Known(1));
+    assert!(state.villager_in_production);
+    // The age comes from the emblem, not from the texts
+    assert_eq!(state.age, Reading::Unknown);
+    assert!(!state.aging_up);
+}
+
+#[test]
+fn test_empty_or_garbage_texts() {
+    let state = GameState::from_texts(&texts(&[]), false);
+    assert_eq!(state, GameState::default());
+
+    let state = GameState::from_texts(
+        &texts(&[
+            ("Food", "2l0"),
+            ("Wood", "-5"),
+            ("Gold", "1,000"),
+            ("Stone", "  "),
+            ("Idle", "?"),
+        ]),
+        false,
+    );
+    assert_eq!(state.resources.food, Reading::Unknown);
+    assert_eq!(state.resources.wood, Reading::Unknown);
+    assert_eq!(state.resources.gold, Reading::Unknown);
+    assert_eq!(state.resources.stone, Reading::Unknown);
+    assert_eq!(state.idle_villagers, Reading::Unknown);
+}
+
+#[test]
+fn test_parse_population() {
+    assert_eq!(population("12/200"), known(12, 200));
+    assert_eq!(population(" 5/10 "), known(5, 10));
+    assert_eq!(population("0/10"), known(0, 10));
+
+    for garbage in [
+        "", "12", "12/", "/200", "12/0", "a/200", "12/2OO", "12//200", "12-200",
+    ] {
+        assert_eq!(population(garbage), Reading::Unknown, "'{}'", garbage);
+    }
+}
+
+#[test]
+fn test_population_over_the_cap() {
+    // Destroyed houses lower the cap below the population, the reading is kept
+    assert_eq!(population("25/20"), known(25, 20));
+    assert_eq!(population("201/200"), known(201, 200));
+}
+
+#[test]
+fn test_villagers() {
+    let state = GameState::from_texts(
+        &texts(&[
+            ("Idle", "2"),
+            ("Food Worker", "6"),
+            ("Wood Worker", "3"),
+            ("Gold Worker", "0"),
+            ("Stone Worker", "1"),
+        ]),
+        false,
+    );
+    assert_eq!(state.villagers(), Reading::Known(12));
+
+    // Every part must be readable
+    let mut unknown_idle = state;
+    unknown_idle.idle_villagers = Reading::Unknown;
+    assert_eq!(unknown_idle.villagers(), Reading::Unknown);
+    let mut unknown_workers = state;
+    unknown_workers.workers.stone = Reading::Unknown;
+    assert_eq!(unknown_workers.villagers(), Reading::Unknown);
+    assert_eq!(GameState::default().villagers(), Reading::Unknown);
+}
@@ -0,0 +1,128 @@
+// Fusion of the game state over consecutive frames
+
+use aoe4_overlay::{
+    game_state::{GameState, PerResource, Population, Reading},
+    game_state_tracker::{GameStateTracker, TrackerConfig},
+};
+
+fn state(population: Option<(u32, u32)>, food: Option<u32>) -> GameState {
+    GameState {
+        population: population
+            .map(|(current, cap)| Population { current, cap })
+            .into(),
+        resources: PerResource {
+            food: food.into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn current_population(tracker: &GameStateTracker) -> Option<u32> {
+    tracker
+        .current()
+        .state
+        .population
+        .known()
+        .map(|p| p.current)
+}
+
+#[test]
+fn test_hold_until_expiry() {
+    let config = TrackerConfig::default();
+    let mut tracker = GameStateTracker::new(config.clone());
+    tracker.update(&state(Some((9, 10)), Some(200)), 1000);
+
+    // Held through a short dropout with decaying confidence
+    let held = tracker.update(&state(Some((9, 10)), None), 1000 + config.hold_ms);
+    assert_eq!(held.state.resources.food, Reading::Known(200));
+    assert!(held.confidence.resources.food < 0.5);
+
+    let expired = tracker.update(&state(Some((9, 10)), None), 1001 + config.hold_ms);
+    assert_eq!(expired.state.resources.food, Reading::Unknown);
+    assert_eq!(expired.confidence.resources.food, 0.0);
+}
+
+#[test]
+fn test_confirmation_raises_confidence() {
+    let mut tracker = GameStateTracker::new(TrackerConfig::default());
+    let first = tracker.update(&state(Some((9, 10)), None), 1000);
+    assert_eq!(first.confidence.population, 0.5);
+    let second = tracker.update(&state(Some((9, 10)), None), 1250);
+    assert!(second.confidence.population > first.confidence.population);
+}
+
+#[test]
+fn test_implausible_jump_rejected() {
+    let mut tracker = GameStateTracker::new(TrackerConfig::default());
+    tracker.update(&state(Some((9, 10)), Some(200)), 1000);
+
+    // Population 9 to 49 within a quarter second, food +5000
+    let tracked = tracker.update(&state(Some((49, 10)), Some(5200)), 1250);
+    assert_eq!(current_population(&tracker), Some(9));
+    assert_eq!(tracked.state.resources.food, Reading::Known(200));
+
+    // A plausible change is accepted at once
+    tracker.update(&state(Some((10, 10)), Some(250)), 1500);
+    assert_eq!(current_population(&tracker), Some(10));
+
+    // Above the maximum population the reading is ignored
+    tracker.update(&state(Some((400, 400)), None), 1750);
+    assert_eq!(current_population(&tracker), Some(10));
+}
+
+#[test]
+fn test_reacquire_after_consecutive_frames() {
+    let config = TrackerConfig {
+        reacquire_frames: 3,
+        ..Default::default()
+    };
+    let mut tracker = GameStateTracker::new(config);
+    tracker.update(&state(Some((10, 10)), None), 1000);
+
+    for timestamp_ms in [1250, 1500] {
+        tracker.update(&state(Some((60, 70)), None), timestamp_ms);
+        assert_eq!(current_population(&tracker), Some(10));
+    }
+    tracker.update(&state(Some((60, 70)), None), 1750);
+    assert_eq!(current_population(&tracker), Some(60));
+}
+
+#[test]
+fn test_interrupted_candidate_starts_over() {
+    let mut tracker = GameStateTracker::new(TrackerConfig::default());
+    tracker.update(&state(Some((10, 10)), None), 1000);
+
+    tracker.update(&state(Some((60, 70)), None), 1250);
+    tracker.update(&state(Some((60, 70)), None), 1500);
+    // A different implausible value or a dropout resets the count
+    tracker.update(&state(Some((80, 90)), None), 1750);
+    tracker.update(&state(Some((60, 70)), None), 2000);
+    tracker.update(&state(None, None), 2250);
+    tracker.update(&state(Some((60, 70)), None), 2500);
+    tracker.update(&state(Some((60, 70)), None), 2750);
+    assert_eq!(current_population(&tracker), Some(10));
+}
+
+#[test]
+fn test_time_going_backwards_resets() {
+    let mut tracker = GameStateTracker::new(TrackerConfig::default());
+    tracker.update(&state(Some((10, 10)), None), 5000);
+    tracker.update(&state(Some((10, 10)), None), 5250);
+
+    // A replay restarted: the implausible value is taken as first reading
+    let tracked = tracker.update(&state(Some((60, 70)), None), 100);
+    assert_eq!(current_population(&tracker), Some(60));
+    assert_eq!(tracked.confidence.population, 0.5);
+}
+
+#[test]
+fn test_workers_above_population_rejected() {
+    let mut tracker = GameStateTracker::new(TrackerConfig::default());
+    let mut frame = state(Some((9, 10)), None);
+    frame.workers.food = Reading::Known(40);
+    frame.idle_villagers = Reading::Known(3);
+    let tracked = tracker.update(&frame, 1000);
+    assert_eq!(tracked.state.workers.food, Reading::Unknown);
+    assert_eq!(tracked.state.idle_villagers, Reading::Known(3));
+}
@@ -0,0 +1,177 @@
+// Resolution of the HUD layout profiles for different frame sizes and UI scales
+
+use aoe4_overlay::{
+    consts::AOE4_STATS_POS,
+    hud_layout::{HudLayout, LayoutRect},
+};
+use image::math::Rect;
+
+fn rect(x: u32, y: u32, width: u32, height: u32) -> Rect {
+    Rect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+fn layout_rect(x: f32, y: f32, width: f32, height: f32) -> LayoutRect {
+    LayoutRect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+#[test]
+fn test_resolve_reference_size() {
+    let resolved = HudLayout::default().resolve(2560, 1440);
+    assert_eq!(resolved.area, rect(0, 954, 267, 486));
+    assert_eq!(resolved.scale, 1.0);
+    assert_eq!(resolved.normalized_size, (267, 486));
+    assert!(!resolved.needs_resize());
+    assert_eq!(resolved.stat_regions[0], rect(50, 190, 80, 34));
+    for (region, stat) in resolved.stat_regions.iter().zip(AOE4_STATS_POS) {
+        assert_eq!(region.x, stat.x as u32, "{}", stat.name);
+    }
+    assert_eq!(resolved.villager_icon_area, rect(0, 0, 250, 80));
+    assert_eq!(resolved.clock_area, rect(1220, 4, 120, 34));
+    assert_eq!(resolved.age_area, rect(1180, 0, 200, 120));
+}
+
+#[test]
+fn test_resolve_scales_with_frame_height() {
+    let layout = HudLayout::default();
+    let reference = layout.resolve(2560, 1440);
+
+    let full_hd = layout.resolve(1920, 1080);
+    assert_eq!(full_hd.area, rect(0, 715, 200, 365));
+    assert_eq!(full_hd.scale, 0.75);
+    assert!(full_hd.needs_resize());
+    assert_eq!(full_hd.clock_area, rect(915, 3, 90, 26));
+    assert_eq!(full_hd.age_area, rect(885, 0, 150, 90));
+
+    let uhd = layout.resolve(3840, 2160);
+    assert_eq!(uhd.area, rect(0, 1431, 401, 729));
+    assert_eq!(uhd.scale, 1.5);
+    assert_eq!(uhd.clock_area, rect(1830, 6, 180, 51));
+
+    // Ultrawide: the HUD panel stays in the bottom-left corner, the timer in the center
+    let ultrawide = layout.resolve(3440, 1440);
+    assert_eq!(ultrawide.area, reference.area);
+    assert_eq!(ultrawide.clock_area, rect(1660, 4, 120, 34));
+
+    // The regions in the normalized HUD panel don't depend on the frame size
+    for resolved in [&full_hd, &uhd, &ultrawide] {
+        assert_eq!(resolved.normalized_size, reference.normalized_size);
+        assert_eq!(resolved.stat_regions, reference.stat_regions);
+        assert_eq!(resolved.villager_icon_area, reference.villager_icon_area);
+    }
+}
+
+#[test]
+fn test_resolve_ui_scale() {
+    let layout = HudLayout {
+        ui_scale: 0.8,
+        ..Default::default()
+    };
+    let resolved = layout.resolve(2560, 1440);
+    assert_eq!(resolved.area, rect(0, 1051, 214, 389));
+    assert!((resolved.scale - 0.8).abs() < 1e-6);
+    assert_eq!(resolved.normalized_size, (267, 486));
+
+    // A profile measured at another UI scale
+    let measured_small = HudLayout {
+        reference_ui_scale: 0.8,
+        ui_scale: 0.8,
+        area_width: 214.0,
+        area_height: 389.0,
+        ..Default::default()
+    };
+    assert_eq!(
+        measured_small.resolve(2560, 1440).area,
+        rect(0, 1051, 214, 389)
+    );
+
+    // The HUD panel never exceeds the frame
+    let tiny = HudLayout::default().resolve(100, 100);
+    assert_eq!((tiny.area.width, tiny.area.height), (19, 34));
+    let huge = HudLayout {
+        ui_scale: 10.0,
+        ..Default::default()
+    }
+    .resolve(1000, 1440);
+    assert_eq!(huge.area, rect(0, 0, 1000, 1440));
+}
+
+#[test]
+fn test_for_frame_size() {
+    let profiles = HudLayout::builtin_profiles();
+    assert!(!profiles.is_empty());
+    for profile in &profiles {
+        profile.validate().unwrap();
+        assert_eq!(
+            profile.name,
+            format!("{}x{}", profile.reference_width, profile.reference_height)
+        );
+    }
+
+    // The measured profile matches the original hard-coded values
+    let default = HudLayout::default();
+    for (width, height) in [
+        (2560, 1440),
+        (1920, 1080),
+        (3840, 2160),
+        (3440, 1440),
+        (1280, 800),
+    ] {
+        let layout = HudLayout::for_frame_size(width, height);
+        assert_eq!(layout.name, "2560x1440");
+        assert_eq!(
+            layout.resolve(width, height),
+            default.resolve(width, height)
+        );
+    }
+}
+
+#[test]
+fn test_top_centered() {
+    let clock = layout_rect(-60.0, 4.0, 120.0, 34.0);
+    assert_eq!(clock.top_centered(1.0, 2560, 1440), rect(1220, 4, 120, 34));
+    assert_eq!(clock.top_centered(0.75, 1920, 1080), rect(915, 3, 90, 26));
+    assert_eq!(clock.top_centered(1.5, 3840, 2160), rect(1830, 6, 180, 51));
+}
+
+#[test]
+fn test_top_centered_clipping() {
+    // Past the left edge: -50..70 is shrunk to 0..70
+    assert_eq!(
+        layout_rect(-100.0, 0.0, 120.0, 20.0).top_centered(1.0, 100, 50),
+        rect(0, 0, 70, 20)
+    );
+    // Past the right and bottom edges
+    assert_eq!(
+        layout_rect(20.0, 40.0, 100.0, 20.0).top_centered(1.0, 100, 50),
+        rect(70, 40, 30, 10)
+    );
+    // Past the top edge
+    assert_eq!(
+        layout_rect(-10.0, -10.0, 20.0, 20.0).top_centered(1.0, 100, 50),
+        rect(40, 0, 20, 10)
+    );
+    // Wider than the frame
+    assert_eq!(
+        layout_rect(-100.0, 0.0, 200.0, 80.0).top_centered(1.0, 100, 50),
+        rect(0, 0, 100, 50)
+    );
+    // Outside of the frame, one pixel at the edge
+    assert_eq!(
+        layout_rect(200.0, 0.0, 10.0, 10.0).top_centered(1.0, 100, 50),
+        rect(99, 0, 1, 10)
+    );
+    assert_eq!(
+        layout_rect(-100.0, 60.0, 10.0, 10.0).top_centered(1.0, 100, 50),
+        rect(0, 49, 1, 1)
+    );
+}
@@ -0,0 +1,48 @@
+// Language selection from the locale environment variables
+
+use aoe4_overlay::i18n::locale_languages;
+
+fn languages(variables: &[(&str, &str)]) -> Vec<String> {
+    locale_languages(|name| {
+        variables
+            .iter()
+            .find(|(variable, _)| *variable == name)
+            .map(|(_, value)| value.to_string())
+    })
+}
+
+#[test]
+fn test_language_list_comes_first() {
+    assert_eq!(
+        languages(&[("LANG", "en_US.UTF-8"), ("LANGUAGE", "de_DE:fr")]),
+        ["de", "fr", "en"]
+    );
+    assert_eq!(
+        languages(&[("LC_MESSAGES", "de_AT.UTF-8"), ("LANGUAGE", "")]),
+        ["de"]
+    );
+}
+
+#[test]
+fn test_lc_all_overrides_lang() {
+    assert_eq!(
+        languages(&[("LC_ALL", "de_DE.UTF-8"), ("LANG", "en_US.UTF-8")]),
+        ["de"]
+    );
+    assert_eq!(
+        languages(&[("LC_ALL", ""), ("LC_MESSAGES", "en_GB"), ("LANG", "de_DE")]),
+        ["en"]
+    );
+}
+
+#[test]
+fn test_c_locale_ignores_language_list() {
+    for locale in ["C", "POSIX", "C.UTF-8"] {
+        assert!(
+            languages(&[("LC_ALL", locale), ("LANG", "de_DE"), ("LANGUAGE", "de")]).is_empty(),
+            "{}",
+            locale
+        );
+    }
+    assert!(languages(&[("LANGUAGE", "de")]).is_empty());
+}
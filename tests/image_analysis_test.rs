@@ -1,189 +1,366 @@
+// Accuracy of all OCR engines on the annotated screenshots of `src_images/annotations.toml`.
+//
+// The test fails if an engine falls below its recorded results in `tests/ocr_baseline.toml`.
+// Engines without recorded results or that cannot be loaded (no models on this machine) are
+// reported and skipped. Record the current results as new baseline with:
+//   UPDATE_OCR_BASELINE=1 cargo test --test image_analysis_test -- --nocapture
+// Recording refuses engines that cannot be loaded, unless they are opted out with a comma
+// separated list, e.g. `OCR_SKIP_ENGINES=pp,onnx`.
+//
+// The age emblem detection is checked on the samples with an annotated age, and on a Feudal Age
+// emblem made from a Dark Age one.
+
 use anyhow::Result;
 use aoe4_overlay::{
-    consts::*,
-    image_analyzer::{DetectedIcon, DetectedText, ImageAnalyzerInner, OCRModel},
+    consts::AOE4_STATS_POS,
+    corpus::Corpus,
+    game_state::Age,
+    hud_layout::HudLayout,
+    image_analyzer::{AnalyzerConfig, ImageAnalyzerInner, OCRModel},
 };
 use opencv::{
-    core::{Mat, Point, Rect, Scalar, Vector},
-    imgcodecs::{self, IMREAD_COLOR},
-    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_8},
+    core::{Mat, Rect, Vector},
+    imgcodecs,
     prelude::*,
 };
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+const ANNOTATIONS: &str = "src_images/annotations.toml";
+const AGE_DIR: &str = "src_images/ages";
+const BASELINE: &str = "tests/ocr_baseline.toml";
+const BASELINE_HEADER: &str = "\
+# Minimum accuracy per OCR engine on src_images/annotations.toml, checked by
+# tests/image_analysis_test.rs. Record with:
+#   UPDATE_OCR_BASELINE=1 cargo test --test image_analysis_test -- --nocapture
+";
 
-/// Load the test image
-fn load_test_image() -> Result<Mat> {
-    let image_path = "src_images/menu_cut1.jpg";
-    let img = imgcodecs::imread(image_path, IMREAD_COLOR)?;
+const ENGINES: [OCRModel; 4] = [
+    OCRModel::TemplateMatching,
+    OCRModel::ONNX,
+    OCRModel::OnnxPar,
+    OCRModel::PP,
+];
 
-    if img.empty() {
-        anyhow::bail!("Failed to load image from {}", image_path);
+fn engine_name(model: OCRModel) -> &'static str {
+    match model {
+        OCRModel::PP => "pp",
+        OCRModel::ONNX => "onnx",
+        OCRModel::OnnxPar => "onnx_par",
+        OCRModel::TemplateMatching => "template_matching",
     }
+}
 
-    println!("Loaded image: {}x{}", img.cols(), img.rows());
-    Ok(img)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct EngineScore {
+    /// Share of correctly read values over all annotated fields
+    field_accuracy: f64,
+    /// Share of correctly read values per stat name
+    per_field: BTreeMap<String, f64>,
+    /// `None` if no sample has (or is detected to have) the villager icon
+    villager_precision: Option<f64>,
+    villager_recall: Option<f64>,
 }
 
-/// Draw detection results on the image
-fn visualize_results(img: &Mat, texts: &[DetectedText], icons: &[DetectedIcon]) -> Result<Mat> {
-    let mut output = img.clone();
-
-    // Draw the search area for villager icon in yellow
-    let img_height = img.rows() as f32;
-    let search_x = VILLAGER_ICON_AREA.x as i32;
-    let search_y = (img_height + AREA_Y_OFFSET) as i32 + VILLAGER_ICON_AREA.y as i32;
-    let search_rect = Rect::new(
-        search_x,
-        search_y,
-        VILLAGER_ICON_AREA.width as i32,
-        VILLAGER_ICON_AREA.height as i32,
-    );
-
-    imgproc::rectangle(
-        &mut output,
-        search_rect,
-        Scalar::new(0.0, 255.0, 255.0, 0.0), // Yellow
-        1,
-        LINE_8,
-        0,
-    )?;
-
-    imgproc::put_text(
-        &mut output,
-        "Search Area",
-        Point::new(search_x, search_y - 5),
-        FONT_HERSHEY_SIMPLEX,
-        0.5,
-        Scalar::new(0.0, 255.0, 255.0, 0.0),
-        1,
-        LINE_8,
-        false,
-    )?;
-
-    // Draw detected icons in blue
-    for icon in icons {
-        imgproc::rectangle(
-            &mut output,
-            icon.bbox,
-            Scalar::new(255.0, 0.0, 0.0, 0.0), // Blue
-            2,
-            LINE_8,
-            0,
-        )?;
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Baseline {
+    #[serde(default)]
+    engines: BTreeMap<String, EngineScore>,
+}
 
-        let label = format!("{}: {:.2}", icon.name, icon.confidence);
-        imgproc::put_text(
-            &mut output,
-            &label,
-            Point::new(icon.bbox.x, icon.bbox.y - 5),
-            FONT_HERSHEY_SIMPLEX,
-            0.6,
-            Scalar::new(255.0, 0.0, 0.0, 0.0),
-            2,
-            LINE_8,
-            false,
-        )?;
+struct EngineReport {
+    score: EngineScore,
+    mean_time: Duration,
+    misreads: Vec<String>,
+}
+
+fn ratio(numerator: usize, denominator: usize) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+fn evaluate(model: OCRModel, corpus: &Corpus) -> Result<EngineReport> {
+    let mut analyzer = ImageAnalyzerInner::new(model)?;
+    let mut fields: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    let (mut true_positives, mut false_positives, mut false_negatives) = (0, 0, 0);
+    let mut total_time = Duration::ZERO;
+    let mut misreads = Vec::new();
+
+    for sample in &corpus.samples {
+        let (hud, layout) = corpus.load_hud(sample)?;
+        let start = std::time::Instant::now();
+        let result = analyzer.analyze(hud, &layout)?;
+        total_time += start.elapsed();
+
+        for (name, expected) in &sample.values {
+            let index = Corpus::stat_index(name).unwrap();
+            let actual = result.detected_texts[index].as_str().trim();
+            let (correct, total) = fields.entry(name.clone()).or_default();
+            *total += 1;
+            if actual == expected {
+                *correct += 1;
+            } else {
+                misreads.push(format!(
+                    "{} {}: expected '{}', read '{}'",
+                    sample.file.display(),
+                    name,
+                    expected,
+                    actual
+                ));
+            }
+        }
+
+        match (sample.villager_icon, result.has_villager_icon) {
+            (Some(true), true) => true_positives += 1,
+            (Some(false), true) => false_positives += 1,
+            (Some(true), false) => false_negatives += 1,
+            _ => {}
+        }
     }
 
-    // Draw text bounding boxes in green
-    for detected_text in texts {
-        imgproc::rectangle(
-            &mut output,
-            detected_text.bbox,
-            Scalar::new(0.0, 255.0, 0.0, 0.0),
-            1,
-            LINE_8,
-            0,
-        )?;
+    let correct: usize = fields.values().map(|(correct, _)| correct).sum();
+    let total: usize = fields.values().map(|(_, total)| total).sum();
+    Ok(EngineReport {
+        score: EngineScore {
+            field_accuracy: ratio(correct, total).unwrap_or(1.0),
+            per_field: fields
+                .into_iter()
+                .map(|(name, (correct, total))| (name, ratio(correct, total).unwrap_or(1.0)))
+                .collect(),
+            villager_precision: ratio(true_positives, true_positives + false_positives),
+            villager_recall: ratio(true_positives, true_positives + false_negatives),
+        },
+        mean_time: total_time / corpus.samples.len().max(1) as u32,
+        misreads,
+    })
+}
 
-        // Add text label
-        imgproc::put_text(
-            &mut output,
-            &detected_text.text,
-            Point::new(detected_text.bbox.x, detected_text.bbox.y - 3),
-            FONT_HERSHEY_SIMPLEX,
-            0.4,
-            Scalar::new(0.0, 255.0, 0.0, 0.0),
-            1,
-            LINE_8,
-            false,
-        )?;
+/// Describe every metric of `score` that is below the baseline
+fn regressions(engine: &str, score: &EngineScore, baseline: &EngineScore) -> Vec<String> {
+    const EPSILON: f64 = 1e-9;
+    let mut regressions = Vec::new();
+    let mut check = |metric: &str, current: Option<f64>, expected: Option<f64>| {
+        if let Some(expected) = expected {
+            let current = current.unwrap_or(0.0);
+            if current + EPSILON < expected {
+                regressions.push(format!(
+                    "{}: {} dropped from {:.3} to {:.3}",
+                    engine, metric, expected, current
+                ));
+            }
+        }
+    };
+
+    check("field accuracy", Some(score.field_accuracy), Some(baseline.field_accuracy));
+    for (name, expected) in &baseline.per_field {
+        check(name, score.per_field.get(name).copied(), Some(*expected));
     }
+    check("villager icon precision", score.villager_precision, baseline.villager_precision);
+    check("villager icon recall", score.villager_recall, baseline.villager_recall);
+    regressions
+}
 
-    Ok(output)
+fn format_metric(value: Option<f64>) -> String {
+    value.map_or_else(|| "n/a".to_string(), |value| format!("{:.1}%", value * 100.0))
 }
 
 #[test]
-fn test_image_analysis() -> Result<()> {
-    println!("\n========================================");
-    println!("Starting Image Analysis Test");
-    println!("========================================\n");
-
-    // Load the test image
-    let img = load_test_image()?;
-
-    // Create image analyzer
-    println!("\n--- Initializing Image Analyzer ---");
-    let mut analyzer = ImageAnalyzerInner::new()?;
-    println!("Image analyzer initialized successfully");
-
-    // Analyze the image
-    println!("\n--- Analyzing Image (OCR + Template Matching) ---");
-    for model in [OCRModel::ONNX, OCRModel::OnnxPar, OCRModel::PP] {
-        println!("Using OCR Model: {:?}", &model);
-        let start_timestamp = std::time::Instant::now();
-        let _ = analyzer.analyze(img.clone(), model)?;
-        let duration_for_all = std::time::Instant::now() - start_timestamp;
-        println!("Full time: {} ms", duration_for_all.as_millis(),);
-    }
+fn test_ocr_accuracy() -> Result<()> {
+    let corpus = Corpus::load(Path::new(ANNOTATIONS))?;
+    assert!(!corpus.samples.is_empty(), "{} has no samples", ANNOTATIONS);
 
-    let analysis_result = analyzer.analyze(img.clone(), OCRModel::ONNX)?;
-
-    println!(
-        "Total texts detected: {}",
-        analysis_result.detected_texts.len()
-    );
-    println!(
-        "Total icons detected: {}",
-        analysis_result.detected_icons.len()
-    );
-
-    // Visualize results
-    println!("\n--- Creating Visualization ---");
-    let output = visualize_results(
-        &img,
-        &analysis_result.detected_texts,
-        &analysis_result.detected_icons,
-    )?;
-
-    // Save the output image
-    let output_path = "target/image_analysis_result.jpg";
-    imgcodecs::imwrite(output_path, &output, &Vector::default())?;
-    println!("Saved visualization to: {}", output_path);
-
-    // Print summary
-    println!("\n========================================");
-    println!("Analysis Summary");
-    println!("========================================");
-    println!("Image size: {}x{}", img.cols(), img.rows());
-    println!("Detected texts: {}", analysis_result.detected_texts.len());
-    println!("Detected icons: {}", analysis_result.detected_icons.len());
-    println!("\nDetected text items:");
-    for text in analysis_result.detected_texts.iter() {
-        println!("  {}. '{}'", text.stat_name, text.text);
-    }
-    println!("\nDetected icon locations:");
-    for (i, icon) in analysis_result.detected_icons.iter().enumerate() {
+    let baseline: Baseline = match std::fs::read_to_string(BASELINE) {
+        Ok(content) => toml::from_str(&content)?,
+        Err(_) => Baseline::default(),
+    };
+    let update_baseline = std::env::var_os("UPDATE_OCR_BASELINE").is_some();
+    let skipped = std::env::var("OCR_SKIP_ENGINES").unwrap_or_default();
+    let skipped: Vec<&str> = skipped.split(',').map(str::trim).collect();
+
+    let mut new_baseline = Baseline::default();
+    let mut failures = Vec::new();
+    let mut unavailable = Vec::new();
+
+    for model in ENGINES {
+        let name = engine_name(model);
+        println!("\n--- {} ---", name);
+        if skipped.contains(&name) {
+            println!("Skipped by OCR_SKIP_ENGINES");
+            if let Some(score) = baseline.engines.get(name) {
+                new_baseline.engines.insert(name.to_string(), score.clone());
+            }
+            continue;
+        }
+        let report = match evaluate(model, &corpus) {
+            Ok(report) => report,
+            Err(e) => {
+                // Models or runtimes that are not installed don't fail the check
+                println!("Skipped, engine unavailable: {:#}", e);
+                unavailable.push(format!("{}: engine failed: {:#}", name, e));
+                continue;
+            }
+        };
+
+        println!("Field accuracy: {}", format_metric(Some(report.score.field_accuracy)));
+        for stat in AOE4_STATS_POS {
+            if let Some(accuracy) = report.score.per_field.get(stat.name) {
+                println!("  {:<13} {}", stat.name, format_metric(Some(*accuracy)));
+            }
+        }
         println!(
-            "  {}. {} at ({}, {}) confidence: {:.2}",
-            i + 1,
-            icon.name,
-            icon.bbox.x,
-            icon.bbox.y,
-            icon.confidence
+            "Villager icon precision/recall: {}/{}",
+            format_metric(report.score.villager_precision),
+            format_metric(report.score.villager_recall)
         );
+        println!("Mean analysis time: {} ms", report.mean_time.as_millis());
+        for misread in &report.misreads {
+            println!("  {}", misread);
+        }
+
+        match baseline.engines.get(name) {
+            Some(expected) => failures.extend(regressions(name, &report.score, expected)),
+            None => println!("Not checked, no baseline recorded, run with UPDATE_OCR_BASELINE=1"),
+        }
+        new_baseline.engines.insert(name.to_string(), report.score);
+    }
+
+    if update_baseline {
+        assert!(
+            unavailable.is_empty(),
+            "Not recording a baseline without these engines, opt them out with OCR_SKIP_ENGINES:\n{}",
+            unavailable.join("\n")
+        );
+        std::fs::write(
+            BASELINE,
+            format!("{}\n{}", BASELINE_HEADER, toml::to_string_pretty(&new_baseline)?),
+        )?;
+        println!("\nBaseline written to {}", BASELINE);
+        return Ok(());
+    }
+
+    assert!(failures.is_empty(), "OCR accuracy check failed:\n{}", failures.join("\n"));
+    Ok(())
+}
+
+#[test]
+fn test_age_detection() -> Result<()> {
+    let corpus = Corpus::load(Path::new(ANNOTATIONS))?;
+    let mut analyzer = ImageAnalyzerInner::new(OCRModel::TemplateMatching)?;
+    let mut checked = 0;
+    let mut failures = Vec::new();
+
+    for sample in corpus.samples.iter().filter(|sample| sample.age.is_some()) {
+        let (frame, layout) = corpus.load_frame(sample)?;
+        let hud = ImageAnalyzerInner::extract_hud_area(&frame, &layout)?;
+        let mut result = analyzer.analyze(hud, &layout)?;
+        analyzer.analyze_frame(&frame, &layout, &mut result)?;
+        checked += 1;
+
+        // A partial set of emblems is not used
+        let expected = sample.age.filter(|_| all_age_templates(Path::new(AGE_DIR)));
+        let detected = result.game_state.age.known();
+        if detected != expected {
+            failures.push(format!(
+                "{}: expected {:?}, detected {:?}",
+                sample.file.display(),
+                expected,
+                detected
+            ));
+        }
+    }
+
+    assert!(checked > 0, "{} has no sample with an age", ANNOTATIONS);
+    assert!(failures.is_empty(), "Age detection failed:\n{}", failures.join("\n"));
+    Ok(())
+}
+
+fn all_age_templates(dir: &Path) -> bool {
+    Age::ALL
+        .iter()
+        .all(|age| dir.join(format!("{}.png", age.name())).is_file())
+}
+
+/// Age emblem of villagers_1.jpg (2560x1440): the numeral "I" between two dashes
+const EMBLEM: Rect = Rect {
+    x: 1245,
+    y: 44,
+    width: 75,
+    height: 42,
+};
+const NUMERAL: Rect = Rect {
+    x: 1273,
+    y: 44,
+    width: 18,
+    height: 42,
+};
+
+fn copy_area(frame: &mut Mat, from: Rect, to_x: i32) -> Result<()> {
+    let area = Mat::roi(frame, from)?.try_clone()?;
+    let mut target = Mat::roi_mut(frame, Rect::new(to_x, from.y, from.width, from.height))?;
+    area.copy_to(&mut target)?;
+    Ok(())
+}
+
+fn write_png(path: &Path, image: &Mat) -> Result<()> {
+    if !imgcodecs::imwrite(&path.to_string_lossy(), image, &Vector::new())? {
+        anyhow::bail!("Failed to write {}", path.display());
     }
+    Ok(())
+}
+
+fn detect_age(analyzer: &mut ImageAnalyzerInner, frame: &Mat) -> Result<Option<Age>> {
+    let layout = HudLayout::for_frame_size(frame.cols() as u32, frame.rows() as u32)
+        .resolve(frame.cols() as u32, frame.rows() as u32);
+    let hud = ImageAnalyzerInner::extract_hud_area(frame, &layout)?;
+    let mut result = analyzer.analyze(hud, &layout)?;
+    analyzer.analyze_frame(frame, &layout, &mut result)?;
+    Ok(result.game_state.age.known())
+}
 
-    println!("\n========================================\n");
+#[test]
+fn test_age_detection_later_age() -> Result<()> {
+    let corpus = Corpus::load(Path::new(ANNOTATIONS))?;
+    let sample = corpus
+        .samples
+        .iter()
+        .find(|sample| sample.file == Path::new("villagers_1.jpg"))
+        .expect("villagers_1.jpg is annotated");
+    let (dark_frame, _) = corpus.load_frame(sample)?;
+
+    // "II": the background left of the numeral over the numeral, then the numeral twice
+    let mut feudal_frame = dark_frame.try_clone()?;
+    let background = Rect::new(1253, NUMERAL.y, NUMERAL.width, NUMERAL.height);
+    copy_area(&mut feudal_frame, background, NUMERAL.x)?;
+    copy_area(&mut feudal_frame, NUMERAL, NUMERAL.x - 9)?;
+    copy_area(&mut feudal_frame, NUMERAL, NUMERAL.x + 10)?;
+
+    let dir = std::env::temp_dir().join(format!("aoe4_overlay_ages_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::copy(Path::new(AGE_DIR).join("dark.png"), dir.join("dark.png"))?;
+    write_png(&dir.join("feudal.png"), &Mat::roi(&feudal_frame, EMBLEM)?.try_clone()?)?;
+    let config = AnalyzerConfig {
+        age_dir: dir.clone(),
+        ..Default::default()
+    };
+
+    // Without templates for the Castle and Imperial Age no age is detected
+    let mut analyzer = ImageAnalyzerInner::with_config(&config)?;
+    assert_eq!(detect_age(&mut analyzer, &feudal_frame)?, None);
+    assert_eq!(detect_age(&mut analyzer, &dark_frame)?, None);
+
+    // Stand-ins cut from the terrain, they match neither frame
+    for (age, x) in [(Age::Castle, 1800), (Age::Imperial, 700)] {
+        let terrain = Rect::new(x, 400, EMBLEM.width, EMBLEM.height);
+        write_png(
+            &dir.join(format!("{}.png", age.name())),
+            &Mat::roi(&dark_frame, terrain)?.try_clone()?,
+        )?;
+    }
+    let mut analyzer = ImageAnalyzerInner::with_config(&config)?;
+    let feudal = detect_age(&mut analyzer, &feudal_frame)?;
+    let dark = detect_age(&mut analyzer, &dark_frame)?;
+    std::fs::remove_dir_all(&dir)?;
 
+    assert_eq!(feudal, Some(Age::Feudal));
+    assert_eq!(dark, Some(Age::Dark));
     Ok(())
 }
@@ -0,0 +1,156 @@
+// Match reports written at the end of a match
+
+use aoe4_overlay::{
+    alerts::Alert,
+    game_state::{GameState, Population, Reading},
+    i18n,
+    match_report::{MatchReport, MatchReportBuilder, ReportConfig, ReportFormat},
+    match_stats::{MatchStats, PhaseStats},
+};
+
+fn alert(name: &str) -> Alert {
+    Alert {
+        name: name.to_string(),
+        priority: 0,
+        message: None,
+        message_args: Vec::new(),
+        icon: None,
+        since_ms: 0,
+    }
+}
+
+fn report(started: &str, alert_name: &str) -> MatchReport {
+    let mut builder = MatchReportBuilder::new(&ReportConfig::default());
+    let state = GameState {
+        population: Reading::Known(Population {
+            current: 5,
+            cap: 10,
+        }),
+        ..Default::default()
+    };
+    for second in 0..120 {
+        let alerts = if (20..30).contains(&second) {
+            vec![alert(alert_name)]
+        } else {
+            Vec::new()
+        };
+        builder.update(second * 1000, &state, &alerts);
+    }
+    let stats = MatchStats {
+        duration_ms: 120_000,
+        tc_idle_ms: 30_000,
+        phases: vec![PhaseStats {
+            name: "phase.opening".to_string(),
+            duration_ms: 120_000,
+            tc_idle_ms: 30_000,
+        }],
+    };
+    builder.finish(started.to_string(), stats)
+}
+
+#[test]
+fn test_alert_statistics() {
+    let report = report("2025-01-31 20:15:00", "idle");
+    assert_eq!(report.alerts.len(), 1);
+    assert_eq!(report.alerts[0].count, 1);
+    assert_eq!(report.alerts[0].total_ms, 10_000);
+    assert_eq!(report.timeline.len(), 24);
+    assert_eq!(report.file_stem(), "match_2025-01-31_20-15-00");
+}
+
+#[test]
+fn test_timeline_never_goes_back() {
+    let mut builder = MatchReportBuilder::new(&ReportConfig {
+        sample_interval_ms: 0,
+        ..Default::default()
+    });
+    let state = GameState::default();
+    builder.update(0, &state, &[]);
+    builder.update(10_000, &state, &[alert("idle")]);
+    // The match timer is readable again and behind the capture time
+    builder.update(4_000, &state, &[alert("idle")]);
+    builder.update(12_000, &state, &[]);
+    builder.update(15_000, &state, &[alert("float")]);
+    builder.update(13_000, &state, &[alert("float")]);
+    let report = builder.finish("2025-01-31 20:15:00".to_string(), MatchStats::default());
+
+    let times: Vec<u64> = report
+        .timeline
+        .iter()
+        .map(|sample| sample.time_ms)
+        .collect();
+    assert_eq!(times, [0, 10_000, 10_000, 12_000, 15_000, 15_000]);
+    let idle = report
+        .alerts
+        .iter()
+        .find(|alert| alert.name == "idle")
+        .unwrap();
+    assert_eq!(idle.total_ms, 2_000);
+    // Ended with the match, not before it started
+    let float = report
+        .alerts
+        .iter()
+        .find(|alert| alert.name == "float")
+        .unwrap();
+    assert_eq!((float.count, float.total_ms), (1, 0));
+}
+
+#[test]
+fn test_labels_from_catalog() {
+    i18n::init(Some("en"));
+    let report = report("2025-01-31 20:15:00", "idle");
+    let markdown = report.to_markdown();
+    for label in [
+        "# Match",
+        "| Duration |",
+        "| TC idle Opening |",
+        "## Timeline",
+    ] {
+        assert!(
+            markdown.contains(label),
+            "'{}' missing in:\n{}",
+            label,
+            markdown
+        );
+    }
+    assert!(markdown.contains("| Time | Population | Population cap |"));
+    assert!(
+        !markdown.contains("report."),
+        "untranslated key in:\n{}",
+        markdown
+    );
+
+    let html = report.to_html();
+    assert!(html.contains("<h2>Alerts</h2>"));
+    assert!(!html.contains("report."), "untranslated key in:\n{}", html);
+}
+
+#[test]
+fn test_markdown_cells_escaped() {
+    let report = report("2025-01-31 20:15:00", "a|b\nc");
+    let markdown = report.to_markdown();
+    let row = markdown
+        .lines()
+        .find(|line| line.starts_with("| a"))
+        .expect("alert row missing");
+    assert!(row.starts_with("| a\\|b c | 1 |"), "{}", row);
+    assert!(report.to_html().contains("<td>a|b\nc</td>"));
+}
+
+#[test]
+fn test_reports_not_overwritten() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("aoe4_overlay_reports_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let first = report("unknown", "idle").save(&dir, ReportFormat::Markdown)?;
+    let second = report("unknown", "house").save(&dir, ReportFormat::Markdown)?;
+    let html = report("unknown", "idle").save(&dir, ReportFormat::Html)?;
+    assert_eq!(first, dir.join("match_unknown.md"));
+    assert_eq!(second, dir.join("match_unknown_2.md"));
+    assert_eq!(html, dir.join("match_unknown.html"));
+    assert!(std::fs::read_to_string(&first)?.contains("| idle |"));
+    assert!(std::fs::read_to_string(&second)?.contains("| house |"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,122 @@
+// Villager production idle time per match and game phase
+
+use aoe4_overlay::{
+    game_state::{Age, GameState, Population, Reading},
+    match_stats::{MatchStatsTracker, StatsConfig},
+};
+
+fn state(age: Option<Age>, villager_in_production: bool) -> GameState {
+    GameState {
+        population: Reading::Known(Population {
+            current: 5,
+            cap: 10,
+        }),
+        villager_in_production,
+        age: age.into(),
+        ..Default::default()
+    }
+}
+
+/// Feed one frame per second for `seconds`, returns the last timestamp
+fn play(tracker: &mut MatchStatsTracker, state: &GameState, from_ms: u64, seconds: u64) -> u64 {
+    let mut timestamp_ms = from_ms;
+    for _ in 0..seconds {
+        timestamp_ms += 1000;
+        tracker.update(state, None, timestamp_ms);
+    }
+    timestamp_ms
+}
+
+#[test]
+fn test_idle_time() {
+    let mut tracker = MatchStatsTracker::new(StatsConfig::default());
+    tracker.update(&state(None, false), None, 0);
+    assert!(tracker.current().is_none(), "no match running");
+
+    tracker.start(1000);
+    let timestamp_ms = play(&mut tracker, &state(None, false), 1000, 30);
+    let timestamp_ms = play(&mut tracker, &state(None, true), timestamp_ms, 30);
+    // A long gap counts only up to `max_frame_gap_ms`
+    tracker.update(&state(None, false), None, timestamp_ms + 60_000);
+
+    let stats = tracker.finish().unwrap();
+    assert_eq!(stats.duration_ms, 62_000);
+    assert_eq!(stats.tc_idle_ms, 32_000);
+    assert!(tracker.current().is_none());
+}
+
+#[test]
+fn test_phase_by_time_without_age() {
+    let mut tracker = MatchStatsTracker::new(StatsConfig::default());
+    tracker.start(0);
+    assert_eq!(tracker.current_phase(), Some("phase.opening"));
+    play(&mut tracker, &state(None, true), 0, 6 * 60);
+    assert_eq!(tracker.current_phase(), Some("phase.early"));
+
+    let stats = tracker.current().unwrap();
+    let names: Vec<_> = stats
+        .phases
+        .iter()
+        .map(|phase| phase.name.as_str())
+        .collect();
+    assert_eq!(names, ["phase.opening", "phase.early"]);
+    assert_eq!(stats.phases[0].duration_ms, 5 * 60_000);
+}
+
+#[test]
+fn test_phase_by_age() {
+    let mut tracker = MatchStatsTracker::new(StatsConfig::default());
+    tracker.start(0);
+    // Still in the Dark Age after 8 minutes
+    let timestamp_ms = play(&mut tracker, &state(Some(Age::Dark), true), 0, 8 * 60);
+    assert_eq!(tracker.current_phase(), Some("phase.opening"));
+
+    // A fast castle skips the time based phases
+    play(
+        &mut tracker,
+        &state(Some(Age::Castle), true),
+        timestamp_ms,
+        60,
+    );
+    assert_eq!(tracker.current_phase(), Some("phase.mid"));
+    let names: Vec<_> = tracker
+        .current()
+        .unwrap()
+        .phases
+        .iter()
+        .map(|phase| phase.name.clone())
+        .collect();
+    assert_eq!(names, ["phase.opening", "phase.mid"]);
+
+    // An unreadable emblem keeps the last detected age
+    tracker.update(&state(None, true), None, timestamp_ms + 61_000);
+    assert_eq!(tracker.current_phase(), Some("phase.mid"));
+
+    // A new match forgets the age
+    tracker.start(0);
+    assert_eq!(tracker.current_phase(), Some("phase.opening"));
+}
+
+#[test]
+fn test_phase_by_game_time() {
+    let mut tracker = MatchStatsTracker::new(StatsConfig::default());
+    // A replay started at 13:00 of the match
+    tracker.start(0);
+    tracker.update(&state(None, true), Some(13 * 60_000), 1000);
+    assert_eq!(tracker.current_phase(), Some("phase.mid"));
+
+    // An unreadable timer keeps the last game time
+    tracker.update(&state(None, true), None, 2000);
+    assert_eq!(tracker.current_phase(), Some("phase.mid"));
+    let stats = tracker.current().unwrap();
+    assert_eq!(stats.duration_ms, 2000);
+    assert_eq!(stats.phases[0].name, "phase.mid");
+
+    // The detected age still comes first
+    tracker.update(&state(Some(Age::Feudal), true), Some(14 * 60_000), 3000);
+    assert_eq!(tracker.current_phase(), Some("phase.early"));
+
+    // A new match forgets the game time
+    tracker.start(0);
+    assert_eq!(tracker.current_phase(), Some("phase.opening"));
+}
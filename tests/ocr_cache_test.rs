@@ -0,0 +1,135 @@
+// Reuse of the OCR texts of unchanged stat regions
+
+use aoe4_overlay::ocr_cache::{OcrCache, OcrCacheConfig};
+use image::{Rgb, RgbImage};
+
+fn text(text: &str) -> fixedstr::str8 {
+    fixedstr::str8::from(text)
+}
+
+fn region() -> RgbImage {
+    RgbImage::from_fn(20, 10, |x, y| Rgb([(x * 10) as u8, (y * 20) as u8, 100]))
+}
+
+/// `region()` with the first `count` pixels brightened past the pixel threshold
+fn changed(count: u32) -> RgbImage {
+    let mut image = region();
+    for pixel in image.pixels_mut().take(count as usize) {
+        pixel.0[2] = 200;
+    }
+    image
+}
+
+#[test]
+fn test_hit_on_unchanged_image() {
+    let mut cache = OcrCache::new(&OcrCacheConfig::default());
+    assert_eq!(cache.lookup(3, &region()), None);
+    cache.insert(3, region(), text("210"));
+
+    assert_eq!(cache.lookup(3, &region()), Some(text("210")));
+    // Differences up to the pixel threshold don't count
+    let mut noisy = region();
+    noisy.pixels_mut().for_each(|pixel| pixel.0[2] += 40);
+    assert_eq!(cache.lookup(3, &noisy), Some(text("210")));
+    // Other regions have their own texts
+    assert_eq!(cache.lookup(2, &region()), None);
+
+    let stats = cache.take_stats();
+    assert_eq!((stats.hits, stats.misses), (2, 2));
+    assert_eq!(stats.hit_rate(), 0.5);
+    assert_eq!(cache.take_stats().hits, 0);
+}
+
+#[test]
+fn test_miss_on_changed_pixels() {
+    let config = OcrCacheConfig::default();
+    let mut cache = OcrCache::new(&config);
+    cache.insert(0, region(), text("50"));
+
+    assert_eq!(
+        cache.lookup(0, &changed(config.max_changed_pixels)),
+        Some(text("50"))
+    );
+    assert_eq!(
+        cache.lookup(0, &changed(config.max_changed_pixels + 1)),
+        None
+    );
+    // Compared with the image the text was read from, not the last lookup
+    assert_eq!(
+        cache.lookup(0, &changed(config.max_changed_pixels)),
+        Some(text("50"))
+    );
+}
+
+#[test]
+fn test_miss_on_size_change() {
+    let mut cache = OcrCache::new(&OcrCacheConfig::default());
+    cache.insert(0, region(), text("50"));
+
+    // A new layout or UI scale changes the region size
+    let larger = RgbImage::from_pixel(40, 20, Rgb([0, 0, 100]));
+    assert_eq!(cache.lookup(0, &larger), None);
+    assert_eq!(cache.lookup(0, &RgbImage::new(0, 0)), None);
+}
+
+#[test]
+fn test_refresh_after_max_reuse() {
+    let config = OcrCacheConfig {
+        max_reuse_frames: 3,
+        ..Default::default()
+    };
+    let mut cache = OcrCache::new(&config);
+    cache.insert(0, region(), text("9/10"));
+    for _ in 0..3 {
+        assert_eq!(cache.lookup(0, &region()), Some(text("9/10")));
+    }
+    assert_eq!(cache.lookup(0, &region()), None);
+    // Reading the region again starts over
+    cache.insert(0, region(), text("9/10"));
+    assert_eq!(cache.lookup(0, &region()), Some(text("9/10")));
+
+    // 0 never refreshes
+    let mut cache = OcrCache::new(&OcrCacheConfig {
+        max_reuse_frames: 0,
+        ..Default::default()
+    });
+    cache.insert(0, region(), text("9/10"));
+    for _ in 0..1000 {
+        assert_eq!(cache.lookup(0, &region()), Some(text("9/10")));
+    }
+}
+
+#[test]
+fn test_empty_text_not_cached() {
+    let mut cache = OcrCache::new(&OcrCacheConfig::default());
+    cache.insert(0, region(), text(""));
+    assert_eq!(cache.lookup(0, &region()), None);
+    cache.insert(0, region(), text(" "));
+    assert_eq!(cache.lookup(0, &region()), None);
+
+    // A failed read replaces the previous text
+    cache.insert(0, region(), text("210"));
+    cache.insert(0, changed(20), text(""));
+    assert_eq!(cache.lookup(0, &region()), None);
+}
+
+#[test]
+fn test_set_config_clears() {
+    let mut cache = OcrCache::new(&OcrCacheConfig::default());
+    cache.insert(0, region(), text("210"));
+    cache.insert(5, region(), text("4"));
+
+    cache.set_config(&OcrCacheConfig::default());
+    assert_eq!(cache.lookup(0, &region()), None);
+    assert_eq!(cache.lookup(5, &region()), None);
+
+    // Disabled, nothing is stored or reused
+    cache.set_config(&OcrCacheConfig {
+        enabled: false,
+        ..Default::default()
+    });
+    cache.insert(0, region(), text("210"));
+    assert_eq!(cache.lookup(0, &region()), None);
+    cache.set_config(&OcrCacheConfig::default());
+    assert_eq!(cache.lookup(0, &region()), None);
+}
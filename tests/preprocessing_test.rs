@@ -0,0 +1,172 @@
+// Preprocessing chains of the stat regions
+
+use anyhow::Result;
+use aoe4_overlay::{
+    consts::AOE4_STATS_POS,
+    preprocessing::{self, PreprocessConfig, PreprocessStep},
+};
+use image::{Rgb, RgbImage};
+use opencv::{
+    core::{CV_8UC3, Mat, Scalar},
+    prelude::*,
+};
+use std::collections::BTreeMap;
+
+fn stat_index(name: &str) -> usize {
+    AOE4_STATS_POS
+        .iter()
+        .position(|stat| stat.name == name)
+        .unwrap()
+}
+
+fn config(regions: &[(&str, Vec<PreprocessStep>)]) -> PreprocessConfig {
+    PreprocessConfig {
+        regions: regions
+            .iter()
+            .map(|(name, chain)| (name.to_string(), chain.clone()))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+const HSV_RANGE: PreprocessStep = PreprocessStep::HsvRange {
+    low: [0, 0, 170],
+    high: [180, 60, 255],
+};
+
+#[test]
+fn test_unknown_region_names() {
+    let error = config(&[("Foood", vec![])]).validate().unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("Unknown preprocessing region 'Foood'"),
+        "{}",
+        error
+    );
+    // Stat names are case sensitive
+    assert!(config(&[("food worker", vec![])]).validate().is_err());
+
+    for name in [
+        "Food Worker",
+        "Pop",
+        "workers",
+        "resource",
+        "population",
+        "idle",
+    ] {
+        config(&[(name, vec![PreprocessStep::Grayscale])])
+            .validate()
+            .unwrap_or_else(|e| panic!("'{}' was rejected: {}", name, e));
+    }
+}
+
+#[test]
+fn test_colour_step_after_grayscale() {
+    let mut chains = PreprocessConfig {
+        default: vec![PreprocessStep::Grayscale, HSV_RANGE],
+        regions: BTreeMap::new(),
+    };
+    assert!(chains.validate().is_err());
+    chains.default = vec![HSV_RANGE, PreprocessStep::Grayscale];
+    chains.validate().unwrap();
+
+    // The adaptive threshold makes the image gray as well
+    let threshold = PreprocessStep::AdaptiveThreshold {
+        block_size: 11,
+        offset: 2.0,
+        invert: false,
+    };
+    let error = config(&[("resource", vec![threshold, HSV_RANGE])])
+        .validate()
+        .unwrap_err();
+    assert!(error.to_string().contains("'resource'"), "{}", error);
+
+    // Steps that keep the colour may come in between
+    let chain = vec![
+        PreprocessStep::Brighten { amount: 10 },
+        HSV_RANGE,
+        PreprocessStep::Upscale { factor: 2.0 },
+        PreprocessStep::Grayscale,
+        PreprocessStep::Sharpen {
+            amount: 1.0,
+            sigma: 1.0,
+        },
+    ];
+    config(&[("workers", chain)]).validate().unwrap();
+}
+
+#[test]
+fn test_stat_name_over_text_type() {
+    let by_type = vec![PreprocessStep::Grayscale];
+    let by_name = vec![HSV_RANGE, PreprocessStep::Grayscale];
+    let config = config(&[
+        ("workers", by_type.clone()),
+        ("Food Worker", by_name.clone()),
+    ]);
+
+    assert_eq!(config.chain(stat_index("Food Worker")), by_name.as_slice());
+    assert_eq!(config.chain(stat_index("Wood Worker")), by_type.as_slice());
+    assert_eq!(config.chain(stat_index("Food")), config.default.as_slice());
+}
+
+#[test]
+fn test_config_file_format() -> Result<()> {
+    let config: PreprocessConfig = toml::from_str(
+        r#"
+        default = [{ step = "grayscale" }]
+
+        [regions]
+        workers = [
+          { step = "hsv_range", low = [0, 0, 170], high = [180, 60, 255] },
+          { step = "upscale", factor = 2.0 },
+        ]
+        "#,
+    )?;
+    assert_eq!(config.default, [PreprocessStep::Grayscale]);
+    assert_eq!(
+        config.chain(stat_index("Gold Worker")),
+        [HSV_RANGE, PreprocessStep::Upscale { factor: 2.0 }]
+    );
+    config.validate()
+}
+
+#[test]
+fn test_run_keeps_stages() -> Result<()> {
+    let img = Mat::new_rows_cols_with_default(4, 6, CV_8UC3, Scalar::all(100.0))?;
+    let steps = PreprocessConfig::default().default;
+    let mut stages = Vec::new();
+    let output = preprocessing::run(&steps, &img, Some(&mut stages))?;
+
+    assert_eq!(output.dimensions(), (6, 4));
+    assert!(output.pixels().all(|pixel| *pixel == Rgb([130, 130, 130])));
+    // The input and the output of every step
+    assert_eq!(stages.len(), steps.len() + 1);
+    assert_eq!(stages[1].channels(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_stacked_region_offsets() {
+    let images = [
+        RgbImage::from_pixel(10, 4, Rgb([255, 0, 0])),
+        RgbImage::from_pixel(6, 3, Rgb([0, 255, 0])),
+        RgbImage::from_pixel(12, 5, Rgb([0, 0, 255])),
+    ];
+    let (stacked, regions) = preprocessing::stack(&images);
+
+    assert_eq!(stacked.dimensions(), (12, 12));
+    assert_eq!(regions, [(0, 0, 10, 4), (0, 4, 6, 3), (0, 7, 12, 5)]);
+    for ((x, y, width, height), image) in regions.iter().zip(&images) {
+        let color = image.get_pixel(0, 0);
+        assert_eq!(stacked.get_pixel(*x, *y), color);
+        assert_eq!(stacked.get_pixel(x + width - 1, y + height - 1), color);
+    }
+    // Narrower images are padded with black
+    assert_eq!(*stacked.get_pixel(10, 0), Rgb([0, 0, 0]));
+    assert_eq!(*stacked.get_pixel(6, 4), Rgb([0, 0, 0]));
+
+    let (empty, regions) = preprocessing::stack(Vec::<&RgbImage>::new());
+    assert_eq!(empty.dimensions(), (0, 0));
+    assert!(regions.is_empty());
+}
@@ -0,0 +1,144 @@
+// Warning about resources that stay banked
+
+use aoe4_overlay::{
+    alerts::AlertTiming,
+    game_state::{GameState, PerResource, Reading},
+    i18n,
+    resource_float::{FloatConfig, FloatDetector},
+};
+
+fn config() -> FloatConfig {
+    FloatConfig {
+        thresholds: PerResource {
+            food: 800,
+            wood: 800,
+            gold: 600,
+            stone: 600,
+        },
+        timing: AlertTiming {
+            min_duration_ms: 10_000,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn stock(food: u32, wood: u32) -> GameState {
+    GameState {
+        resources: PerResource {
+            food: Reading::Known(food),
+            wood: Reading::Known(wood),
+            gold: Reading::Known(0),
+            stone: Reading::Known(0),
+        },
+        ..Default::default()
+    }
+}
+
+/// Resource names of the warning at `timestamp_ms`, `None` if nothing floats
+fn floating(
+    detector: &mut FloatDetector,
+    state: &GameState,
+    timestamp_ms: u64,
+) -> Option<Vec<String>> {
+    detector
+        .alert(state, timestamp_ms)
+        .map(|alert| alert.message_args)
+}
+
+#[test]
+fn test_threshold() {
+    let mut detector = FloatDetector::new(&config());
+    // Exactly the threshold is not floating
+    assert_eq!(floating(&mut detector, &stock(800, 0), 0), None);
+    assert_eq!(floating(&mut detector, &stock(800, 0), 60_000), None);
+
+    assert_eq!(floating(&mut detector, &stock(801, 0), 60_000), None);
+    assert_eq!(
+        floating(&mut detector, &stock(801, 0), 70_000),
+        Some(vec!["stat.food".to_string()])
+    );
+    // Dropping below the threshold clears the warning at once
+    assert_eq!(floating(&mut detector, &stock(500, 0), 71_000), None);
+}
+
+#[test]
+fn test_duration() {
+    let mut detector = FloatDetector::new(&config());
+    assert!(detector.alert(&stock(1000, 0), 0).is_none());
+    assert!(detector.alert(&stock(1000, 0), 9_999).is_none());
+    let alert = detector.alert(&stock(1000, 0), 10_000).unwrap();
+    assert_eq!(alert.name, "float");
+    assert_eq!(alert.priority, config().priority);
+    assert_eq!(alert.since_ms, 10_000);
+
+    // The timer starts again after the stock was spent
+    assert!(detector.alert(&stock(200, 0), 12_000).is_none());
+    assert!(detector.alert(&stock(1000, 0), 13_000).is_none());
+    assert!(detector.alert(&stock(1000, 0), 22_999).is_none());
+    assert_eq!(
+        detector.alert(&stock(1000, 0), 23_000).unwrap().since_ms,
+        23_000
+    );
+
+    // Unreadable stocks keep their timer but are not listed
+    let mut unreadable = stock(1000, 0);
+    unreadable.resources.food = Reading::Unknown;
+    assert!(detector.alert(&unreadable, 25_000).is_none());
+    assert_eq!(
+        detector.alert(&stock(1000, 0), 26_000).unwrap().since_ms,
+        23_000
+    );
+
+    detector.reset();
+    assert!(detector.alert(&stock(1000, 0), 27_000).is_none());
+}
+
+#[test]
+fn test_several_resources() {
+    let mut detector = FloatDetector::new(&config());
+    detector.alert(&stock(1000, 0), 0);
+    detector.alert(&stock(1000, 1000), 5_000);
+    let alert = detector.alert(&stock(1000, 1000), 10_000).unwrap();
+    assert_eq!(alert.message_args, ["stat.food"]);
+    let alert = detector.alert(&stock(1000, 1000), 15_000).unwrap();
+    assert_eq!(alert.message_args, ["stat.food", "stat.wood"]);
+    // Since the first resource floats
+    assert_eq!(alert.since_ms, 10_000);
+}
+
+#[test]
+fn test_config() {
+    let mut detector = FloatDetector::new(&FloatConfig {
+        enabled: false,
+        ..config()
+    });
+    assert!(detector.alert(&stock(5000, 5000), 0).is_none());
+    assert!(detector.alert(&stock(5000, 5000), 60_000).is_none());
+
+    let mut detector = FloatDetector::new(&config());
+    detector.alert(&stock(1000, 0), 0);
+    // The same configuration keeps the timers, another one starts over
+    detector.set_config(&config());
+    assert!(detector.alert(&stock(1000, 0), 10_000).is_some());
+    detector.set_config(&FloatConfig {
+        priority: 1,
+        ..config()
+    });
+    assert!(detector.alert(&stock(1000, 0), 11_000).is_none());
+}
+
+#[test]
+fn test_message_translated_when_shown() {
+    i18n::init(Some("en"));
+    let mut detector = FloatDetector::new(&config());
+    detector.alert(&stock(1000, 1000), 0);
+    let alert = detector.alert(&stock(1000, 1000), 10_000).unwrap();
+    assert_eq!(alert.message.as_deref(), Some("alert.float"));
+    assert_eq!(alert.text().unwrap(), "Spend your resources! Food, Wood");
+
+    // A language change applies to alerts that are already raised
+    i18n::init(Some("de"));
+    assert_eq!(alert.text().unwrap(), "Ressourcen ausgeben! Nahrung, Holz");
+    i18n::init(Some("en"));
+}
@@ -0,0 +1,117 @@
+// Match start and end detection from the screen classification of consecutive frames
+
+use aoe4_overlay::session::{Screen, SessionConfig, SessionDetector, SessionEvent};
+
+/// Feed `frames` frames of `screen` 100 ms apart, returns the events and the last timestamp
+fn feed(
+    detector: &mut SessionDetector,
+    screen: Screen,
+    from_ms: u64,
+    frames: u64,
+) -> (Vec<SessionEvent>, u64) {
+    let mut events = Vec::new();
+    let mut timestamp_ms = from_ms;
+    for _ in 0..frames {
+        timestamp_ms += 100;
+        events.extend(detector.update(screen, timestamp_ms));
+    }
+    (events, timestamp_ms)
+}
+
+#[test]
+fn test_classify() {
+    let config = SessionConfig::default();
+    assert_eq!(
+        Screen::classify(3, || panic!("brightness of an in-game frame"), &config),
+        Screen::InGame
+    );
+    assert_eq!(Screen::classify(2, || 10.0, &config), Screen::Loading);
+    assert_eq!(Screen::classify(0, || 120.0, &config), Screen::Menu);
+
+    let disabled = SessionConfig {
+        enabled: false,
+        ..Default::default()
+    };
+    assert_eq!(Screen::classify(0, || 120.0, &disabled), Screen::InGame);
+}
+
+#[test]
+fn test_match_starts_after_confirm_frames() {
+    let mut detector = SessionDetector::new(&SessionConfig::default());
+    let (events, timestamp_ms) = feed(&mut detector, Screen::InGame, 0, 2);
+    assert!(events.is_empty());
+    assert!(!detector.in_match());
+
+    // A flickering HUD starts counting again
+    let (events, timestamp_ms) = feed(&mut detector, Screen::Menu, timestamp_ms, 1);
+    assert!(events.is_empty());
+    let (events, timestamp_ms) = feed(&mut detector, Screen::InGame, timestamp_ms, 2);
+    assert!(events.is_empty());
+
+    let (events, _) = feed(&mut detector, Screen::InGame, timestamp_ms, 1);
+    assert_eq!(events, [SessionEvent::MatchStarted]);
+    assert!(detector.in_match());
+    assert_eq!(detector.screen(), Screen::InGame);
+}
+
+#[test]
+fn test_loading_screen_ends_match() {
+    let mut detector = SessionDetector::new(&SessionConfig::default());
+    let (_, timestamp_ms) = feed(&mut detector, Screen::InGame, 0, 5);
+    assert!(detector.in_match());
+
+    // The game menu or a single dark frame do not end the match
+    let (events, timestamp_ms) = feed(&mut detector, Screen::Menu, timestamp_ms, 20);
+    assert!(events.is_empty());
+    let (events, timestamp_ms) = feed(&mut detector, Screen::Loading, timestamp_ms, 2);
+    assert!(events.is_empty());
+    let (events, timestamp_ms) = feed(&mut detector, Screen::InGame, timestamp_ms, 1);
+    assert!(events.is_empty());
+
+    let (events, _) = feed(&mut detector, Screen::Loading, timestamp_ms, 3);
+    assert_eq!(events, [SessionEvent::MatchEnded]);
+    assert!(!detector.in_match());
+}
+
+#[test]
+fn test_match_ends_after_timeout() {
+    let config = SessionConfig::default();
+    let mut detector = SessionDetector::new(&config);
+    let (_, last_in_game_ms) = feed(&mut detector, Screen::InGame, 0, 5);
+
+    assert_eq!(
+        detector.update(Screen::Menu, last_in_game_ms + config.end_timeout_ms),
+        None
+    );
+    assert_eq!(
+        detector.update(Screen::Menu, last_in_game_ms + config.end_timeout_ms + 1),
+        Some(SessionEvent::MatchEnded)
+    );
+    // Only one end event
+    assert_eq!(
+        detector.update(Screen::Menu, last_in_game_ms + config.end_timeout_ms + 100),
+        None
+    );
+}
+
+#[test]
+fn test_time_going_backwards_ends_match() {
+    let mut detector = SessionDetector::new(&SessionConfig::default());
+    let (_, _) = feed(&mut detector, Screen::InGame, 10_000, 5);
+    assert_eq!(
+        detector.update(Screen::InGame, 100),
+        Some(SessionEvent::MatchEnded)
+    );
+    // The restarted replay needs to confirm the HUD again
+    let (events, _) = feed(&mut detector, Screen::InGame, 100, 3);
+    assert_eq!(events, [SessionEvent::MatchStarted]);
+}
+
+#[test]
+fn test_finish() {
+    let mut detector = SessionDetector::new(&SessionConfig::default());
+    assert_eq!(detector.finish(), None);
+    feed(&mut detector, Screen::InGame, 0, 3);
+    assert_eq!(detector.finish(), Some(SessionEvent::MatchEnded));
+    assert_eq!(detector.finish(), None);
+}
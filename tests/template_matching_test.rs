@@ -0,0 +1,95 @@
+// Speed of the template matching OCR on the annotated screenshots of `src_images/annotations.toml`,
+// at their own size and scaled up to 4K. The OCR time of a frame (all stat regions) is only
+// checked in optimized builds:
+//   cargo test --release --test template_matching_test -- --nocapture
+
+use anyhow::Result;
+use aoe4_overlay::{
+    consts::AOE4_STATS_POS,
+    corpus::Corpus,
+    hud_layout::HudLayout,
+    image_analyzer::{AnalyzerConfig, ImageAnalyzerInner, OCRModel},
+    ocr_cache::OcrCacheConfig,
+};
+use opencv::{
+    core::{Mat, Size},
+    imgproc,
+    prelude::*,
+};
+use std::{path::Path, time::Duration};
+
+const ANNOTATIONS: &str = "src_images/annotations.toml";
+/// Analyses per frame, the fastest one counts
+const ROUNDS: usize = 5;
+/// Maximum OCR time of a frame in optimized builds
+const BUDGET: Duration = Duration::from_millis(10);
+
+/// Fastest OCR time of the normalized HUD panel of a frame
+fn ocr_time(
+    analyzer: &mut ImageAnalyzerInner,
+    frame: &Mat,
+    layout: &HudLayout,
+) -> Result<Duration> {
+    let resolved = layout.resolve(frame.cols() as u32, frame.rows() as u32);
+    let mut fastest = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let hud = ImageAnalyzerInner::extract_hud_area(frame, &resolved)?;
+        fastest = fastest.min(analyzer.analyze(hud, &resolved)?.ocr_time);
+    }
+    Ok(fastest)
+}
+
+#[test]
+fn test_template_matching_speed() -> Result<()> {
+    let corpus = Corpus::load(Path::new(ANNOTATIONS))?;
+    // Every round reads all regions again
+    let mut analyzer = ImageAnalyzerInner::with_config(&AnalyzerConfig {
+        ocr_engine: OCRModel::TemplateMatching,
+        ocr_cache: OcrCacheConfig {
+            enabled: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    })?;
+
+    let mut slow = Vec::new();
+    for sample in corpus.samples.iter().filter(|sample| !sample.hud_crop) {
+        let (frame, _) = corpus.load_frame(sample)?;
+        let mut frame_4k = Mat::default();
+        imgproc::resize(
+            &frame,
+            &mut frame_4k,
+            Size::new(3840, 2160),
+            0.0,
+            0.0,
+            imgproc::INTER_LINEAR,
+        )?;
+
+        for (frame, size) in [(&frame, "native"), (&frame_4k, "4K")] {
+            let layout = HudLayout::for_frame_size(frame.cols() as u32, frame.rows() as u32);
+            let time = ocr_time(&mut analyzer, frame, &layout)?;
+            println!(
+                "{} ({}): {} regions in {:.2} ms",
+                sample.file.display(),
+                size,
+                AOE4_STATS_POS.len(),
+                time.as_secs_f64() * 1000.0
+            );
+            if time > BUDGET {
+                slow.push(format!("{} ({}): {:?}", sample.file.display(), size, time));
+            }
+        }
+    }
+
+    if cfg!(debug_assertions) {
+        println!("Debug build, the times are not checked");
+    } else {
+        assert!(
+            slow.is_empty(),
+            "OCR slower than {:?}:\n{}",
+            BUDGET,
+            slow.join("\n")
+        );
+    }
+    Ok(())
+}
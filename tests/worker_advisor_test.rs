@@ -0,0 +1,135 @@
+// Worker distribution advice for the builtin profiles
+
+use aoe4_overlay::{
+    game_state::{GameState, PerResource, Reading, Resource},
+    i18n,
+    worker_advisor::{Imbalance, WorkerAdvisor, WorkerConfig},
+};
+
+fn workers(food: u32, wood: u32, gold: u32, stone: u32) -> GameState {
+    GameState {
+        workers: PerResource {
+            food: Reading::Known(food),
+            wood: Reading::Known(wood),
+            gold: Reading::Known(gold),
+            stone: Reading::Known(stone),
+        },
+        ..Default::default()
+    }
+}
+
+fn advisor(profile: &str) -> WorkerAdvisor {
+    WorkerAdvisor::new(&WorkerConfig {
+        profile: profile.to_string(),
+        ..Default::default()
+    })
+}
+
+fn imbalance(resource: Resource, difference: i32) -> Option<Imbalance> {
+    Some(Imbalance {
+        resource,
+        difference,
+    })
+}
+
+#[test]
+fn test_standard_profile() {
+    let advisor = advisor("standard");
+    // Early game targets 50% food, 30% wood, 20% gold: 10, 6 and 4 of 20
+    let advice = advisor
+        .advise(&workers(15, 3, 2, 0), "phase.early")
+        .unwrap();
+    assert_eq!(advice.surplus, imbalance(Resource::Food, 5));
+    assert_eq!(advice.deficit, imbalance(Resource::Wood, -3));
+
+    let advice = advisor.advise(&workers(6, 9, 9, 0), "phase.early").unwrap();
+    assert_eq!(advice.surplus, imbalance(Resource::Gold, 4));
+    assert_eq!(advice.deficit, imbalance(Resource::Food, -6));
+
+    assert_eq!(advisor.advise(&workers(10, 6, 4, 0), "phase.early"), None);
+    // The opening has no gold target
+    assert_eq!(advisor.advise(&workers(12, 8, 0, 0), "phase.opening"), None);
+}
+
+#[test]
+fn test_fast_castle_profile() {
+    let advisor = advisor("fast_castle");
+    // Early game targets 45% food, 20% wood, 35% gold: 9, 4 and 7 of 20
+    let advice = advisor
+        .advise(&workers(15, 3, 2, 0), "phase.early")
+        .unwrap();
+    assert_eq!(advice.surplus, imbalance(Resource::Food, 6));
+    // Wood is only 1 below its target
+    assert_eq!(advice.deficit, imbalance(Resource::Gold, -5));
+
+    // Balanced for the standard profile, too much wood and too little gold for a fast castle
+    let advice = advisor
+        .advise(&workers(10, 6, 4, 0), "phase.early")
+        .unwrap();
+    assert_eq!(advice.surplus, imbalance(Resource::Wood, 2));
+    assert_eq!(advice.deficit, imbalance(Resource::Gold, -3));
+}
+
+#[test]
+fn test_small_imbalances_ignored() {
+    let advisor = advisor("standard");
+    assert_eq!(advisor.advise(&workers(11, 5, 4, 0), "phase.early"), None);
+    assert_eq!(advisor.advise(&workers(0, 0, 0, 0), "phase.early"), None);
+
+    let strict = WorkerAdvisor::new(&WorkerConfig {
+        min_imbalance: 1,
+        ..Default::default()
+    });
+    let advice = strict.advise(&workers(11, 5, 4, 0), "phase.early").unwrap();
+    assert_eq!(advice.surplus, imbalance(Resource::Food, 1));
+    assert_eq!(advice.deficit, imbalance(Resource::Wood, -1));
+}
+
+#[test]
+fn test_unknown_resources() {
+    let advisor = advisor("standard");
+    for resource in Resource::ALL {
+        let mut state = workers(15, 3, 2, 0);
+        *state.workers.get_mut(resource) = Reading::Unknown;
+        assert_eq!(
+            advisor.advise(&state, "phase.early"),
+            None,
+            "{:?}",
+            resource
+        );
+    }
+    assert_eq!(advisor.advise(&GameState::default(), "phase.early"), None);
+}
+
+#[test]
+fn test_profile_and_phase_selection() {
+    // Phases without a target use the last one: 35% food, 30% wood, 30% gold, 5% stone
+    assert_eq!(
+        advisor("standard").advise(&workers(7, 6, 6, 1), "phase.unknown"),
+        None
+    );
+    let advice = advisor("standard")
+        .advise(&workers(7, 6, 2, 5), "phase.unknown")
+        .unwrap();
+    assert_eq!(advice.surplus, imbalance(Resource::Stone, 4));
+    assert_eq!(advice.deficit, imbalance(Resource::Gold, -4));
+
+    assert_eq!(
+        advisor("unknown").advise(&workers(15, 3, 2, 0), "phase.early"),
+        None
+    );
+    let disabled = WorkerAdvisor::new(&WorkerConfig {
+        enabled: false,
+        ..Default::default()
+    });
+    assert_eq!(disabled.advise(&workers(15, 3, 2, 0), "phase.early"), None);
+}
+
+#[test]
+fn test_display() {
+    i18n::init(Some("en"));
+    let advice = advisor("standard")
+        .advise(&workers(15, 3, 2, 0), "phase.early")
+        .unwrap();
+    assert_eq!(advice.to_string(), "+5 food, -3 wood");
+}